@@ -0,0 +1,151 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::{Parser, Subcommand};
+use deconz::{DeconzClient, Light, LightClient, LightId};
+
+/// Controls the lights of a deconz gateway from the command line
+#[derive(Parser)]
+struct Args {
+    /// Url of the gateway, e.g. http://192.168.0.2
+    #[arg(long, env = "DECONZ_URL")]
+    gateway: String,
+    /// The api token (username) for the gateway
+    #[arg(long, env = "DECONZ_TOKEN")]
+    token: String,
+    /// Print machine readable json instead of text
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Lists all lights
+    List,
+    /// Turns a light on
+    On { id: String },
+    /// Turns a light off
+    Off { id: String },
+    /// Sets the color of a light, values which are left out stay unchanged
+    Color {
+        id: String,
+        /// Hue from 0 to 65535
+        #[arg(long)]
+        hue: Option<u16>,
+        /// Saturation from 0 to 254
+        #[arg(long)]
+        sat: Option<u8>,
+        /// Brightness from 0 to 254
+        #[arg(long)]
+        bri: Option<u8>,
+    },
+    /// Prints the current state of a light
+    State { id: String },
+    /// Sends a json body to the state of a light and prints the answer (advanced)
+    ///
+    /// For state fields the other commands don't cover, e.g. '{"speed": 3}'.
+    RawState { id: String, body: String },
+    /// Saves everything the gateway knows (lights, groups, scenes, rules, config) to a json file
+    ///
+    /// The backup contains the tokens of all clients, keep it private.
+    Backup { path: PathBuf },
+}
+
+async fn find_light(client: &DeconzClient, id: &str) -> Result<Light, String> {
+    client
+        .get_light(&LightId::from(id))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    let client =
+        DeconzClient::login_with_token(&args.gateway, args.token).map_err(|e| e.to_string())?;
+
+    match args.command {
+        Command::List => {
+            let lights = client.get_light_list().await.map_err(|e| e.to_string())?;
+            if args.json {
+                println!("{}", serde_json::to_string(&lights).unwrap());
+            } else {
+                for light in lights {
+                    println!("{:>4}  {}", light.id, light.name);
+                }
+            }
+        }
+        Command::On { ref id } | Command::Off { ref id } => {
+            let on = matches!(args.command, Command::On { .. });
+            let light = find_light(&client, id).await?;
+            client
+                .set_on_state(&light, on)
+                .await
+                .map_err(|e| e.to_string())?;
+            if !args.json {
+                println!("Turned {} {}", light.name, if on { "on" } else { "off" });
+            }
+        }
+        Command::Color { id, hue, sat, bri } => {
+            let light = find_light(&client, &id).await?;
+            client
+                .set_light_color(&light, hue, bri, sat)
+                .await
+                .map_err(|e| e.to_string())?;
+            if !args.json {
+                println!("Changed the color of {}", light.name);
+            }
+        }
+        Command::State { id } => {
+            let light = find_light(&client, &id).await?;
+            let state = client
+                .get_light_state(&light)
+                .await
+                .map_err(|e| e.to_string())?;
+            if args.json {
+                println!("{}", serde_json::to_string(&state).unwrap());
+            } else {
+                println!("{}", light.name);
+                println!("  on:        {}", state.on());
+                println!("  reachable: {}", state.reachable());
+                if let Some(bri) = state.bri() {
+                    println!("  bri:       {}", bri);
+                }
+                if let (Some(hue), Some(sat)) = (state.hue(), state.sat()) {
+                    println!("  hue:       {}", hue);
+                    println!("  sat:       {}", sat);
+                }
+                if let Some(ct) = state.ct() {
+                    println!("  ct:        {}", ct);
+                }
+            }
+        }
+        Command::RawState { id, body } => {
+            let body = serde_json::from_str(&body).map_err(|e| format!("Invalid json: {}", e))?;
+            let light = find_light(&client, &id).await?;
+            let resp = client
+                .set_raw_state(&light, body)
+                .await
+                .map_err(|e| e.to_string())?;
+            println!("{}", resp);
+        }
+        Command::Backup { path } => {
+            client.save_backup(&path).await.map_err(|e| e.to_string())?;
+            if !args.json {
+                println!("Saved the gateway config to {}", path.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    match run(Args::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
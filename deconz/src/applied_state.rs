@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{infer_color_mode, Error, LightState, LightStateChange};
+
+/// The values the gateway confirmed when changing the state of a light
+///
+/// The gateway answers a change with the values it applied, e.g.
+/// `[{"success":{"/lights/1/state/bri":128}}]`, which are enough to update a known state
+/// without loading it again. Values which weren't part of the change are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[non_exhaustive]
+pub struct AppliedState {
+    pub on: Option<bool>,
+    pub hue: Option<u16>,
+    pub bri: Option<u8>,
+    pub sat: Option<u8>,
+    /// Color temperature in mireds
+    pub ct: Option<u16>,
+    pub xy: Option<[f32; 2]>,
+}
+
+impl AppliedState {
+    /// Reads the values from the answer to a PUT, fields it doesn't know (like
+    /// `transitiontime`) are skipped
+    pub(crate) fn from_response(body: &str) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        struct ApiResult {
+            success: Option<HashMap<String, serde_json::Value>>,
+        }
+
+        fn value<T: DeserializeOwned>(address: &str, value: serde_json::Value) -> Result<T, Error> {
+            serde_json::from_value(value)
+                .map_err(|e| Error::ResponseParseError(format!("{}: {}", address, e)))
+        }
+
+        let results = serde_json::from_str::<Vec<ApiResult>>(body)
+            .map_err(|e| Error::ResponseParseError(e.to_string()))?;
+
+        let mut applied = AppliedState::default();
+        for (address, v) in results.into_iter().filter_map(|r| r.success).flatten() {
+            // The address is the path of the field, e.g. `/lights/1/state/bri`
+            match address.rsplit('/').next().unwrap_or_default() {
+                "on" => applied.on = Some(value(&address, v)?),
+                "hue" => applied.hue = Some(value(&address, v)?),
+                "bri" => applied.bri = Some(value(&address, v)?),
+                "sat" => applied.sat = Some(value(&address, v)?),
+                "ct" => applied.ct = Some(value(&address, v)?),
+                "xy" => applied.xy = Some(value(&address, v)?),
+                _ => {}
+            }
+        }
+        Ok(applied)
+    }
+
+    /// The change to a known light state, including the color mode the applied values imply
+    pub fn as_state_change(&self) -> LightStateChange {
+        let color_mode = infer_color_mode(self.ct, self.xy, self.hue, self.sat);
+
+        LightStateChange {
+            on: self.on,
+            hue: self.hue,
+            bri: self.bri,
+            sat: self.sat,
+            ct: self.ct,
+            xy: self.xy,
+            color_mode,
+            ..Default::default()
+        }
+    }
+
+    /// Updates a known light state with the applied values
+    pub fn apply_to(&self, state: &mut LightState) {
+        self.as_state_change().apply_to(state);
+    }
+}
@@ -0,0 +1,71 @@
+use tokio::runtime::Runtime;
+
+use crate::{AppliedState, DeconzClient, Error, Light, LightClient, LightState};
+
+/// A `DeconzClient` for synchronous code, which blocks until the gateway answered
+///
+/// Like `reqwest::blocking`, it drives the async client on a private tokio runtime. The common
+/// calls are wrapped, `block_on` runs anything else of the async client.
+///
+/// <div class="warning">
+///
+/// It must not be used (or dropped) from within an async runtime, e.g. inside a
+/// `#[tokio::main]` function, which panics. Use the async client there, or move the blocking
+/// calls to a thread of their own with `tokio::task::spawn_blocking`.
+///
+/// </div>
+pub struct BlockingDeconzClient {
+    client: DeconzClient,
+    runtime: Runtime,
+}
+
+impl BlockingDeconzClient {
+    /// Wraps an async client, e.g. one configured by `DeconzClientBuilder`
+    ///
+    /// Fails with `Error::IoError` if the runtime can't be started.
+    pub fn new(client: DeconzClient) -> Result<Self, Error> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(Error::IoError)?;
+        Ok(BlockingDeconzClient { client, runtime })
+    }
+
+    /// Creates a client from an existing token aka. username, see `DeconzClient::login_with_token`
+    pub fn login_with_token<U: AsRef<str>>(url: U, token: String) -> Result<Self, Error> {
+        Self::new(DeconzClient::login_with_token(url, token)?)
+    }
+
+    /// The wrapped async client
+    pub fn client(&self) -> &DeconzClient {
+        &self.client
+    }
+
+    /// Runs a future of the async client to completion
+    pub fn block_on<F: std::future::Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    pub fn get_light_list(&self) -> Result<Vec<Light>, Error> {
+        self.block_on(self.client.get_light_list())
+    }
+
+    pub fn get_light_state(&self, light: &Light) -> Result<LightState, Error> {
+        self.block_on(self.client.get_light_state(light))
+    }
+
+    pub fn set_on_state(&self, light: &Light, state: bool) -> Result<AppliedState, Error> {
+        self.block_on(self.client.set_on_state(light, state))
+    }
+
+    /// Sets the color of a light, values which are `None` stay unchanged
+    pub fn set_light_color(
+        &self,
+        light: &Light,
+        hue: Option<u16>,
+        bri: Option<u8>,
+        sat: Option<u8>,
+    ) -> Result<AppliedState, Error> {
+        self.block_on(self.client.set_light_color(light, hue, bri, sat))
+    }
+}
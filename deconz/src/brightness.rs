@@ -0,0 +1,40 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{AppliedState, LightId, MAX_BRIGHTNESS};
+
+/// The last brightness of each light which wasn't 0, see `DeconzClientBuilder::restore_brightness`
+#[derive(Debug, Default)]
+pub(crate) struct BrightnessMemory {
+    lights: Mutex<HashMap<LightId, u8>>,
+}
+
+impl BrightnessMemory {
+    /// Remembers the brightness of a light, a brightness of 0 is ignored
+    ///
+    /// Some lights report 255, which is capped at `MAX_BRIGHTNESS` so it can be sent again.
+    pub(crate) fn record(&self, id: &LightId, bri: Option<u8>) {
+        if let Some(bri) = bri.filter(|&bri| bri > 0) {
+            self.lights
+                .lock()
+                .unwrap()
+                .insert(id.clone(), bri.min(MAX_BRIGHTNESS));
+        }
+    }
+
+    /// Remembers the brightness the gateway confirmed in its answer to a change of a light's state
+    pub(crate) fn record_response(&self, path: &str, response: &str) {
+        let Some(id) = path
+            .strip_prefix("lights/")
+            .and_then(|path| path.strip_suffix("/state"))
+        else {
+            return;
+        };
+        if let Ok(applied) = AppliedState::from_response(response) {
+            self.record(&LightId::from(id), applied.bri);
+        }
+    }
+
+    pub(crate) fn get(&self, id: &LightId) -> Option<u8> {
+        self.lights.lock().unwrap().get(id).copied()
+    }
+}
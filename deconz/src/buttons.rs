@@ -0,0 +1,77 @@
+use crate::SensorReading;
+
+/// A press of a button of a switch, decoded from the `buttonevent` of a `ZHASwitch` sensor
+///
+/// deconz encodes the button in the thousands and the action in the rest, e.g. 1002 is a short
+/// press of button 1 and 4003 a long press of button 4 released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonEvent {
+    /// The button, starting at 1, or 0 if the event couldn't be decoded
+    pub button: u8,
+    pub action: ButtonAction,
+}
+
+/// What happened to a button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonAction {
+    /// The button went down, followed by a release or `Hold`
+    InitialPress,
+    /// The button is held down, repeated while it is
+    Hold,
+    /// The button was released after a short press
+    ShortRelease,
+    /// The button was released after being held
+    LongRelease,
+    DoublePress,
+    TriplePress,
+    QuadruplePress,
+    /// The switch (e.g. a cube) was shaken
+    Shake,
+    /// The switch was dropped
+    Drop,
+    /// The switch was tilted
+    Tilt,
+    /// The button was pressed more than four times in a row
+    ManyPress,
+    /// An event this crate doesn't know, as the raw `buttonevent`
+    Other(u32),
+}
+
+impl ButtonEvent {
+    /// Decodes a `buttonevent` like 1002
+    pub fn from_code(code: u32) -> Self {
+        let action = match code % 1000 {
+            0 => ButtonAction::InitialPress,
+            1 => ButtonAction::Hold,
+            2 => ButtonAction::ShortRelease,
+            3 => ButtonAction::LongRelease,
+            4 => ButtonAction::DoublePress,
+            5 => ButtonAction::TriplePress,
+            6 => ButtonAction::QuadruplePress,
+            7 => ButtonAction::Shake,
+            8 => ButtonAction::Drop,
+            9 => ButtonAction::Tilt,
+            10 => ButtonAction::ManyPress,
+            _ => ButtonAction::Other(code),
+        };
+
+        match u8::try_from(code / 1000) {
+            Ok(button) if button > 0 => ButtonEvent { button, action },
+            _ => ButtonEvent {
+                button: 0,
+                action: ButtonAction::Other(code),
+            },
+        }
+    }
+}
+
+impl SensorReading {
+    /// The decoded button event of a switch, `None` for other sensors or switches which
+    /// weren't pressed yet
+    pub fn button_event(&self) -> Option<ButtonEvent> {
+        match self {
+            SensorReading::Switch(Some(code)) => Some(ButtonEvent::from_code(*code)),
+            _ => None,
+        }
+    }
+}
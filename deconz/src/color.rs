@@ -0,0 +1,225 @@
+//! Conversions between RGB colors and the color values deconz uses
+//!
+//! deconz scales the hue to 0-65535 and saturation and brightness to 0-255, while palette
+//! uses degrees (0-360) and fractions (0-1). Converting here keeps the scales in one place.
+
+use palette::{
+    color_difference::Ciede2000, convert::FromColorUnclamped, FromColor, Hsv, Lab, LinSrgb, RgbHue,
+    Srgb, Yxy,
+};
+use serde::{Deserialize, Serialize};
+
+/// Converts an RGB color to deconz' hue (0-65535), saturation (0-`MAX_SATURATION`) and
+/// brightness (0-`MAX_BRIGHTNESS`), which the gateway accepts as they are
+pub fn rgb_to_deconz_hsv(rgb: Srgb) -> (u16, u8, u8) {
+    let hsv = Hsv::from_color(rgb);
+    let hue = hsv.hue.into_positive_degrees() / 360.0 * u16::MAX as f32;
+
+    (
+        hue.round() as u16,
+        to_scale(hsv.saturation, MAX_SATURATION),
+        to_scale(hsv.value, MAX_BRIGHTNESS),
+    )
+}
+
+/// Converts deconz' hue (0-65535), saturation (0-`MAX_SATURATION`) and brightness
+/// (0-`MAX_BRIGHTNESS`) to an RGB color
+///
+/// Both ends of the hue scale are red. Values above the maximum, which some lights report, are
+/// read as the maximum.
+pub fn deconz_hsv_to_rgb(hue: u16, sat: u8, bri: u8) -> Srgb {
+    let hsv = Hsv::new(
+        RgbHue::from_degrees(hue as f32 / u16::MAX as f32 * 360.0),
+        sat.min(MAX_SATURATION) as f32 / MAX_SATURATION as f32,
+        bri.min(MAX_BRIGHTNESS) as f32 / MAX_BRIGHTNESS as f32,
+    );
+    Srgb::from_color(hsv)
+}
+
+/// How much a color drifts when it is sent to a light as hue, saturation and brightness and
+/// read back, as CIEDE2000 color difference
+///
+/// The color goes through the gateway's scales like the app sends it. Differences below about 1
+/// can't be seen.
+pub fn color_roundtrip_error(rgb: Srgb) -> f32 {
+    let (hue, sat, bri) = rgb_to_deconz_hsv(rgb);
+    let back = deconz_hsv_to_rgb(hue, sat, bri);
+    Lab::from_color(rgb).difference(Lab::from_color(back))
+}
+
+/// Black or white, whichever is easier to read on the background, e.g. for the name of a light
+/// on a swatch of its color
+///
+/// Picks the one with the higher contrast ratio as defined by WCAG 2, which is black from a
+/// relative luminance of about 0.18 on.
+pub fn contrasting_text_color(bg: Srgb) -> Srgb {
+    let linear = bg.into_linear();
+    let luminance = 0.2126 * linear.red + 0.7152 * linear.green + 0.0722 * linear.blue;
+    let against_black = (luminance + 0.05) / 0.05;
+    let against_white = 1.05 / (luminance + 0.05);
+    if against_black >= against_white {
+        Srgb::new(0.0, 0.0, 0.0)
+    } else {
+        Srgb::new(1.0, 1.0, 1.0)
+    }
+}
+
+/// Converts an RGB color to CIE xy coordinates, the brightness is lost
+pub fn rgb_to_xy(rgb: Srgb) -> [f32; 2] {
+    let yxy = Yxy::from_color(rgb.into_linear());
+    [yxy.x, yxy.y]
+}
+
+/// Converts CIE xy coordinates and a brightness (0-255) to an RGB color
+///
+/// The color is scaled so its brightest channel matches `bri`, as lights do. Colors outside
+/// of the sRGB gamut are clipped.
+pub fn xy_to_rgb(xy: [f32; 2], bri: u8) -> Srgb {
+    // Clamping before normalizing would distort the color, so convert unclamped
+    let linear = LinSrgb::from_color_unclamped(Yxy::new(xy[0], xy[1], 1.0));
+    let linear = LinSrgb::new(
+        linear.red.max(0.0),
+        linear.green.max(0.0),
+        linear.blue.max(0.0),
+    );
+    let max = linear.red.max(linear.green).max(linear.blue);
+    if max <= 0.0 {
+        return Srgb::new(0.0, 0.0, 0.0);
+    }
+
+    let rgb = Srgb::from_linear(linear / max);
+    rgb * (bri as f32 / 255.0)
+}
+
+/// The colors a light can show, a triangle of its primaries in CIE xy coordinates
+///
+/// Newer gateways report it in `capabilities.color.xy` of a light, see
+/// `LightCapabilities::gamut`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorGamut {
+    pub red: [f32; 2],
+    pub green: [f32; 2],
+    pub blue: [f32; 2],
+}
+
+impl ColorGamut {
+    /// Whether the light can show the color, including the edges of the triangle
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        let p = [x, y];
+        let d1 = cross(self.red, self.green, p);
+        let d2 = cross(self.green, self.blue, p);
+        let d3 = cross(self.blue, self.red, p);
+        // A little tolerance so points clamped onto an edge count as inside despite rounding
+        let negative = d1 < -1e-6 || d2 < -1e-6 || d3 < -1e-6;
+        let positive = d1 > 1e-6 || d2 > 1e-6 || d3 > 1e-6;
+        !(negative && positive)
+    }
+
+    /// Moves a color the light can't show to the closest one it can, others stay as they are
+    ///
+    /// Bulbs map such colors on their own, often to a washed-out one, while the closest point
+    /// on the edge of the triangle keeps the hue and is as saturated as the light gets.
+    pub fn clamp_to_gamut(&self, x: f32, y: f32) -> (f32, f32) {
+        if self.contains(x, y) {
+            return (x, y);
+        }
+
+        let p = [x, y];
+        let [x, y] = [
+            closest_on_segment(self.red, self.green, p),
+            closest_on_segment(self.green, self.blue, p),
+            closest_on_segment(self.blue, self.red, p),
+        ]
+        .into_iter()
+        .min_by(|a, b| distance_squared(*a, p).total_cmp(&distance_squared(*b, p)))
+        .unwrap();
+        (x, y)
+    }
+}
+
+/// On which side of the line from `a` to `b` the point `p` is, 0 if it is on the line
+fn cross(a: [f32; 2], b: [f32; 2], p: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+/// The point between `a` and `b` which is closest to `p`
+fn closest_on_segment(a: [f32; 2], b: [f32; 2], p: [f32; 2]) -> [f32; 2] {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let length_squared = ab[0] * ab[0] + ab[1] * ab[1];
+    if length_squared == 0.0 {
+        return a;
+    }
+    let t = ((p[0] - a[0]) * ab[0] + (p[1] - a[1]) * ab[1]) / length_squared;
+    // Beyond the ends the corners themselves are closest, returned as they are to avoid rounding
+    if t <= 0.0 {
+        a
+    } else if t >= 1.0 {
+        b
+    } else {
+        [a[0] + ab[0] * t, a[1] + ab[1] * t]
+    }
+}
+
+fn distance_squared(a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)
+}
+
+/// The highest brightness the gateway accepts, `LightClient::set_light_color` rejects higher ones
+pub const MAX_BRIGHTNESS: u8 = 254;
+
+/// The highest saturation the gateway accepts, `LightClient::set_light_color` rejects higher ones
+pub const MAX_SATURATION: u8 = 254;
+
+/// Converts a brightness in percent (0-100) to the gateway's brightness (0-254)
+///
+/// 100% is `MAX_BRIGHTNESS` and only 0% maps to 0, percentages just above it map to 1.
+pub fn percent_to_bri(percent: f32) -> u8 {
+    let percent = percent.clamp(0.0, 100.0);
+    if percent == 0.0 {
+        return 0;
+    }
+    ((percent / 100.0 * MAX_BRIGHTNESS as f32).round() as u8).max(1)
+}
+
+/// Converts the gateway's brightness (0-254) to percent (0-100)
+pub fn bri_to_percent(bri: u8) -> f32 {
+    bri.min(MAX_BRIGHTNESS) as f32 / MAX_BRIGHTNESS as f32 * 100.0
+}
+
+/// Converts a perceived brightness (0.0-1.0) to the gateway's brightness (0-254)
+///
+/// The eye notices changes of dim light much more than of bright light, so equal steps of
+/// `bri` don't look equal. This follows the CIE 1976 lightness curve instead, half the perceived
+/// brightness is about a fifth of `MAX_BRIGHTNESS`. Like with `percent_to_bri`, only 0.0 maps to 0.
+pub fn perceptual_to_bri(f: f32) -> u8 {
+    let lightness = f.clamp(0.0, 1.0) * 100.0;
+    if lightness == 0.0 {
+        return 0;
+    }
+    let luminance = if lightness > 8.0 {
+        ((lightness + 16.0) / 116.0).powi(3)
+    } else {
+        lightness / CIE_KAPPA
+    };
+    ((luminance * MAX_BRIGHTNESS as f32).round() as u8).max(1)
+}
+
+/// Converts the gateway's brightness (0-254) to the perceived brightness (0.0-1.0),
+/// the inverse of `perceptual_to_bri`
+pub fn bri_to_perceptual(bri: u8) -> f32 {
+    let luminance = bri.min(MAX_BRIGHTNESS) as f32 / MAX_BRIGHTNESS as f32;
+    let lightness = if luminance > CIE_EPSILON {
+        116.0 * luminance.cbrt() - 16.0
+    } else {
+        luminance * CIE_KAPPA
+    };
+    (lightness / 100.0).clamp(0.0, 1.0)
+}
+
+/// Constants of the CIE lightness curve, below `CIE_EPSILON` it is linear
+const CIE_EPSILON: f32 = 216.0 / 24389.0;
+const CIE_KAPPA: f32 = 24389.0 / 27.0;
+
+fn to_scale(fraction: f32, max: u8) -> u8 {
+    (fraction.clamp(0.0, 1.0) * max as f32).round() as u8
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use semver::Version;
+use serde::{Deserialize, Deserializer};
+
+use crate::{gateway_url, read_body, DeconzClient, DeconzClientBuilder, Error};
+
+/// The configuration of a gateway as returned by `GET api/<user>/config`
+#[derive(Debug, Clone, Deserialize)]
+pub struct GatewayConfig {
+    pub name: String,
+    /// The version of the deCONZ software
+    #[serde(rename = "swversion")]
+    pub sw_version: String,
+    /// Missing on some very old firmware, see `DeconzClient::api_version`
+    #[serde(rename = "apiversion")]
+    pub api_version: Option<String>,
+    /// The port of the websocket which pushes events
+    #[serde(rename = "websocketport")]
+    pub websocket_port: u16,
+    #[serde(rename = "ipaddress")]
+    pub ip_address: String,
+    /// The unique id of the gateway, e.g. `00212EFFFF012345`, empty if the gateway doesn't tell
+    #[serde(rename = "bridgeid", default)]
+    pub bridge_id: String,
+    /// The apps allowed to use the api, keyed by their username
+    #[serde(default, deserialize_with = "deserialize_whitelist")]
+    pub whitelist: HashMap<String, WhitelistEntry>,
+}
+
+/// Reads the whitelist and copies the key of each entry to its `token`
+fn deserialize_whitelist<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, WhitelistEntry>, D::Error> {
+    let mut whitelist = HashMap::<String, WhitelistEntry>::deserialize(deserializer)?;
+    for (token, entry) in &mut whitelist {
+        entry.token.clone_from(token);
+    }
+    Ok(whitelist)
+}
+
+/// An app which was granted access to the gateway, see `DeconzClient::get_whitelist`
+#[derive(Debug, Clone, Deserialize)]
+pub struct WhitelistEntry {
+    /// The token (username) of the app, which `DeconzClient::delete_token` revokes
+    ///
+    /// The gateway sends it as the key of the entry in `GatewayConfig::whitelist`.
+    #[serde(skip)]
+    pub token: String,
+    /// The device type the app registered with
+    pub name: String,
+    /// When the app was paired, in UTC without a time zone, e.g. `2020-11-22T10:59:19`
+    #[serde(rename = "create date", default)]
+    pub create_date: String,
+    /// When the app last used the api, in UTC without a time zone, empty if the gateway doesn't
+    /// tell
+    #[serde(rename = "last use date", default)]
+    pub last_use_date: String,
+}
+
+#[cfg(feature = "chrono")]
+impl WhitelistEntry {
+    /// Parses `create_date`, `None` if it is missing or has an unknown format
+    pub fn created(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        whitelist_date(&self.create_date)
+    }
+
+    /// Parses `last_use_date`, `None` if it is missing or has an unknown format
+    pub fn last_used(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        whitelist_date(&self.last_use_date)
+    }
+
+    /// How long ago the app last used the api, e.g. to point out tokens nobody uses anymore
+    ///
+    /// `None` if `last_used` is, or if the date is ahead of the clock of this computer.
+    pub fn unused_for(&self) -> Option<Duration> {
+        (chrono::Utc::now() - self.last_used()?).to_std().ok()
+    }
+}
+
+/// Unlike other timestamps without a time zone, those of the whitelist are in UTC
+#[cfg(feature = "chrono")]
+fn whitelist_date(date: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f")
+        .map(|date| date.and_utc())
+        .ok()
+        .or_else(|| crate::Timestamp::from(String::from(date)).to_utc())
+}
+
+/// A client which was just paired with a gateway and which gateway that is, see
+/// `DeconzClientBuilder::pair_with_link_button`
+#[derive(Debug, Clone)]
+pub struct LoginResult {
+    pub client: DeconzClient,
+    /// The name the gateway was given, e.g. `Phoscon-GW`
+    pub gateway_name: String,
+    /// See `GatewayConfig::bridge_id`
+    pub bridge_id: String,
+}
+
+/// The public part of the config which a gateway returns without a token, see `DeconzClient::probe`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProbeResult {
+    pub name: String,
+    #[serde(rename = "bridgeid", default)]
+    pub bridge_id: String,
+    /// `deCONZ` for every deCONZ gateway
+    #[serde(rename = "modelid", default)]
+    pub model_id: String,
+    #[serde(rename = "swversion", default)]
+    pub sw_version: String,
+    #[serde(rename = "apiversion")]
+    pub api_version: Option<String>,
+    /// Whether the gateway hasn't been set up yet
+    #[serde(rename = "factorynew", default)]
+    pub factory_new: bool,
+}
+
+impl DeconzClient {
+    /// Checks that a deCONZ gateway is reachable at `url`, without logging in
+    ///
+    /// Fails with `Error::NotADeconzGateway` if the server answers but isn't a deCONZ gateway.
+    pub async fn probe<U: AsRef<str>>(url: U) -> Result<ProbeResult, Error> {
+        DeconzClientBuilder::new().probe(url).await
+    }
+
+    /// Returns the config of the gateway, which is only fetched once and then cached
+    pub async fn get_config(&self) -> Result<GatewayConfig, Error> {
+        if let Some(config) = self.config.read().unwrap().as_ref() {
+            return Ok(config.clone());
+        }
+        self.refresh_config().await
+    }
+
+    /// The name the gateway was given in the Phoscon app, read from the cached config
+    pub async fn gateway_name(&self) -> Result<String, Error> {
+        Ok(self.get_config().await?.name)
+    }
+
+    /// The version of the api the gateway implements, parsed from the cached config
+    pub async fn api_version(&self) -> Result<Version, Error> {
+        let config = self.get_config().await?;
+        let version = config.api_version.ok_or_else(|| {
+            Error::ResponseParseError(String::from("The gateway config has no apiversion"))
+        })?;
+
+        Version::parse(&version).map_err(|e| {
+            Error::ResponseParseError(format!("Invalid apiversion {:?}: {}", version, e))
+        })
+    }
+
+    /// Fails with `Error::Unsupported` if the gateway's api is older than `min_version`
+    pub(crate) async fn require_api_version(
+        &self,
+        min_version: Version,
+        feature: &str,
+    ) -> Result<(), Error> {
+        let version = self.api_version().await?;
+        if version < min_version {
+            return Err(Error::Unsupported(format!(
+                "{} needs api version {} but the gateway has {}",
+                feature, min_version, version
+            )));
+        }
+        Ok(())
+    }
+
+    /// Measures the round trip of a request to the gateway, e.g. for a connection indicator
+    ///
+    /// Sends `GET api/<user>/config` once without retries, so a failure shows right away. If it
+    /// succeeds, the changes kept by the offline queue (see `DeconzClientBuilder::offline_queue`)
+    /// are sent afterwards.
+    pub async fn ping(&self) -> Result<Duration, Error> {
+        let request = self
+            .http
+            .get(self.api_url("config"))
+            .build()
+            .map_err(Error::HttpError)?;
+
+        let start = Instant::now();
+        let result = self.send_once(request).await;
+        self.track_connection(result)?;
+        let latency = start.elapsed();
+
+        self.replay_offline_queue().await;
+        Ok(latency)
+    }
+
+    /// Fetches everything the gateway knows in one request: lights, groups, scenes, schedules,
+    /// rules, sensors and the config, e.g. for a backup before a firmware upgrade
+    ///
+    /// The answer is kept as raw json so nothing the crate doesn't model gets lost. It includes
+    /// the whitelist with the tokens of all clients, so keep it private.
+    pub async fn export_full_config(&self) -> Result<serde_json::Value, Error> {
+        let url = self
+            .url
+            .join(&format!("api/{}", self.username))
+            .map_err(|e| Error::InvalidUrl(e.to_string()))?;
+        let body = self.send(self.http.get(url)).await?;
+        serde_json::from_str(&body).map_err(|e| Error::ResponseParseError(e.to_string()))
+    }
+
+    /// Writes the result of `export_full_config` as pretty-printed json to a file
+    pub async fn save_backup<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let config = self.export_full_config().await?;
+        let json = serde_json::to_string_pretty(&config).map_err(|e| Error::IoError(e.into()))?;
+        tokio::fs::write(path, json).await.map_err(Error::IoError)
+    }
+
+    /// Lists the apps which have a token for the gateway, the most recently used first
+    ///
+    /// The config is loaded again, as the cached one has outdated last use dates. Tokens of apps
+    /// which aren't used anymore can be revoked with `delete_token`.
+    pub async fn get_whitelist(&self) -> Result<Vec<WhitelistEntry>, Error> {
+        let config = self.refresh_config().await?;
+        let mut entries: Vec<WhitelistEntry> = config.whitelist.into_values().collect();
+        // The dates share one format, so they sort as text, missing ones last
+        entries.sort_by(|a, b| b.last_use_date.cmp(&a.last_use_date));
+        Ok(entries)
+    }
+
+    /// Fetches the config of the gateway even if it is cached, e.g. after it was changed
+    pub async fn refresh_config(&self) -> Result<GatewayConfig, Error> {
+        let config = self.get_json::<GatewayConfig>("config").await?;
+        *self.config.write().unwrap() = Some(config.clone());
+        Ok(config)
+    }
+}
+
+impl DeconzClientBuilder {
+    /// Like `DeconzClient::probe`, but with the http settings of the builder, e.g. for an https
+    /// gateway with a self-signed certificate
+    pub async fn probe<U: AsRef<str>>(self, url: U) -> Result<ProbeResult, Error> {
+        let url = gateway_url(url.as_ref())?;
+        let http = self.build_http(&self.read_root_certificates()?)?;
+
+        let resp = http
+            .get(url.join("api/config").unwrap())
+            .send()
+            .await
+            .map_err(Error::HttpError)?;
+        let body = read_body(resp).await?;
+
+        let probe = serde_json::from_str::<ProbeResult>(&body)
+            .map_err(|e| Error::ResponseParseError(e.to_string()))?;
+        if probe.model_id != "deCONZ" {
+            return Err(Error::NotADeconzGateway);
+        }
+
+        Ok(probe)
+    }
+}
@@ -0,0 +1,68 @@
+use std::fmt;
+
+use crate::{DeconzClient, Error};
+
+/// Whether the gateway can currently be reached, see `LightClient::watch_connection_state`
+///
+/// Requests which the gateway answers, even with an error, count as connected. Only failures to
+/// reach it at all (refused connections, timeouts, a dropped websocket) change the state.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ConnectionState {
+    #[default]
+    Connected,
+    /// A request or the event socket failed and is being retried
+    Reconnecting,
+    /// The gateway couldn't be reached, with the message of the last error
+    ///
+    /// `Error` can't be cloned, so only its message is kept.
+    Disconnected(String),
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Connected => f.write_str("Connected"),
+            ConnectionState::Reconnecting => f.write_str("Reconnecting"),
+            ConnectionState::Disconnected(error) => write!(f, "Disconnected: {}", error),
+        }
+    }
+}
+
+/// Whether an error means the gateway didn't answer at all
+pub(crate) fn is_connection_error(error: &Error) -> bool {
+    match error {
+        Error::HttpError(e) => e.status().is_none() || crate::is_transient(error),
+        Error::WebsocketError(_) => true,
+        _ => false,
+    }
+}
+
+impl DeconzClient {
+    /// Updates the connection state, subscribers are only woken if it changed
+    pub(crate) fn set_connection_state(&self, state: ConnectionState) {
+        self.connection.send_if_modified(|current| {
+            if *current == state {
+                return false;
+            }
+            *current = state;
+            true
+        });
+    }
+
+    /// Updates the connection state from the outcome of a request and passes the outcome on
+    pub(crate) fn track_connection<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        match &result {
+            Err(e) if is_connection_error(e) => {
+                self.set_connection_state(ConnectionState::Disconnected(e.to_string()))
+            }
+            _ => self.set_connection_state(ConnectionState::Connected),
+        }
+        result
+    }
+
+    pub(crate) fn subscribe_connection_state(
+        &self,
+    ) -> tokio::sync::watch::Receiver<ConnectionState> {
+        self.connection.subscribe()
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{Error, Group, Light, LightClient, LightState};
+
+/// Something that can be switched and colored like a light: a single `Light` or a whole `Group`
+///
+/// Lets a UI treat rooms and bulbs the same, e.g. in one mixed list with one set of controls.
+/// Each call goes to the right endpoint, `lights/<id>/state` for lights and `groups/<id>/action`
+/// for groups.
+#[allow(async_fn_in_trait)]
+pub trait Controllable {
+    /// The name to show for it
+    fn display_name(&self) -> &str;
+
+    async fn set_on<C: LightClient + ?Sized>(&self, client: &C, on: bool) -> Result<(), Error>;
+
+    /// Sets the color, values which are `None` stay unchanged
+    async fn set_color<C: LightClient + ?Sized>(
+        &self,
+        client: &C,
+        hue: Option<u16>,
+        bri: Option<u8>,
+        sat: Option<u8>,
+    ) -> Result<(), Error>;
+
+    /// The current state, for a group that of its last action and on if any of its lights is on
+    async fn get_state<C: LightClient + ?Sized>(&self, client: &C) -> Result<LightState, Error>;
+}
+
+impl Controllable for Light {
+    fn display_name(&self) -> &str {
+        &self.name
+    }
+
+    async fn set_on<C: LightClient + ?Sized>(&self, client: &C, on: bool) -> Result<(), Error> {
+        client.set_on_state(self, on).await?;
+        Ok(())
+    }
+
+    async fn set_color<C: LightClient + ?Sized>(
+        &self,
+        client: &C,
+        hue: Option<u16>,
+        bri: Option<u8>,
+        sat: Option<u8>,
+    ) -> Result<(), Error> {
+        client.set_light_color(self, hue, bri, sat).await?;
+        Ok(())
+    }
+
+    async fn get_state<C: LightClient + ?Sized>(&self, client: &C) -> Result<LightState, Error> {
+        client.get_light_state(self).await
+    }
+}
+
+impl Controllable for Group {
+    fn display_name(&self) -> &str {
+        &self.name
+    }
+
+    async fn set_on<C: LightClient + ?Sized>(&self, client: &C, on: bool) -> Result<(), Error> {
+        client.set_group_on_state(self, on).await
+    }
+
+    async fn set_color<C: LightClient + ?Sized>(
+        &self,
+        client: &C,
+        hue: Option<u16>,
+        bri: Option<u8>,
+        sat: Option<u8>,
+    ) -> Result<(), Error> {
+        client.set_group_color(self, hue, bri, sat).await
+    }
+
+    async fn get_state<C: LightClient + ?Sized>(&self, client: &C) -> Result<LightState, Error> {
+        let group_state = client.get_group_state(self).await?;
+        let mut state = group_state.action;
+        state.on = group_state.any_on;
+        // Groups have no reachability of their own, the gateway leaves it out of the action
+        state.reachable = true;
+        Ok(state)
+    }
+}
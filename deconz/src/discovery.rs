@@ -0,0 +1,142 @@
+use std::{collections::HashSet, time::Duration};
+
+use futures_util::future::join_all;
+use reqwest::Url;
+use serde::Deserialize;
+use tokio::{net::UdpSocket, time::Instant};
+
+use crate::{read_body, Error, DEFAULT_TIMEOUT};
+
+/// The endpoint of the Phoscon cloud which lists the gateways in the local network of the caller
+const DISCOVERY_URL: &str = "https://phoscon.de/discover";
+
+const SSDP_ADDRESS: &str = "239.255.255.250:1900";
+const SSDP_SEARCH_TARGET: &str = "urn:schemas-upnp-org:device:basic:1";
+/// How long to collect answers to the M-SEARCH request
+const SSDP_WAIT: Duration = Duration::from_secs(3);
+
+/// A gateway found by one of the discovery methods
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DiscoveredGateway {
+    /// The bridge id of the gateway, or its serial number if found by local discovery
+    pub id: String,
+    #[serde(rename = "internalipaddress")]
+    pub internal_ip: String,
+    #[serde(rename = "internalport")]
+    pub internal_port: u16,
+}
+
+impl DiscoveredGateway {
+    /// The url which can be passed to the login methods of `DeconzClient`
+    pub fn url(&self) -> String {
+        if self.internal_ip.parse::<std::net::Ipv6Addr>().is_ok() {
+            format!("http://[{}]:{}", self.internal_ip, self.internal_port)
+        } else {
+            format!("http://{}:{}", self.internal_ip, self.internal_port)
+        }
+    }
+}
+
+/// Asks the Phoscon cloud for the gateways in the local network
+///
+/// This needs internet access. An empty list means no gateway has registered from this network.
+pub async fn discover_gateways() -> Result<Vec<DiscoveredGateway>, Error> {
+    let resp = discovery_http()?
+        .get(DISCOVERY_URL)
+        .send()
+        .await
+        .map_err(Error::HttpError)?;
+    let body = read_body(resp).await?;
+
+    serde_json::from_str(&body).map_err(|e| Error::ResponseParseError(e.to_string()))
+}
+
+/// Searches the local network for gateways via SSDP (UPnP), which also works without internet access
+///
+/// Every device answering the search has its description fetched, only deCONZ gateways are returned.
+pub async fn discover_gateways_local() -> Result<Vec<DiscoveredGateway>, Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await.map_err(Error::IoError)?;
+
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\nST: {}\r\n\r\n",
+        SSDP_ADDRESS,
+        SSDP_WAIT.as_secs(),
+        SSDP_SEARCH_TARGET
+    );
+    socket
+        .send_to(search.as_bytes(), SSDP_ADDRESS)
+        .await
+        .map_err(Error::IoError)?;
+
+    let mut locations = HashSet::new();
+    let deadline = Instant::now() + SSDP_WAIT;
+    let mut buf = [0; 2048];
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, _) = received.map_err(Error::IoError)?;
+        if let Some(location) = location_header(&String::from_utf8_lossy(&buf[..len])) {
+            locations.insert(location);
+        }
+    }
+
+    let http = discovery_http()?;
+    let gateways = join_all(
+        locations
+            .into_iter()
+            .map(|location| describe_gateway(&http, location)),
+    )
+    .await;
+
+    Ok(gateways.into_iter().flatten().collect())
+}
+
+fn discovery_http() -> Result<reqwest::Client, Error> {
+    reqwest::ClientBuilder::new()
+        .timeout(DEFAULT_TIMEOUT)
+        .build()
+        .map_err(Error::HttpError)
+}
+
+/// Finds the `LOCATION` header in an answer to an M-SEARCH request
+fn location_header(response: &str) -> Option<Url> {
+    response.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if !name.trim().eq_ignore_ascii_case("location") {
+            return None;
+        }
+        Url::parse(value.trim()).ok()
+    })
+}
+
+/// Fetches the UPnP description of a device, returns `None` if it isn't a deCONZ gateway
+async fn describe_gateway(http: &reqwest::Client, location: Url) -> Option<DiscoveredGateway> {
+    let description = http
+        .get(location.clone())
+        .send()
+        .await
+        .ok()?
+        .text()
+        .await
+        .ok()?;
+
+    let is_deconz = ["deCONZ", "Phoscon", "dresden elektronik"]
+        .iter()
+        .any(|marker| description.contains(marker));
+    if !is_deconz {
+        return None;
+    }
+
+    Some(DiscoveredGateway {
+        id: xml_element(&description, "serialNumber")
+            .unwrap_or_default()
+            .to_string(),
+        internal_ip: location.host_str()?.to_string(),
+        internal_port: location.port_or_known_default()?,
+    })
+}
+
+/// Returns the text of the first element with the given name. Good enough for the flat description xml.
+fn xml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(xml[start..end].trim())
+}
@@ -0,0 +1,49 @@
+use reqwest::Method;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::DeconzClient;
+
+impl DeconzClient {
+    /// Logs a request which changes something instead of sending it and makes up the answer
+    /// the gateway would give, see `DeconzClientBuilder::dry_run`
+    pub(crate) fn skip_request(&self, request: &reqwest::Request) -> String {
+        let method = request.method();
+        let path = request.url().path().replacen(&self.username, "<token>", 1);
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|body| String::from_utf8_lossy(body).into_owned());
+        info!(%method, %path, body = body.as_deref().unwrap_or_default(), "Dry run, not sending");
+
+        let started = self.request_started();
+        self.request_finished(started, method, &path, None, body.clone());
+
+        // Addresses in answers are relative to `api/<username>`, e.g. `/lights/1/state/on`
+        let address = request
+            .url()
+            .path()
+            .strip_prefix(&format!("/api/{}", self.username))
+            .unwrap_or_default();
+        let body = body.and_then(|body| serde_json::from_str::<Value>(&body).ok());
+        dry_run_answer(method, address, body).to_string()
+    }
+}
+
+/// What the gateway answers to a request which succeeded
+fn dry_run_answer(method: &Method, address: &str, body: Option<Value>) -> Value {
+    match *method {
+        // Every changed field is confirmed with the value it was set to
+        Method::PUT => match body {
+            Some(Value::Object(fields)) => fields
+                .into_iter()
+                .map(|(field, value)| json!({ "success": { format!("{}/{}", address, field): value } }))
+                .collect(),
+            _ => json!([]),
+        },
+        // Nothing was created, so there is no real id
+        Method::POST => json!([{ "success": { "id": "0" } }]),
+        Method::DELETE => json!([{ "success": format!("{} deleted", address) }]),
+        _ => json!([]),
+    }
+}
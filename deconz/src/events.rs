@@ -0,0 +1,435 @@
+use std::{collections::HashMap, io, time::Duration};
+
+use futures_util::{stream, Stream, StreamExt};
+use serde::Deserialize;
+use tokio::{net::TcpStream, sync::broadcast};
+use tokio_tungstenite::{tungstenite::Message, Connector, MaybeTlsStream, WebSocketStream};
+use tracing::{info, warn};
+
+use crate::{
+    ColorMode, ConnectionState, DeconzClient, Error, LightClient, LightId, LightState,
+    SensorReading,
+};
+
+type EventSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// An event pushed by the gateway over its websocket
+#[derive(Debug, Clone)]
+pub enum LightEvent {
+    /// Some fields of a light's state changed
+    StateChanged {
+        id: LightId,
+        change: LightStateChange,
+    },
+    /// A resource was added to the gateway
+    Added { resource: ResourceKind, id: String },
+    /// A resource was removed from the gateway
+    Removed { resource: ResourceKind, id: String },
+    /// The websocket was connected again after it dropped, e.g. because the gateway restarted
+    ///
+    /// Changes in between were missed, so states known from before should be reloaded.
+    Reconnected,
+}
+
+/// A new reading of a sensor pushed by the gateway over its websocket
+#[derive(Debug, Clone)]
+pub struct SensorEvent {
+    /// The id of the sensor, as in `Sensor::id`
+    pub id: u32,
+    pub reading: SensorReading,
+}
+
+/// The kind of resource an event refers to (the `r` field of an event)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceKind {
+    Lights,
+    Groups,
+    Scenes,
+    Sensors,
+    Other(String),
+}
+
+impl From<&str> for ResourceKind {
+    fn from(value: &str) -> Self {
+        match value {
+            "lights" => ResourceKind::Lights,
+            "groups" => ResourceKind::Groups,
+            "scenes" => ResourceKind::Scenes,
+            "sensors" => ResourceKind::Sensors,
+            other => ResourceKind::Other(String::from(other)),
+        }
+    }
+}
+
+/// The fields of a light state that changed. Fields which didn't change are `None`.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct LightStateChange {
+    pub on: Option<bool>,
+    pub reachable: Option<bool>,
+    pub hue: Option<u16>,
+    pub bri: Option<u8>,
+    pub sat: Option<u8>,
+    pub ct: Option<u16>,
+    pub xy: Option<[f32; 2]>,
+    #[serde(rename = "colormode")]
+    pub color_mode: Option<ColorMode>,
+    pub speed: Option<u8>,
+}
+
+impl LightStateChange {
+    /// Collects the fields in which `new` differs from `old`, `None` if nothing changed
+    ///
+    /// Fields which `new` doesn't have any more are not reported.
+    pub fn between(old: &LightState, new: &LightState) -> Option<Self> {
+        fn changed<T: Copy + PartialEq>(old: Option<T>, new: Option<T>) -> Option<T> {
+            new.filter(|_| old != new)
+        }
+
+        let change = LightStateChange {
+            on: changed(Some(old.on), Some(new.on)),
+            reachable: changed(Some(old.reachable), Some(new.reachable)),
+            hue: changed(old.hue, new.hue),
+            bri: changed(old.bri, new.bri),
+            sat: changed(old.sat, new.sat),
+            ct: changed(old.ct, new.ct),
+            xy: changed(old.xy, new.xy),
+            color_mode: changed(old.color_mode, new.color_mode),
+            speed: changed(old.speed, new.speed),
+        };
+        change.has_changes().then_some(change)
+    }
+
+    fn has_changes(&self) -> bool {
+        self.on.is_some()
+            || self.reachable.is_some()
+            || self.hue.is_some()
+            || self.bri.is_some()
+            || self.sat.is_some()
+            || self.ct.is_some()
+            || self.xy.is_some()
+            || self.color_mode.is_some()
+            || self.speed.is_some()
+    }
+
+    /// Updates a known light state with the changed fields
+    pub fn apply_to(&self, state: &mut LightState) {
+        if let Some(on) = self.on {
+            state.on = on;
+        }
+        if let Some(reachable) = self.reachable {
+            state.reachable = reachable;
+        }
+        if self.hue.is_some() {
+            state.hue = self.hue;
+        }
+        if self.bri.is_some() {
+            state.bri = self.bri;
+        }
+        if self.sat.is_some() {
+            state.sat = self.sat;
+        }
+        if self.ct.is_some() {
+            state.ct = self.ct;
+        }
+        if self.xy.is_some() {
+            state.xy = self.xy;
+        }
+        if self.color_mode.is_some() {
+            state.color_mode = self.color_mode;
+        }
+        if self.speed.is_some() {
+            state.speed = self.speed;
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawEvent {
+    e: String,
+    r: String,
+    id: Option<String>,
+    /// The changed fields, light and sensor events have different ones
+    state: Option<serde_json::Value>,
+}
+
+impl RawEvent {
+    /// Converts the raw event into a `LightEvent`, skipping events we don't model
+    fn into_light_event(self) -> Option<LightEvent> {
+        let resource = ResourceKind::from(self.r.as_str());
+        let id = self.id?;
+        match self.e.as_str() {
+            "changed" if resource == ResourceKind::Lights => Some(LightEvent::StateChanged {
+                id: LightId::from(id),
+                change: serde_json::from_value(self.state?).ok()?,
+            }),
+            "added" => Some(LightEvent::Added { resource, id }),
+            "deleted" => Some(LightEvent::Removed { resource, id }),
+            _ => None,
+        }
+    }
+
+    /// Converts the raw event into a `SensorEvent` if a sensor got a new reading
+    ///
+    /// Changes of the config of a sensor (e.g. its battery) have no `state` and are skipped.
+    fn into_sensor_event(self) -> Option<SensorEvent> {
+        if self.e != "changed" || ResourceKind::from(self.r.as_str()) != ResourceKind::Sensors {
+            return None;
+        }
+        Some(SensorEvent {
+            id: self.id?.parse().ok()?,
+            reading: SensorReading::from_event_state(self.state?),
+        })
+    }
+}
+
+enum EventStreamState {
+    Connecting(DeconzClient),
+    Connected(DeconzClient, Box<EventSocket>),
+    /// The connection dropped, `error` tells why unless it was closed cleanly
+    Reconnecting {
+        client: DeconzClient,
+        attempt: u32,
+        error: Option<Error>,
+    },
+    Done,
+}
+
+/// Connects to the websocket of the gateway and streams the light events it pushes
+///
+/// A dropped connection is reconnected as configured with `DeconzClientBuilder::event_reconnects`,
+/// followed by a `LightEvent::Reconnected`. Yields a single error if connecting fails.
+pub(crate) fn subscribe(
+    client: &DeconzClient,
+) -> impl Stream<Item = Result<LightEvent, Error>> + 'static {
+    let brightness = client.brightness.clone();
+    subscribe_with(
+        client,
+        RawEvent::into_light_event,
+        Some(LightEvent::Reconnected),
+    )
+    .inspect(move |event| {
+        // Keeps the brightness `set_on_state` restores up to date
+        if let (Some(memory), Ok(LightEvent::StateChanged { id, change })) = (&brightness, event) {
+            memory.record(id, change.bri);
+        }
+    })
+}
+
+/// Connects to the websocket of the gateway and streams the new readings of sensors
+pub(crate) fn subscribe_sensors(
+    client: &DeconzClient,
+) -> impl Stream<Item = Result<SensorEvent, Error>> + 'static {
+    subscribe_with(client, RawEvent::into_sensor_event, None)
+}
+
+/// Streams the events of the websocket which `convert` turns into a `T`, skipping the others
+///
+/// `reconnected` is emitted after the connection dropped and was established again. Failed
+/// reconnects aren't yielded, only the last error once the client gives up.
+fn subscribe_with<T: Clone + 'static>(
+    client: &DeconzClient,
+    convert: fn(RawEvent) -> Option<T>,
+    reconnected: Option<T>,
+) -> impl Stream<Item = Result<T, Error>> + 'static {
+    stream::unfold(
+        EventStreamState::Connecting(client.clone()),
+        move |mut state| {
+            let reconnected = reconnected.clone();
+            async move {
+                loop {
+                    state = match state {
+                        EventStreamState::Connecting(client) => {
+                            match client.connect_events().await {
+                                Ok(socket) => EventStreamState::Connected(client, Box::new(socket)),
+                                Err(e) => return Some((Err(e), EventStreamState::Done)),
+                            }
+                        }
+                        EventStreamState::Connected(client, mut socket) => {
+                            match socket.next().await {
+                                Some(Ok(Message::Text(text))) => {
+                                    let event = serde_json::from_str::<RawEvent>(&text)
+                                        .ok()
+                                        .and_then(convert);
+                                    let state = EventStreamState::Connected(client, socket);
+                                    match event {
+                                        Some(event) => return Some((Ok(event), state)),
+                                        None => state,
+                                    }
+                                }
+                                Some(Ok(_)) => EventStreamState::Connected(client, socket),
+                                Some(Err(e)) => EventStreamState::Reconnecting {
+                                    client,
+                                    attempt: 0,
+                                    error: Some(Error::WebsocketError(e)),
+                                },
+                                None => EventStreamState::Reconnecting {
+                                    client,
+                                    attempt: 0,
+                                    error: None,
+                                },
+                            }
+                        }
+                        EventStreamState::Reconnecting {
+                            client,
+                            attempt,
+                            error,
+                        } => {
+                            let max = client.retry.event_reconnects;
+                            if max.is_some_and(|max| attempt >= max) {
+                                let message = match &error {
+                                    Some(e) => e.to_string(),
+                                    None => String::from("The event socket was closed"),
+                                };
+                                client.set_connection_state(ConnectionState::Disconnected(message));
+                                return error.map(|e| (Err(e), EventStreamState::Done));
+                            }
+
+                            let delay = crate::backoff(attempt);
+                            warn!(attempt, ?delay, "Event socket dropped, reconnecting");
+                            client.set_connection_state(ConnectionState::Reconnecting);
+                            tokio::time::sleep(delay).await;
+                            match client.connect_events().await {
+                                Ok(socket) => {
+                                    info!("Event socket reconnected");
+                                    client.set_connection_state(ConnectionState::Connected);
+                                    let state =
+                                        EventStreamState::Connected(client, Box::new(socket));
+                                    match reconnected {
+                                        Some(marker) => return Some((Ok(marker), state)),
+                                        None => state,
+                                    }
+                                }
+                                Err(e) => EventStreamState::Reconnecting {
+                                    client,
+                                    attempt: attempt + 1,
+                                    error: Some(e),
+                                },
+                            }
+                        }
+                        EventStreamState::Done => return None,
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Streams the events sent to a broadcast channel
+///
+/// Events missed because the receiver lagged behind are skipped, the stream ends when the
+/// sender is dropped.
+pub(crate) fn receive(
+    receiver: broadcast::Receiver<LightEvent>,
+) -> impl Stream<Item = Result<LightEvent, Error>> + 'static {
+    stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((Ok(event), receiver)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+/// Compares two polls of all light states and describes the difference as events
+fn diff_states(
+    old: &HashMap<LightId, LightState>,
+    new: &HashMap<LightId, LightState>,
+) -> Vec<LightEvent> {
+    let mut events = vec![];
+    for (id, state) in new {
+        match old.get(id) {
+            Some(old_state) => {
+                if let Some(change) = LightStateChange::between(old_state, state) {
+                    events.push(LightEvent::StateChanged {
+                        id: id.clone(),
+                        change,
+                    });
+                }
+            }
+            None => events.push(LightEvent::Added {
+                resource: ResourceKind::Lights,
+                id: id.to_string(),
+            }),
+        }
+    }
+    for id in old.keys().filter(|id| !new.contains_key(id)) {
+        events.push(LightEvent::Removed {
+            resource: ResourceKind::Lights,
+            id: id.to_string(),
+        });
+    }
+    events
+}
+
+/// Polls the states of all lights every `interval` and streams the changes as events
+///
+/// The first poll only records the states. A failed poll yields an error, but polling goes on.
+pub(crate) fn poll<C: LightClient + ?Sized>(
+    client: &C,
+    interval: Duration,
+) -> impl Stream<Item = Result<LightEvent, Error>> + '_ {
+    stream::unfold(
+        (None::<HashMap<LightId, LightState>>, true),
+        move |(known, first)| async move {
+            if !first {
+                tokio::time::sleep(interval).await;
+            }
+
+            let states = match client.get_all_light_states().await {
+                Ok(states) => states,
+                Err(Error::NoLights) => HashMap::new(),
+                Err(e) => return Some((vec![Err(e)], (known, false))),
+            };
+            let events = match &known {
+                Some(known) => diff_states(known, &states).into_iter().map(Ok).collect(),
+                None => vec![],
+            };
+            Some((events, (Some(states), false)))
+        },
+    )
+    .flat_map(stream::iter)
+}
+
+impl DeconzClient {
+    async fn connect_events(&self) -> Result<EventSocket, Error> {
+        let config = self.get_config().await?;
+
+        // A gateway behind an https proxy also serves its events over TLS
+        let (scheme, connector) = match self.url.scheme() {
+            "https" => ("wss", Connector::Rustls(self.websocket_tls.clone())),
+            _ => ("ws", Connector::Plain),
+        };
+        // IPv6 addresses come in brackets
+        let host = self.url.host_str().unwrap_or("localhost");
+        let url = format!("{}://{}:{}", scheme, host, config.websocket_port);
+
+        let (socket, _) = if crate::mdns::is_local_name(host) {
+            // The websocket doesn't use the resolver of the http client, so `.local` names are
+            // resolved here. The request still names the host, e.g. for a reverse proxy.
+            let address = crate::mdns::resolve_host(host)
+                .await
+                .map_err(Error::IoError)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| {
+                    Error::IoError(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("No address found for {}", host),
+                    ))
+                })?;
+            let stream = TcpStream::connect((address, config.websocket_port))
+                .await
+                .map_err(Error::IoError)?;
+            tokio_tungstenite::client_async_tls_with_config(url, stream, None, Some(connector))
+                .await
+        } else {
+            tokio_tungstenite::connect_async_tls_with_config(url, None, false, Some(connector))
+                .await
+        }
+        .map_err(Error::WebsocketError)?;
+
+        Ok(socket)
+    }
+}
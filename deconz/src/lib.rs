@@ -1,13 +1,164 @@
-use std::{collections::HashMap, num::ParseIntError, sync::Mutex};
+use std::{
+    collections::HashMap,
+    fmt,
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
 
-use reqwest::{IntoUrl, Url};
-use serde::{Deserialize, Serialize};
+use futures_util::{future, stream, Stream, StreamExt, TryStreamExt};
+use reqwest::{Method, StatusCode, Url};
+use serde::{
+    de::{DeserializeOwned, IntoDeserializer},
+    Deserialize, Serialize,
+};
+use tracing::{debug, info, warn};
+
+mod applied_state;
+#[cfg(feature = "blocking")]
+mod blocking;
+mod brightness;
+mod buttons;
+mod color;
+mod config;
+mod connection;
+mod controllable;
+mod discovery;
+mod dry_run;
+mod events;
+mod light_type;
+mod mdns;
+mod metrics;
+mod offline;
+mod queue;
+mod rules;
+mod schedules;
+mod sensors;
+mod timestamp;
+mod tls;
+mod usage;
+
+pub use applied_state::AppliedState;
+#[cfg(feature = "blocking")]
+pub use blocking::BlockingDeconzClient;
+pub use buttons::{ButtonAction, ButtonEvent};
+pub use color::{
+    bri_to_percent, bri_to_perceptual, color_roundtrip_error, contrasting_text_color,
+    deconz_hsv_to_rgb, percent_to_bri, perceptual_to_bri, rgb_to_deconz_hsv, rgb_to_xy, xy_to_rgb,
+    ColorGamut, MAX_BRIGHTNESS, MAX_SATURATION,
+};
+pub use config::{GatewayConfig, LoginResult, ProbeResult, WhitelistEntry};
+pub use connection::ConnectionState;
+pub use controllable::Controllable;
+pub use discovery::{discover_gateways, discover_gateways_local, DiscoveredGateway};
+pub use events::{LightEvent, LightStateChange, ResourceKind, SensorEvent};
+pub use light_type::LightType;
+pub use metrics::RequestMetric;
+pub use offline::OfflineQueue;
+pub use queue::{ColorCommand, CommandOutcome, LightCommandQueue};
+pub use rules::{Rule, RuleAction, RuleCondition};
+pub use schedules::{
+    CommandMethod, Date, NewSchedule, Repeat, Schedule, ScheduleCommand, ScheduleTime, TimeOfDay,
+    Weekdays,
+};
+pub use sensors::{Sensor, SensorReading};
+pub use timestamp::Timestamp;
+pub use usage::LightUsageTracker;
 
 #[derive(Debug)]
 pub enum Error {
     HttpError(reqwest::Error),
     IdParseError(ParseIntError),
     ResponseParseError(String),
+    /// The light does not support the requested feature (the gateway answered with 400)
+    Unsupported(String),
+    WebsocketError(tokio_tungstenite::tungstenite::Error),
+    /// The operation needs lights but the gateway has none
+    NoLights,
+    /// A local socket (e.g. for discovery) or file (e.g. a backup) couldn't be used
+    IoError(std::io::Error),
+    /// The address of the gateway isn't a valid http(s) url
+    InvalidUrl(String),
+    /// The server answered with something other than json (e.g. an html page) or isn't a
+    /// deCONZ gateway for another reason
+    NotADeconzGateway,
+    /// The gateway answered with an error object
+    ApiError {
+        type_code: u32,
+        address: String,
+        description: String,
+    },
+    /// A request for several lights failed for some of them, the others were changed
+    PartialFailure(Vec<(LightId, Error)>),
+    /// A value is outside of the range the gateway accepts, nothing was sent
+    InvalidValue {
+        field: &'static str,
+        value: u32,
+    },
+    /// The gateway doesn't know the light (or other resource) asked for
+    NotFound(String),
+    /// The gateway can't store any more of something, e.g. scenes of a group
+    LimitReached(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::HttpError(e) => match e.status() {
+                Some(status) => write!(f, "The gateway answered with {}", status),
+                None => write!(f, "Request to the gateway failed: {}", e),
+            },
+            Error::IdParseError(e) => write!(f, "Invalid id: {}", e),
+            Error::ResponseParseError(e) => write!(f, "Unexpected answer from the gateway: {}", e),
+            Error::Unsupported(message) => f.write_str(message),
+            Error::WebsocketError(e) => write!(f, "Websocket error: {}", e),
+            Error::NoLights => f.write_str("The gateway has no lights"),
+            Error::IoError(e) => e.fmt(f),
+            Error::InvalidUrl(e) => write!(f, "Invalid address {}", e),
+            Error::NotADeconzGateway => f.write_str("That address isn't a deCONZ gateway"),
+            Error::ApiError {
+                type_code,
+                description,
+                ..
+            } => write!(f, "{} (error {})", description, type_code),
+            Error::PartialFailure(failures) => {
+                write!(f, "Failed to change {} lights:", failures.len())?;
+                for (id, e) in failures {
+                    write!(f, " light {}: {};", id, e)?;
+                }
+                Ok(())
+            }
+            Error::InvalidValue { field, value } => {
+                write!(f, "{} is out of range for {}", value, field)
+            }
+            Error::NotFound(message) | Error::LimitReached(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::HttpError(e) => Some(e),
+            Error::IdParseError(e) => Some(e),
+            Error::WebsocketError(e) => Some(e),
+            Error::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::HttpError(e)
+    }
+}
+
+impl From<ParseIntError> for Error {
+    fn from(e: ParseIntError) -> Self {
+        Error::IdParseError(e)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -18,183 +169,2438 @@ pub struct DeconzClient {
     /// The API token for the deconz server
     pub username: String,
     http: reqwest::Client,
+    retry: RetryPolicy,
+    /// Shared by all clones so the config is only fetched once
+    config: Arc<RwLock<Option<GatewayConfig>>>,
+    /// Shared by all clones so every request updates the same state
+    connection: Arc<tokio::sync::watch::Sender<ConnectionState>>,
+    on_request: Option<metrics::RequestHook>,
+    /// In 1/10 seconds, for state changes which don't set a transition themselves
+    default_transition: Option<u16>,
+    dry_run: bool,
+    /// Shared by all clones, `None` unless enabled on the builder
+    offline_queue: Option<Arc<OfflineQueue>>,
+    /// Shared by all clones, `None` unless `restore_brightness` is enabled on the builder
+    brightness: Option<Arc<brightness::BrightnessMemory>>,
+    /// For the websocket of an https gateway, which the http client can't be used for
+    websocket_tls: Arc<rustls::ClientConfig>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Light {
     pub name: String,
+    pub id: LightId,
+    pub capabilities: LightCapabilities,
+    /// The deconz type of the light, e.g. `LightType::ColorTemperatureLight`
+    pub light_type: Option<LightType>,
+    pub model_id: Option<String>,
+    pub manufacturer: Option<String>,
+    /// When the gateway last heard from the light (minute precision), `None` if never
+    #[serde(default)]
+    pub last_seen: Option<Timestamp>,
+    /// When the light last joined the network
+    #[serde(default)]
+    pub last_announced: Option<Timestamp>,
+}
+
+/// Lookups in a list of lights, e.g. the result of `LightClient::get_light_list`
+pub trait LightListExt {
+    fn find_light_by_id(&self, id: &LightId) -> Option<&Light>;
+
+    /// Finds the first light with the name, prefer `find_light_by_id` as names aren't unique
+    fn find_light_by_name(&self, name: &str) -> Option<&Light>;
+}
+
+impl<T: AsRef<[Light]> + ?Sized> LightListExt for T {
+    fn find_light_by_id(&self, id: &LightId) -> Option<&Light> {
+        self.as_ref().iter().find(|l| &l.id == id)
+    }
+
+    fn find_light_by_name(&self, name: &str) -> Option<&Light> {
+        self.as_ref().iter().find(|l| l.name == name)
+    }
+}
+
+/// The id of a light exactly as the gateway reports it
+///
+/// deconz numbers its lights, but some bridges use ids like `0x1234`, so the id is kept as a string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct LightId(String);
+
+impl LightId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The id as a number, if it is a decimal one
+    pub fn as_u32(&self) -> Option<u32> {
+        self.0.parse().ok()
+    }
+}
+
+impl fmt::Display for LightId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for LightId {
+    fn from(id: String) -> Self {
+        LightId(id)
+    }
+}
+
+impl From<&str> for LightId {
+    fn from(id: &str) -> Self {
+        LightId(String::from(id))
+    }
+}
+
+impl From<u32> for LightId {
+    fn from(id: u32) -> Self {
+        LightId(id.to_string())
+    }
+}
+
+/// Numeric ids are ordered by their value and before all other ids
+impl Ord for LightId {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self.as_u32(), other.as_u32()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => self.0.cmp(&other.0),
+        }
+    }
+}
+
+impl PartialOrd for LightId {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Also accepts numbers, which older versions of this crate serialized
+impl<'de> Deserialize<'de> for LightId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawId {
+            Number(u32),
+            String(String),
+        }
+
+        Ok(match RawId::deserialize(deserializer)? {
+            RawId::Number(id) => LightId::from(id),
+            RawId::String(id) => LightId(id),
+        })
+    }
+}
+
+/// The features a light supports
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LightCapabilities {
+    pub dimmable: bool,
+    pub color: bool,
+    pub color_temp: bool,
+    /// The lowest brightness at which the light stays on, lower values may turn it off
+    #[serde(default)]
+    pub min_bri: u8,
+    /// Whether the light has color gradients (like some led strips) and supports setting their `speed`
+    #[serde(default)]
+    pub gradient: bool,
+    /// The effects the light can run, including `Effect::None`
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    /// The alerts the light can show
+    #[serde(default)]
+    pub alerts: Vec<AlertMode>,
+    /// The colors the light can show, `None` if the gateway doesn't report them
+    #[serde(default)]
+    pub gamut: Option<ColorGamut>,
+}
+
+impl LightCapabilities {
+    /// Infers the capabilities from the type of a light and the fields present in its state
+    fn from_type_and_state(
+        light_type: Option<&LightType>,
+        state: &CapabilityState,
+        reported: &ReportedCapabilities,
+    ) -> Self {
+        let (dimmable, color, color_temp) = match light_type {
+            Some(t) => (t.is_dimmable(), t.supports_color(), t.supports_ct()),
+            None => (false, false, false),
+        };
+
+        LightCapabilities {
+            dimmable: dimmable || state.bri.is_some(),
+            color: color || state.hue.is_some() || state.xy.is_some(),
+            color_temp: color_temp || state.ct.is_some(),
+            // The gateway reports the minimum as a fraction of the full brightness
+            min_bri: reported
+                .bri
+                .as_ref()
+                .and_then(|bri| bri.min_dim_level)
+                .map(|level| (level.clamp(0.0, 1.0) * u8::MAX as f64).ceil() as u8)
+                .unwrap_or_default(),
+            gradient: state.speed.is_some()
+                || reported
+                    .color
+                    .as_ref()
+                    .is_some_and(|color| color.gradient.is_some()),
+            // Older firmware doesn't list them, so assume what nearly every light supports
+            effects: match reported.color.as_ref().and_then(|c| c.effects.clone()) {
+                Some(effects) => effects,
+                None if color => vec![Effect::None, Effect::ColorLoop],
+                None => vec![],
+            },
+            alerts: match &reported.alerts {
+                Some(alerts) => alerts
+                    .iter()
+                    .filter_map(|alert| {
+                        let alert: Result<_, serde::de::value::Error> =
+                            AlertMode::deserialize(alert.as_str().into_deserializer());
+                        alert.ok()
+                    })
+                    .collect(),
+                None => vec![AlertMode::None, AlertMode::Select, AlertMode::LSelect],
+            },
+            gamut: reported.color.as_ref().and_then(|color| color.xy),
+        }
+    }
+}
+
+/// The blink effects used to physically identify a light
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertMode {
+    /// Blinks once
+    Select,
+    /// Breathes for 15 seconds
+    LSelect,
+    /// Stops an ongoing alert
+    None,
+}
+
+/// What a light does when it gets power again, e.g. after a power outage
+#[derive(Debug, Clone)]
+pub enum PowerOnBehavior {
+    /// Restores the state from before the power was lost
+    LastState,
+    On,
+    Off,
+    /// Turns on or off with the brightness and color of the given state
+    Custom(LightState),
+}
+
+impl PowerOnBehavior {
+    /// Builds the body of `PUT api/<user>/lights/<id>/config`
+    fn to_config(&self) -> serde_json::Value {
+        match self {
+            PowerOnBehavior::LastState => serde_json::json!({ "on": { "startup": "previous" } }),
+            PowerOnBehavior::On => serde_json::json!({ "on": { "startup": true } }),
+            PowerOnBehavior::Off => serde_json::json!({ "on": { "startup": false } }),
+            PowerOnBehavior::Custom(state) => {
+                let mut config = serde_json::json!({ "on": { "startup": state.on } });
+                if let Some(bri) = state.bri {
+                    config["bri"] = serde_json::json!({ "startup": bri });
+                }
+                if let Some(xy) = state.xy {
+                    config["color"]["xy"] = serde_json::json!({ "startup": xy });
+                } else if let Some(ct) = state.ct {
+                    config["color"]["ct"] = serde_json::json!({ "startup": ct });
+                }
+                config
+            }
+        }
+    }
+}
+
+/// Dynamic effects a light can run on its own
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    /// Cycles through all hues
+    ColorLoop,
+    /// Stops the running effect
+    None,
+    /// An effect without its own variant by its deconz name, e.g. `candle`
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl Effect {
+    /// The name of the effect in the api, e.g. `colorloop`
+    pub fn as_str(&self) -> &str {
+        match self {
+            Effect::ColorLoop => "colorloop",
+            Effect::None => "none",
+            Effect::Other(name) => name,
+        }
+    }
+}
+
+/// Which of its color values a light currently uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Hue and saturation
+    Hs,
+    /// CIE xy coordinates
+    Xy,
+    /// Color temperature
+    Ct,
+    /// A mode this crate doesn't know
+    #[serde(other)]
+    Unknown,
+}
+
+/// The color mode a change to the given values puts a light in, `None` if it leaves the color
+/// alone
+///
+/// A color temperature wins over xy, which wins over hue and saturation.
+pub(crate) fn infer_color_mode(
+    ct: Option<u16>,
+    xy: Option<[f32; 2]>,
+    hue: Option<u16>,
+    sat: Option<u8>,
+) -> Option<ColorMode> {
+    if ct.is_some() {
+        Some(ColorMode::Ct)
+    } else if xy.is_some() {
+        Some(ColorMode::Xy)
+    } else if hue.is_some() || sat.is_some() {
+        Some(ColorMode::Hs)
+    } else {
+        None
+    }
+}
+
+/// A group of lights (usually a room) as configured on the gateway
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub id: u32,
+    /// The ids of the lights in this group
+    pub lights: Vec<LightId>,
+}
+
+impl Group {
+    /// The id the gateway reserves for the group of all lights
+    pub const ALL_LIGHTS_ID: u32 = 0;
+
+    /// The special group containing every light, which isn't part of `get_group_list`
+    ///
+    /// Its `lights` are left empty as the gateway doesn't list them.
+    pub fn all_lights_group() -> Self {
+        Group {
+            name: String::from("All lights"),
+            id: Self::ALL_LIGHTS_ID,
+            lights: vec![],
+        }
+    }
+}
+
+/// The state of a group as returned by `GET api/<user>/groups/<id>`
+#[derive(Debug, Clone)]
+pub struct GroupState {
+    /// The last state sent to the whole group, which doesn't follow changes of single lights
+    ///
+    /// Still a good guess for the color and brightness of the room. `reachable` is always false.
+    pub action: LightState,
+    /// Whether at least one light of the group is on
+    pub any_on: bool,
+    /// Whether every light of the group is on
+    pub all_on: bool,
+}
+
+/// A saved light configuration of a group
+#[derive(Debug, Clone)]
+pub struct Scene {
     pub id: u32,
+    pub name: String,
+    /// The id of the group the scene belongs to
+    pub group_id: u32,
+    /// How long recalling the scene fades the lights, in 1/10 seconds, `None` if the gateway
+    /// doesn't tell
+    pub transition_time: Option<u16>,
+}
+
+/// The `capabilities` object newer gateways include for a light
+#[derive(Deserialize, Default)]
+struct ReportedCapabilities {
+    bri: Option<BriCapabilities>,
+    color: Option<ColorCapabilities>,
+    /// Unknown alerts are skipped, so they are parsed later
+    alerts: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct BriCapabilities {
+    min_dim_level: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct ColorCapabilities {
+    /// Only present for lights with gradients, the details aren't used yet
+    gradient: Option<serde::de::IgnoredAny>,
+    effects: Option<Vec<Effect>>,
+    /// The gamut triangle, only reported for lights with xy colors
+    xy: Option<ColorGamut>,
+}
+
+/// The fields of a light state whose presence tells which features a light has
+#[derive(Deserialize, Default)]
+struct CapabilityState {
+    bri: Option<u8>,
+    hue: Option<u16>,
+    xy: Option<[f32; 2]>,
+    ct: Option<u16>,
+    speed: Option<u8>,
 }
 
-#[derive(Debug, Clone, Deserialize, Copy)]
+/// The state of a light as reported by the gateway
+///
+/// The gateway only reports the fields a light supports, the others are `None`. On/off-only
+/// lights like plugs have neither `bri` nor a color, `is_dimmable` and `is_color_capable` tell
+/// which controls make sense for a light.
+///
+/// New fields are added as the gateway reports more, so outside of this crate the state can't
+/// be built with a struct expression or matched exhaustively.
+///
+/// # Migrating
+///
+/// Code which built or destructured the state (like the desktop app did) changes as follows.
+/// The fields stay public so existing changes like `state.on = false` keep working, but reads
+/// should go through the accessor methods, which keep working if a field's representation
+/// changes:
+///
+/// ```
+/// # use deconz::LightState;
+/// // Instead of LightState { on: true, bri: Some(200), ..  }
+/// let state = LightState::builder().on(true).bri(200).build();
+///
+/// // Instead of state.bri and `let LightState { on, .. } = state`
+/// assert_eq!(state.bri(), Some(200));
+/// assert!(state.on());
+///
+/// // Instead of LightState { on: false, ..state }
+/// let mut off = state.clone();
+/// off.on = false;
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct LightState {
     pub on: bool,
+    /// Missing in the `action` of groups, which is read as unreachable
+    #[serde(default)]
     pub reachable: bool,
     pub hue: Option<u16>,
     pub bri: Option<u8>,
     pub sat: Option<u8>,
+    /// Color temperature in mireds
+    pub ct: Option<u16>,
+    /// Color as CIE xy coordinates
+    pub xy: Option<[f32; 2]>,
+    /// The running effect, e.g. `colorloop` or `none`
+    pub effect: Option<String>,
+    /// Whether `hue`/`sat`, `xy` or `ct` describe the current color, `None` for lights without color
+    #[serde(rename = "colormode", default)]
+    pub color_mode: Option<ColorMode>,
+    /// The speed of effects and gradients (0-255), only reported by lights which support it
+    #[serde(default)]
+    pub speed: Option<u8>,
+}
+
+impl LightState {
+    /// Returns a builder for a state which is off and reachable, with every other field unset
+    pub fn builder() -> LightStateBuilder {
+        LightStateBuilder::new()
+    }
+
+    pub fn on(&self) -> bool {
+        self.on
+    }
+
+    /// Whether the gateway can currently reach the light, always `false` for the action of a group
+    pub fn reachable(&self) -> bool {
+        self.reachable
+    }
+
+    pub fn hue(&self) -> Option<u16> {
+        self.hue
+    }
+
+    pub fn bri(&self) -> Option<u8> {
+        self.bri
+    }
+
+    pub fn sat(&self) -> Option<u8> {
+        self.sat
+    }
+
+    /// The color temperature in mireds
+    pub fn ct(&self) -> Option<u16> {
+        self.ct
+    }
+
+    /// The color as CIE xy coordinates
+    pub fn xy(&self) -> Option<[f32; 2]> {
+        self.xy
+    }
+
+    /// The running effect by its name in the api, e.g. `colorloop` or `none`
+    pub fn effect(&self) -> Option<&str> {
+        self.effect.as_deref()
+    }
+
+    pub fn color_mode(&self) -> Option<ColorMode> {
+        self.color_mode
+    }
+
+    pub fn speed(&self) -> Option<u8> {
+        self.speed
+    }
+
+    /// The perceived brightness in percent (0-100), `None` for lights which can't be dimmed
+    ///
+    /// Matches `LightClient::set_brightness_percent`, see `bri_to_perceptual`.
+    pub fn brightness_percent(&self) -> Option<f32> {
+        self.bri.map(|bri| bri_to_perceptual(bri) * 100.0)
+    }
+
+    /// Whether the light reports a brightness, `false` for on/off-only lights like plugs
+    pub fn is_dimmable(&self) -> bool {
+        self.bri.is_some()
+    }
+
+    /// Whether the light reports a color as `hue`/`sat` or `xy`
+    ///
+    /// White lights which only have a color temperature (`ct`) aren't color capable.
+    pub fn is_color_capable(&self) -> bool {
+        (self.hue.is_some() && self.sat.is_some()) || self.xy.is_some()
+    }
+}
+
+/// Builds a `LightState`, e.g. for `PowerOnBehavior::Custom` or a mock `LightClient`
+#[derive(Debug, Clone)]
+pub struct LightStateBuilder {
+    state: LightState,
+}
+
+impl Default for LightStateBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LightStateBuilder {
+    pub fn new() -> Self {
+        LightStateBuilder {
+            state: LightState {
+                on: false,
+                reachable: true,
+                hue: None,
+                bri: None,
+                sat: None,
+                ct: None,
+                xy: None,
+                effect: None,
+                color_mode: None,
+                speed: None,
+            },
+        }
+    }
+
+    pub fn on(mut self, on: bool) -> Self {
+        self.state.on = on;
+        self
+    }
+
+    pub fn reachable(mut self, reachable: bool) -> Self {
+        self.state.reachable = reachable;
+        self
+    }
+
+    pub fn hue(mut self, hue: u16) -> Self {
+        self.state.hue = Some(hue);
+        self
+    }
+
+    pub fn bri(mut self, bri: u8) -> Self {
+        self.state.bri = Some(bri);
+        self
+    }
+
+    pub fn sat(mut self, sat: u8) -> Self {
+        self.state.sat = Some(sat);
+        self
+    }
+
+    /// Sets the color temperature in mireds
+    pub fn ct(mut self, ct: u16) -> Self {
+        self.state.ct = Some(ct);
+        self
+    }
+
+    /// Sets the color as CIE xy coordinates
+    pub fn xy(mut self, xy: [f32; 2]) -> Self {
+        self.state.xy = Some(xy);
+        self
+    }
+
+    /// Sets the running effect by its name in the api, e.g. `colorloop`
+    pub fn effect<S: Into<String>>(mut self, effect: S) -> Self {
+        self.state.effect = Some(effect.into());
+        self
+    }
+
+    pub fn color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.state.color_mode = Some(color_mode);
+        self
+    }
+
+    pub fn speed(mut self, speed: u8) -> Self {
+        self.state.speed = Some(speed);
+        self
+    }
+
+    pub fn build(self) -> LightState {
+        self.state
+    }
 }
 
+/// How many requests bulk operations like `LightClient::get_all_light_states` send at once
+pub const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+#[allow(async_fn_in_trait)]
 pub trait LightClient {
     async fn get_light_list(&self) -> Result<Vec<Light>, crate::Error>;
 
-    async fn set_on_state(&self, light: &Light, state: bool) -> Result<(), Error>;
+    /// Loads a single light, e.g. a favorite whose id is known, without listing all lights
+    ///
+    /// Fails with `Error::NotFound` if the gateway has no light with that id.
+    async fn get_light(&self, id: &LightId) -> Result<Light, Error> {
+        self.get_light_list()
+            .await?
+            .find_light_by_id(id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("There is no light with id {}", id)))
+    }
+
+    /// Whether the gateway has any lights at all
+    async fn has_lights(&self) -> Result<bool, Error> {
+        Ok(!self.get_light_list().await?.is_empty())
+    }
+
+    /// Turns a light on or off and returns what the gateway applied
+    async fn set_on_state(&self, light: &Light, state: bool) -> Result<AppliedState, Error> {
+        self.set_on_state_with_transition(light, state, None).await
+    }
+
+    /// Turns a light off if it is on and on if it is off, returns whether it is on now
+    ///
+    /// The current state is fetched first, so no cached state is needed.
+    async fn toggle_on_state(&self, light: &Light) -> Result<bool, Error> {
+        let on = !self.get_light_state(light).await?.on;
+        self.set_on_state(light, on).await?;
+        Ok(on)
+    }
+
+    /// Like `set_on_state` but fades over `transition_time` (in 1/10 seconds)
+    ///
+    /// `None` uses the default transition of the client, see
+    /// `DeconzClientBuilder::default_transition`, and `Some(0)` switches right away.
+    async fn set_on_state_with_transition(
+        &self,
+        light: &Light,
+        state: bool,
+        transition_time: Option<u16>,
+    ) -> Result<AppliedState, Error>;
 
+    /// Sets the color of a light and returns what the gateway applied, values which are
+    /// `None` stay unchanged
+    ///
+    /// Fails with `Error::InvalidValue` without sending anything if `sat` is above
+    /// `MAX_SATURATION` or `bri` above `MAX_BRIGHTNESS`. A `bri` of 0 is passed on, depending on
+    /// the light it is its lowest brightness or the gateway rejects it with `Error::ApiError`.
     async fn set_light_color(
         &self,
         light: &Light,
         hue: Option<u16>,
         bri: Option<u8>,
         sat: Option<u8>,
-    ) -> Result<(), Error>;
+    ) -> Result<AppliedState, Error> {
+        self.set_light_color_with_transition(light, hue, bri, sat, None)
+            .await
+    }
+
+    /// Sets the color of a light from RGB values, converting them to hue, saturation and brightness
+    ///
+    /// Black (all channels zero) sets the brightness to 0 but doesn't turn the light off,
+    /// most lights stay on at their lowest brightness. Use `set_on_state` to turn it off.
+    /// Fully saturated or bright colors map to `MAX_SATURATION` and `MAX_BRIGHTNESS`.
+    async fn set_light_rgb(
+        &self,
+        light: &Light,
+        r: u8,
+        g: u8,
+        b: u8,
+    ) -> Result<AppliedState, Error> {
+        let (hue, sat, bri) = rgb_to_deconz_hsv(palette::Srgb::new(r, g, b).into_format());
+        self.set_light_color(light, Some(hue), Some(bri), Some(sat))
+            .await
+    }
+
+    /// Like `set_light_color` but fades over `transition_time` (in 1/10 seconds)
+    ///
+    /// `None` uses the default transition of the client, see
+    /// `DeconzClientBuilder::default_transition`, and `Some(0)` switches right away.
+    async fn set_light_color_with_transition(
+        &self,
+        light: &Light,
+        hue: Option<u16>,
+        bri: Option<u8>,
+        sat: Option<u8>,
+        transition_time: Option<u16>,
+    ) -> Result<AppliedState, Error>;
+
+    /// Sets the color temperature of a light in mireds (roughly 153 to 500)
+    async fn set_color_temp(&self, light: &Light, ct: u16) -> Result<(), Error>;
+
+    /// Sets the color of a light as CIE xy coordinates (both 0.0 to 1.0)
+    ///
+    /// Colors outside of `LightCapabilities::gamut` are moved to the closest color the light
+    /// can show, see `ColorGamut::clamp_to_gamut`.
+    async fn set_light_xy(&self, light: &Light, x: f32, y: f32) -> Result<(), Error>;
 
     async fn get_light_state(&self, light: &Light) -> Result<LightState, Error>;
-}
 
-impl LightClient for DeconzClient {
-    async fn get_light_list(&self) -> Result<Vec<Light>, crate::Error> {
-        let resp = self
-            .http
-            .get(
-                self.url
-                    .join(&format!("api/{}/lights", self.username))
-                    .unwrap(),
-            )
-            .send()
-            .await;
+    /// Tells for every light (by id) whether the gateway can reach it
+    ///
+    /// Unlike `get_all_light_states` this only needs a single request.
+    async fn get_light_reachability(&self) -> Result<HashMap<LightId, bool>, Error>;
+
+    /// Sets the perceived brightness in percent (0-100)
+    ///
+    /// 50% looks half as bright as 100% rather than being half the light output, see
+    /// `perceptual_to_bri`.
+    async fn set_brightness_percent(
+        &self,
+        light: &Light,
+        percent: f32,
+    ) -> Result<AppliedState, Error> {
+        let bri = perceptual_to_bri(percent / 100.0);
+        self.set_light_color(light, None, Some(bri), None).await
+    }
+
+    /// Raises a brightness to the lowest level at which the light stays on and lowers it to
+    /// `MAX_BRIGHTNESS`
+    fn clamp_brightness(&self, light: &Light, bri: u8) -> u8 {
+        bri.max(light.capabilities.min_bri).min(MAX_BRIGHTNESS)
+    }
 
-        let resp = resp
-            .and_then(|r| r.error_for_status())
-            .map_err(|e| Error::HttpError(e))?;
+    /// Changes the brightness relative to its current value by `delta` (-254 to 254)
+    ///
+    /// The gateway clamps the result, use `get_light_state` to read the new brightness.
+    async fn step_brightness(&self, light: &Light, delta: i16) -> Result<(), Error>;
 
-        #[derive(Deserialize)]
-        struct LightWithoutId {
-            name: String,
+    /// Turns the hue relative to its current value by `delta` (-65534 to 65534)
+    ///
+    /// The hue is a circle, so the result wraps around: stepping 65000 by 1000 gives 464.
+    async fn step_hue(&self, light: &Light, delta: i32) -> Result<(), Error>;
+
+    /// Changes the saturation relative to its current value by `delta` (-254 to 254)
+    ///
+    /// The gateway clamps the result, use `get_light_state` to read the new saturation.
+    async fn step_saturation(&self, light: &Light, delta: i16) -> Result<(), Error>;
+
+    /// Loads the states of all lights, keyed by light id
+    ///
+    /// The per-light requests run concurrently, at most `MAX_CONCURRENT_REQUESTS` at a time.
+    /// Fails with `Error::NoLights` if the gateway has no lights.
+    async fn get_all_light_states(&self) -> Result<HashMap<LightId, LightState>, Error> {
+        let lights = self.get_light_list().await?;
+        if lights.is_empty() {
+            return Err(Error::NoLights);
         }
 
-        let lights = resp
-            .json::<HashMap<String, LightWithoutId>>()
+        stream::iter(&lights)
+            .map(|light| async move { Ok((light.id.clone(), self.get_light_state(light).await?)) })
+            .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+            .try_collect()
             .await
-            .map_err(|e| Error::HttpError(e))?;
+    }
 
-        let lights: Vec<Light> = lights
-            .into_iter()
-            .map(|(id, light)| {
-                u32::from_str_radix(&id, 10)
-                    .map_err(|e| Error::IdParseError(e))
-                    .and_then(|id| {
-                        Ok(Light {
-                            name: light.name,
-                            id,
-                        })
-                    })
+    /// Sets several lights to the same color, e.g. to paint a selection of lights at once
+    ///
+    /// The requests run concurrently, at most `MAX_CONCURRENT_REQUESTS` at a time. A failing
+    /// light doesn't stop the others, the failed ones are returned as `Error::PartialFailure`.
+    async fn set_lights_color(
+        &self,
+        lights: &[&Light],
+        hue: Option<u16>,
+        bri: Option<u8>,
+        sat: Option<u8>,
+    ) -> Result<(), Error> {
+        let failures: Vec<(LightId, Error)> = stream::iter(lights)
+            .map(|light| async move {
+                let result = self.set_light_color(light, hue, bri, sat).await;
+                result.err().map(|e| (light.id.clone(), e))
             })
-            .collect::<Result<Vec<Light>, Error>>()?;
+            .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+            .filter_map(future::ready)
+            .collect()
+            .await;
 
-        Ok(lights)
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::PartialFailure(failures))
+        }
     }
 
-    async fn set_on_state(&self, light: &Light, state: bool) -> Result<(), Error> {
-        #[derive(Serialize)]
-        struct OnOffReq {
-            on: bool,
+    /// Makes a light blink so it can be identified. `AlertMode::None` cancels an ongoing `LSelect`.
+    async fn alert(&self, light: &Light, mode: AlertMode) -> Result<(), Error>;
+
+    async fn set_effect(&self, light: &Light, effect: Effect) -> Result<(), Error> {
+        self.set_effect_with_speed(light, effect, None).await
+    }
+
+    /// Like `set_effect` but also sets the `colorloopspeed` if given,
+    /// which is the seconds a loop takes from 1 (fast) to 255 (slow)
+    ///
+    /// Effects missing from `LightCapabilities::effects` fail with `Error::Unsupported`.
+    async fn set_effect_with_speed(
+        &self,
+        light: &Light,
+        effect: Effect,
+        color_loop_speed: Option<u8>,
+    ) -> Result<(), Error>;
+
+    /// Sets the speed of effects and gradients (0-255)
+    ///
+    /// Only lights with `LightCapabilities::gradient` support it, others fail with
+    /// `Error::Unsupported`.
+    async fn set_speed(&self, light: &Light, speed: u8) -> Result<(), Error>;
+
+    /// Configures the state a light starts in when it gets power
+    ///
+    /// Lights which can't be configured fail with `Error::Unsupported`.
+    async fn set_power_on_behavior(
+        &self,
+        light: &Light,
+        behavior: PowerOnBehavior,
+    ) -> Result<(), Error>;
+
+    /// Renames a light. The gateway rejects names longer than 32 characters with an `Error::ApiError`.
+    async fn set_light_name(&self, light: &Light, name: &str) -> Result<(), Error>;
+
+    /// Renames a group (room), with the same limit on the length as `set_light_name`
+    async fn set_group_name(&self, group: &Group, name: &str) -> Result<(), Error>;
+
+    /// Renames a scene, with the same limit on the length as `set_light_name`
+    async fn set_scene_name(&self, scene: &Scene, name: &str) -> Result<(), Error>;
+
+    async fn get_group_list(&self) -> Result<Vec<Group>, Error>;
+
+    /// Reads the last state sent to a group and whether its lights are on
+    async fn get_group_state(&self, group: &Group) -> Result<GroupState, Error>;
+
+    /// Turns all lights of a group on or off with a single request
+    async fn set_group_on_state(&self, group: &Group, state: bool) -> Result<(), Error>;
+
+    /// Sets the color of all lights of a group with a single request
+    ///
+    /// The values are checked like those of `set_light_color`.
+    async fn set_group_color(
+        &self,
+        group: &Group,
+        hue: Option<u16>,
+        bri: Option<u8>,
+        sat: Option<u8>,
+    ) -> Result<(), Error>;
+
+    /// Replaces the lights of a group
+    ///
+    /// The last writer wins, lights added or removed by someone else in the meantime are overwritten.
+    async fn set_group_lights(&self, group: &Group, light_ids: &[LightId]) -> Result<(), Error>;
+
+    /// Adds a light to a group, keeping the lights already in it
+    ///
+    /// The membership is fetched right before it is written back to keep the window for
+    /// concurrent edits small, but an edit between the two requests is still lost.
+    async fn add_light_to_group(&self, group: &Group, light: &Light) -> Result<(), Error> {
+        let mut lights = current_group_lights(self, group).await?;
+        if lights.contains(&light.id) {
+            return Ok(());
         }
+        lights.push(light.id.clone());
+        self.set_group_lights(group, &lights).await
+    }
+
+    /// Removes a light from a group, with the same caveat as `add_light_to_group`
+    async fn remove_light_from_group(&self, group: &Group, light: &Light) -> Result<(), Error> {
+        let mut lights = current_group_lights(self, group).await?;
+        if !lights.contains(&light.id) {
+            return Ok(());
+        }
+        lights.retain(|id| *id != light.id);
+        self.set_group_lights(group, &lights).await
+    }
+
+    /// Creates an empty group and returns it
+    ///
+    /// The gateway allows several groups with the same name, the id of the returned group tells them apart.
+    async fn create_group(&self, name: &str) -> Result<Group, Error>;
+
+    /// Deletes a group, its lights are left as they are
+    async fn delete_group(&self, group: &Group) -> Result<(), Error>;
+
+    async fn get_scenes(&self, group: &Group) -> Result<Vec<Scene>, Error>;
+
+    /// Applies a scene to the lights of its group
+    async fn recall_scene(&self, scene: &Scene) -> Result<(), Error>;
+
+    /// Creates a scene of a group from the current state of its lights and returns it
+    ///
+    /// Fails with `Error::LimitReached` if the gateway can't store more scenes.
+    async fn create_scene(&self, group: &Group, name: &str) -> Result<Scene, Error>;
+
+    /// Replaces what a scene sets the lights of its group to with their current state
+    async fn store_scene(&self, scene: &Scene) -> Result<(), Error>;
+
+    /// Reads the states a scene sets its lights to, e.g. to preview it before recalling it
+    ///
+    /// Every light of the group is listed, those the scene doesn't store are left as they are on
+    /// recall and come with `None`. Lights removed from the group after the scene was stored
+    /// are still listed, as the gateway keeps recalling them.
+    async fn get_scene_details(
+        &self,
+        scene: &Scene,
+    ) -> Result<Vec<(LightId, Option<LightState>)>, Error>;
+
+    /// Reads all sensors (temperature, humidity, presence, ...) and their last values
+    async fn get_sensors(&self) -> Result<Vec<Sensor>, Error>;
+
+    async fn get_schedules(&self) -> Result<Vec<Schedule>, Error>;
+
+    /// Stores a schedule on the gateway and returns its id
+    async fn create_schedule(&self, schedule: &NewSchedule) -> Result<u32, Error>;
+
+    async fn delete_schedule(&self, schedule: &Schedule) -> Result<(), Error>;
+
+    /// Reads the automations stored on the gateway
+    async fn get_rules(&self) -> Result<Vec<Rule>, Error>;
+
+    /// Enables or disables an automation, e.g. to pause a motion light during a movie
+    async fn set_rule_enabled(&self, rule: &Rule, enabled: bool) -> Result<(), Error>;
+
+    /// Streams changes of lights as they happen, including those made by other clients
+    ///
+    /// Clients which can't push changes return an empty stream.
+    fn subscribe_events(&self) -> impl Stream<Item = Result<LightEvent, Error>> + 'static {
+        stream::empty()
+    }
+
+    /// Tells whether the gateway can be reached, updated by every request and the event socket
+    ///
+    /// The receiver starts with the current state, `changed()` waits for the next one. Clients
+    /// which are always connected return a receiver which stays `Connected`.
+    fn watch_connection_state(&self) -> tokio::sync::watch::Receiver<ConnectionState> {
+        tokio::sync::watch::channel(ConnectionState::Connected).1
+    }
+
+    /// Streams new readings of sensors as they happen
+    ///
+    /// Clients which can't push changes return an empty stream.
+    fn subscribe_sensor_events(&self) -> impl Stream<Item = Result<SensorEvent, Error>> + 'static {
+        stream::empty()
+    }
+
+    /// Streams the readings of presence (motion) sensors, e.g. to refresh after an automation
+    /// triggered by motion changed some lights
+    ///
+    /// Errors of the underlying stream are passed on.
+    fn subscribe_presence_events(
+        &self,
+    ) -> impl Stream<Item = Result<SensorEvent, Error>> + 'static {
+        self.subscribe_sensor_events().filter(|event| {
+            future::ready(matches!(
+                event,
+                Ok(SensorEvent {
+                    reading: SensorReading::Presence(_),
+                    ..
+                }) | Err(_)
+            ))
+        })
+    }
+
+    /// Streams the presses of the buttons of switches with the id of the switch, e.g. to
+    /// react to a wall switch in the app
+    ///
+    /// Errors of the underlying stream are passed on.
+    fn subscribe_button_events(
+        &self,
+    ) -> impl Stream<Item = Result<(u32, ButtonEvent), Error>> + 'static {
+        self.subscribe_sensor_events().filter_map(|event| {
+            future::ready(match event {
+                Ok(event) => event
+                    .reading
+                    .button_event()
+                    .map(|button| Ok((event.id, button))),
+                Err(e) => Some(Err(e)),
+            })
+        })
+    }
+
+    /// Polls the states of all lights every `interval` and streams what changed
+    ///
+    /// Emits the same events as `subscribe_events` and serves as a fallback when the gateway
+    /// doesn't expose its websocket. A failed poll yields an error, but polling goes on.
+    fn watch_light_states(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<LightEvent, Error>> + '_ {
+        events::poll(self, interval)
+    }
+}
+
+/// Body of a request changing the on state of a light or group
+#[derive(Serialize)]
+struct OnOffReq {
+    on: bool,
+    /// The brightness to turn on with, see `DeconzClientBuilder::restore_brightness`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bri: Option<u8>,
+    #[serde(rename = "transitiontime", skip_serializing_if = "Option::is_none")]
+    transition_time: Option<u16>,
+}
+
+/// Body of a request renaming a light, group or scene
+#[derive(Serialize)]
+struct RenameReq<'a> {
+    name: &'a str,
+}
+
+/// Body of a request changing the color of a light or group
+///
+/// Fields which are `None` are left out so the gateway leaves them unchanged.
+#[derive(Serialize)]
+struct ColorChangeReq {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hue: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bri: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sat: Option<u8>,
+    #[serde(rename = "transitiontime", skip_serializing_if = "Option::is_none")]
+    transition_time: Option<u16>,
+}
+
+/// Loads the lights of a group as they are on the gateway right now
+async fn current_group_lights<C: LightClient + ?Sized>(
+    client: &C,
+    group: &Group,
+) -> Result<Vec<LightId>, Error> {
+    client
+        .get_group_list()
+        .await?
+        .into_iter()
+        .find(|g| g.id == group.id)
+        .map(|g| g.lights)
+        // Like the gateway answers for a group which doesn't exist (anymore)
+        .ok_or_else(|| Error::ApiError {
+            type_code: 3,
+            address: format!("/groups/{}", group.id),
+            description: format!("resource, /groups/{}, not available", group.id),
+        })
+}
+
+/// Checks a color change against the ranges the gateway accepts, see `LightClient::set_light_color`
+fn check_color_ranges(bri: Option<u8>, sat: Option<u8>) -> Result<(), Error> {
+    for (field, value, max) in [("bri", bri, MAX_BRIGHTNESS), ("sat", sat, MAX_SATURATION)] {
+        if let Some(value) = value.filter(|value| *value > max) {
+            return Err(Error::InvalidValue {
+                field,
+                value: value as u32,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Whether a failed request might succeed if it is sent again
+///
+/// That is the case if the gateway is overloaded (503) or the connection failed or was reset.
+fn is_transient(error: &Error) -> bool {
+    let Error::HttpError(e) = error else {
+        return false;
+    };
+    if e.status() == Some(StatusCode::SERVICE_UNAVAILABLE) || e.is_connect() {
+        return true;
+    }
+
+    let mut source = std::error::Error::source(e);
+    while let Some(inner) = source {
+        if let Some(io) = inner.downcast_ref::<std::io::Error>() {
+            return io.kind() == std::io::ErrorKind::ConnectionReset;
+        }
+        source = inner.source();
+    }
+    false
+}
+
+/// The time to wait before the next attempt: 100ms doubled per attempt plus up to 50% jitter
+fn backoff(attempt: u32) -> Duration {
+    let delay = Duration::from_millis(100) * 2u32.pow(attempt.min(10));
+    // The sub-second part of the clock is random enough to spread out concurrent retries
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    delay + delay.mul_f64(nanos as f64 / 1e9 / 2.0)
+}
+
+/// Reads the body of a response
+///
+/// Error responses of the gateway are turned into `Error::ApiError`, even if they come with status 200.
+async fn read_body(resp: reqwest::Response) -> Result<String, Error> {
+    let status_error = resp.error_for_status_ref().err();
+    // deconz always answers with json, other servers like captive portals usually with html
+    let is_json = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_none_or(|content_type| content_type.contains("json"));
+    if !is_json && status_error.is_none() {
+        return Err(Error::NotADeconzGateway);
+    }
+
+    let body = resp.text().await.map_err(Error::HttpError)?;
+
+    if let Some(e) = parse_api_error(&body) {
+        return Err(e);
+    }
+    match status_error {
+        Some(e) => Err(Error::HttpError(e)),
+        None => Ok(body),
+    }
+}
+
+/// Finds the first error in a response body like `[{"error":{"type":3,"address":"/lights/9","description":"..."}}]`
+fn parse_api_error(body: &str) -> Option<Error> {
+    #[derive(Deserialize)]
+    struct ApiErrorBody {
+        #[serde(rename = "type")]
+        type_code: u32,
+        #[serde(default)]
+        address: String,
+        #[serde(default)]
+        description: String,
+    }
+
+    #[derive(Deserialize)]
+    struct ApiResult {
+        error: Option<ApiErrorBody>,
+    }
+
+    let results = serde_json::from_str::<Vec<ApiResult>>(body).ok()?;
+    let error = results.into_iter().find_map(|r| r.error)?;
+
+    Some(Error::ApiError {
+        type_code: error.type_code,
+        address: error.address,
+        description: error.description,
+    })
+}
+
+/// Reads the id from the answer to creating a resource, like `[{"success":{"id":"7"}}]`
+fn parse_created_id(body: &str) -> Result<u32, Error> {
+    #[derive(Deserialize)]
+    struct Created {
+        id: String,
+    }
+    #[derive(Deserialize)]
+    struct CreateResponse {
+        success: Created,
+    }
+
+    let [resp] = serde_json::from_str::<[CreateResponse; 1]>(body)
+        .map_err(|e| Error::ResponseParseError(e.to_string()))?;
+    resp.success.id.parse().map_err(Error::IdParseError)
+}
+
+/// Maps the gateway rejecting a parameter (status 400 or error type 6) to `Error::Unsupported`
+fn map_unsupported<T>(
+    result: Result<T, Error>,
+    message: impl FnOnce() -> String,
+) -> Result<T, Error> {
+    match result {
+        Err(Error::ApiError { type_code: 6, .. }) => Err(Error::Unsupported(message())),
+        Err(Error::HttpError(e)) if e.status() == Some(StatusCode::BAD_REQUEST) => {
+            Err(Error::Unsupported(message()))
+        }
+        other => other,
+    }
+}
+
+/// Turns the answers of the gateway for a missing resource into `Error::NotFound`
+fn map_not_found<T>(
+    result: Result<T, Error>,
+    message: impl FnOnce() -> String,
+) -> Result<T, Error> {
+    match result {
+        Err(Error::ApiError { type_code: 3, .. }) => Err(Error::NotFound(message())),
+        Err(Error::HttpError(e)) if e.status() == Some(StatusCode::NOT_FOUND) => {
+            Err(Error::NotFound(message()))
+        }
+        other => other,
+    }
+}
+
+/// A light as the gateway describes it, the id is the key it is stored under
+#[derive(Deserialize)]
+struct LightWithoutId {
+    name: String,
+    // Older firmware leaves out some of these
+    #[serde(rename = "type")]
+    light_type: Option<LightType>,
+    #[serde(rename = "modelid")]
+    model_id: Option<String>,
+    #[serde(rename = "manufacturername")]
+    manufacturer: Option<String>,
+    #[serde(default)]
+    state: CapabilityState,
+    #[serde(default)]
+    capabilities: ReportedCapabilities,
+    #[serde(rename = "lastseen")]
+    last_seen: Option<Timestamp>,
+    #[serde(rename = "lastannounced")]
+    last_announced: Option<Timestamp>,
+}
+
+impl LightWithoutId {
+    fn into_light(self, id: LightId) -> Light {
+        Light {
+            capabilities: LightCapabilities::from_type_and_state(
+                self.light_type.as_ref(),
+                &self.state,
+                &self.capabilities,
+            ),
+            name: self.name,
+            id,
+            light_type: self.light_type,
+            model_id: self.model_id,
+            manufacturer: self.manufacturer,
+            last_seen: self.last_seen,
+            last_announced: self.last_announced,
+        }
+    }
+}
+
+impl LightClient for DeconzClient {
+    async fn get_light_list(&self) -> Result<Vec<Light>, crate::Error> {
+        let lights = self
+            .get_json::<HashMap<String, LightWithoutId>>("lights")
+            .await?;
+
+        let mut lights: Vec<Light> = lights
+            .into_iter()
+            .map(|(id, light)| light.into_light(LightId(id)))
+            .collect();
+
+        // The gateway returns an object, so sort to keep the order stable between calls
+        lights.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(lights)
+    }
+
+    async fn get_light(&self, id: &LightId) -> Result<Light, Error> {
+        let light = self
+            .get_json::<LightWithoutId>(&format!("lights/{}", id))
+            .await;
+        let light = map_not_found(light, || format!("There is no light with id {}", id))?;
+
+        Ok(light.into_light(id.clone()))
+    }
+
+    async fn set_on_state_with_transition(
+        &self,
+        light: &Light,
+        state: bool,
+        transition_time: Option<u16>,
+    ) -> Result<AppliedState, Error> {
+        let resp = self
+            .put_json(
+                &format!("lights/{}/state", light.id),
+                &OnOffReq {
+                    on: state,
+                    bri: state.then(|| self.remembered_brightness(light)).flatten(),
+                    transition_time: self.transition(transition_time),
+                },
+            )
+            .await?;
+
+        AppliedState::from_response(&resp)
+    }
+
+    async fn set_light_color_with_transition(
+        &self,
+        light: &Light,
+        hue: Option<u16>,
+        bri: Option<u8>,
+        sat: Option<u8>,
+        transition_time: Option<u16>,
+    ) -> Result<AppliedState, Error> {
+        check_color_ranges(bri, sat)?;
+        let resp = self
+            .put_json(
+                &format!("lights/{}/state", light.id),
+                &ColorChangeReq {
+                    hue,
+                    bri,
+                    sat,
+                    transition_time: self.transition(transition_time),
+                },
+            )
+            .await?;
+
+        AppliedState::from_response(&resp)
+    }
+
+    async fn set_color_temp(&self, light: &Light, ct: u16) -> Result<(), Error> {
+        #[derive(Serialize)]
+        struct ColorTempReq {
+            ct: u16,
+            #[serde(rename = "transitiontime", skip_serializing_if = "Option::is_none")]
+            transition_time: Option<u16>,
+        }
+
+        let resp = self
+            .put_json(
+                &format!("lights/{}/state", light.id),
+                &ColorTempReq {
+                    ct,
+                    transition_time: self.transition(None),
+                },
+            )
+            .await;
+        map_unsupported(resp, || {
+            format!("{} does not support color temperature", light.name)
+        })?;
+
+        Ok(())
+    }
+
+    async fn set_light_xy(&self, light: &Light, x: f32, y: f32) -> Result<(), Error> {
+        let (x, y) = match &light.capabilities.gamut {
+            Some(gamut) => gamut.clamp_to_gamut(x, y),
+            None => (x, y),
+        };
+
+        #[derive(Serialize)]
+        struct XyReq {
+            xy: [f32; 2],
+            #[serde(rename = "transitiontime", skip_serializing_if = "Option::is_none")]
+            transition_time: Option<u16>,
+        }
+
+        let resp = self
+            .put_json(
+                &format!("lights/{}/state", light.id),
+                &XyReq {
+                    xy: [x, y],
+                    transition_time: self.transition(None),
+                },
+            )
+            .await;
+        map_unsupported(resp, || format!("{} does not support colors", light.name))?;
+
+        Ok(())
+    }
+
+    async fn get_light_state(&self, light: &Light) -> Result<LightState, Error> {
+        #[derive(Deserialize)]
+        struct OuterLightState {
+            state: LightState,
+        }
+
+        let state = self
+            .get_json::<OuterLightState>(&format!("lights/{}", light.id))
+            .await?;
+        if let Some(memory) = &self.brightness {
+            memory.record(&light.id, state.state.bri);
+        }
+
+        Ok(state.state)
+    }
+
+    async fn get_light_reachability(&self) -> Result<HashMap<LightId, bool>, Error> {
+        #[derive(Deserialize)]
+        struct ReachableState {
+            #[serde(default)]
+            reachable: bool,
+        }
+        #[derive(Deserialize)]
+        struct LightWithState {
+            state: ReachableState,
+        }
+
+        let lights = self
+            .get_json::<HashMap<String, LightWithState>>("lights")
+            .await?;
+
+        Ok(lights
+            .into_iter()
+            .map(|(id, light)| (LightId(id), light.state.reachable))
+            .collect())
+    }
+
+    async fn step_brightness(&self, light: &Light, delta: i16) -> Result<(), Error> {
+        // bri_inc was added in api version 1.7
+        self.require_api_version(semver::Version::new(1, 7, 0), "Stepping the brightness")
+            .await?;
+
+        #[derive(Serialize)]
+        struct BrightnessStepReq {
+            bri_inc: i16,
+            #[serde(rename = "transitiontime", skip_serializing_if = "Option::is_none")]
+            transition_time: Option<u16>,
+        }
+
+        let resp = self
+            .put_json(
+                &format!("lights/{}/state", light.id),
+                &BrightnessStepReq {
+                    bri_inc: delta,
+                    transition_time: self.transition(None),
+                },
+            )
+            .await;
+        map_unsupported(resp, || format!("{} is not dimmable", light.name))?;
+
+        Ok(())
+    }
+
+    async fn step_hue(&self, light: &Light, delta: i32) -> Result<(), Error> {
+        // hue_inc came with bri_inc in api version 1.7
+        self.require_api_version(semver::Version::new(1, 7, 0), "Stepping the hue")
+            .await?;
+
+        #[derive(Serialize)]
+        struct HueStepReq {
+            hue_inc: i32,
+            #[serde(rename = "transitiontime", skip_serializing_if = "Option::is_none")]
+            transition_time: Option<u16>,
+        }
+
+        let resp = self
+            .put_json(
+                &format!("lights/{}/state", light.id),
+                &HueStepReq {
+                    hue_inc: delta,
+                    transition_time: self.transition(None),
+                },
+            )
+            .await;
+        map_unsupported(resp, || format!("{} does not support colors", light.name))?;
+
+        Ok(())
+    }
+
+    async fn step_saturation(&self, light: &Light, delta: i16) -> Result<(), Error> {
+        // sat_inc came with bri_inc in api version 1.7
+        self.require_api_version(semver::Version::new(1, 7, 0), "Stepping the saturation")
+            .await?;
+
+        #[derive(Serialize)]
+        struct SaturationStepReq {
+            sat_inc: i16,
+            #[serde(rename = "transitiontime", skip_serializing_if = "Option::is_none")]
+            transition_time: Option<u16>,
+        }
+
+        let resp = self
+            .put_json(
+                &format!("lights/{}/state", light.id),
+                &SaturationStepReq {
+                    sat_inc: delta,
+                    transition_time: self.transition(None),
+                },
+            )
+            .await;
+        map_unsupported(resp, || format!("{} does not support colors", light.name))?;
+
+        Ok(())
+    }
+
+    async fn alert(&self, light: &Light, mode: AlertMode) -> Result<(), Error> {
+        #[derive(Serialize)]
+        struct AlertReq {
+            alert: AlertMode,
+        }
+
+        self.put_json(
+            &format!("lights/{}/state", light.id),
+            &AlertReq { alert: mode },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_effect_with_speed(
+        &self,
+        light: &Light,
+        effect: Effect,
+        color_loop_speed: Option<u8>,
+    ) -> Result<(), Error> {
+        #[derive(Serialize)]
+        struct EffectReq {
+            effect: Effect,
+            #[serde(rename = "colorloopspeed", skip_serializing_if = "Option::is_none")]
+            color_loop_speed: Option<u8>,
+        }
+
+        let effects = &light.capabilities.effects;
+        if !effects.is_empty() && !effects.contains(&effect) {
+            return Err(Error::Unsupported(format!(
+                "{} does not support the effect {}",
+                light.name,
+                effect.as_str()
+            )));
+        }
+
+        let resp = self
+            .put_json(
+                &format!("lights/{}/state", light.id),
+                &EffectReq {
+                    effect,
+                    color_loop_speed,
+                },
+            )
+            .await;
+        map_unsupported(resp, || format!("{} does not support effects", light.name))?;
+
+        Ok(())
+    }
+
+    async fn set_speed(&self, light: &Light, speed: u8) -> Result<(), Error> {
+        #[derive(Serialize)]
+        struct SpeedReq {
+            speed: u8,
+        }
+
+        let resp = self
+            .put_json(&format!("lights/{}/state", light.id), &SpeedReq { speed })
+            .await;
+        map_unsupported(resp, || format!("{} does not support a speed", light.name))?;
+
+        Ok(())
+    }
+
+    async fn set_power_on_behavior(
+        &self,
+        light: &Light,
+        behavior: PowerOnBehavior,
+    ) -> Result<(), Error> {
+        let resp = self
+            .put_json(
+                &format!("lights/{}/config", light.id),
+                &behavior.to_config(),
+            )
+            .await;
+        map_unsupported(resp, || {
+            format!(
+                "{} does not support configuring its power on state",
+                light.name
+            )
+        })?;
+
+        Ok(())
+    }
+
+    async fn set_light_name(&self, light: &Light, name: &str) -> Result<(), Error> {
+        self.put_json(&format!("lights/{}", light.id), &RenameReq { name })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_group_name(&self, group: &Group, name: &str) -> Result<(), Error> {
+        if group.id == Group::ALL_LIGHTS_ID {
+            return Err(Error::Unsupported(String::from(
+                "The group of all lights can't be renamed",
+            )));
+        }
+        self.put_json(&format!("groups/{}", group.id), &RenameReq { name })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn set_scene_name(&self, scene: &Scene, name: &str) -> Result<(), Error> {
+        self.put_json(
+            &format!("groups/{}/scenes/{}", scene.group_id, scene.id),
+            &RenameReq { name },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_group_list(&self) -> Result<Vec<Group>, Error> {
+        #[derive(Deserialize)]
+        struct GroupWithoutId {
+            name: String,
+            lights: Vec<String>,
+        }
+
+        let groups = self
+            .get_json::<HashMap<String, GroupWithoutId>>("groups")
+            .await?;
+
+        let mut groups = groups
+            .into_iter()
+            .map(|(id, group)| {
+                Ok(Group {
+                    id: id.parse().map_err(Error::IdParseError)?,
+                    lights: group.lights.into_iter().map(LightId).collect(),
+                    name: group.name,
+                })
+            })
+            .collect::<Result<Vec<Group>, Error>>()?;
+        groups.sort_by_key(|g| g.id);
+
+        Ok(groups)
+    }
+
+    async fn get_group_state(&self, group: &Group) -> Result<GroupState, Error> {
+        #[derive(Deserialize)]
+        struct OnState {
+            any_on: bool,
+            all_on: bool,
+        }
+        #[derive(Deserialize)]
+        struct GroupWithState {
+            action: LightState,
+            state: OnState,
+        }
+
+        let group = self
+            .get_json::<GroupWithState>(&format!("groups/{}", group.id))
+            .await?;
+
+        Ok(GroupState {
+            action: group.action,
+            any_on: group.state.any_on,
+            all_on: group.state.all_on,
+        })
+    }
+
+    async fn set_group_lights(&self, group: &Group, light_ids: &[LightId]) -> Result<(), Error> {
+        #[derive(Serialize)]
+        struct GroupLightsReq<'a> {
+            lights: &'a [LightId],
+        }
+
+        if group.id == Group::ALL_LIGHTS_ID {
+            return Err(Error::Unsupported(String::from(
+                "The group of all lights can't be changed",
+            )));
+        }
+        self.put_json(
+            &format!("groups/{}", group.id),
+            &GroupLightsReq { lights: light_ids },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn create_group(&self, name: &str) -> Result<Group, Error> {
+        #[derive(Serialize)]
+        struct CreateGroupReq<'a> {
+            name: &'a str,
+        }
+
+        let body = self.post_json("groups", &CreateGroupReq { name }).await?;
+        Ok(Group {
+            name: String::from(name),
+            id: parse_created_id(&body)?,
+            lights: vec![],
+        })
+    }
+
+    async fn delete_group(&self, group: &Group) -> Result<(), Error> {
+        if group.id == Group::ALL_LIGHTS_ID {
+            return Err(Error::Unsupported(String::from(
+                "The group of all lights can't be deleted",
+            )));
+        }
+        self.delete(&format!("groups/{}", group.id)).await?;
+        Ok(())
+    }
+
+    async fn set_group_on_state(&self, group: &Group, state: bool) -> Result<(), Error> {
+        self.put_json(
+            &format!("groups/{}/action", group.id),
+            &OnOffReq {
+                on: state,
+                bri: None,
+                transition_time: self.transition(None),
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_group_color(
+        &self,
+        group: &Group,
+        hue: Option<u16>,
+        bri: Option<u8>,
+        sat: Option<u8>,
+    ) -> Result<(), Error> {
+        check_color_ranges(bri, sat)?;
+        self.put_json(
+            &format!("groups/{}/action", group.id),
+            &ColorChangeReq {
+                hue,
+                bri,
+                sat,
+                transition_time: self.transition(None),
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_scenes(&self, group: &Group) -> Result<Vec<Scene>, Error> {
+        #[derive(Deserialize)]
+        struct SceneWithoutId {
+            name: String,
+            #[serde(rename = "transitiontime")]
+            transition_time: Option<u16>,
+        }
+
+        let scenes = self
+            .get_json::<HashMap<String, SceneWithoutId>>(&format!("groups/{}/scenes", group.id))
+            .await?;
+
+        let mut scenes = scenes
+            .into_iter()
+            .map(|(id, scene)| {
+                Ok(Scene {
+                    id: id.parse().map_err(Error::IdParseError)?,
+                    name: scene.name,
+                    group_id: group.id,
+                    transition_time: scene.transition_time,
+                })
+            })
+            .collect::<Result<Vec<Scene>, Error>>()?;
+        scenes.sort_by_key(|s| s.id);
+
+        Ok(scenes)
+    }
+
+    async fn recall_scene(&self, scene: &Scene) -> Result<(), Error> {
+        // Recalling takes no body
+        self.send(self.http.put(self.api_url(&format!(
+            "groups/{}/scenes/{}/recall",
+            scene.group_id, scene.id
+        ))))
+        .await?;
+
+        Ok(())
+    }
+
+    async fn create_scene(&self, group: &Group, name: &str) -> Result<Scene, Error> {
+        #[derive(Serialize)]
+        struct CreateSceneReq<'a> {
+            name: &'a str,
+        }
+
+        let body = self
+            .post_json(
+                &format!("groups/{}/scenes", group.id),
+                &CreateSceneReq { name },
+            )
+            .await;
+        let body = match body {
+            // The gateway has room for a limited number of scenes per group
+            Err(Error::ApiError { type_code: 402, .. }) => Err(Error::LimitReached(format!(
+                "{} can't have any more scenes, delete one first",
+                group.name
+            ))),
+            other => other,
+        }?;
+
+        Ok(Scene {
+            id: parse_created_id(&body)?,
+            name: String::from(name),
+            group_id: group.id,
+            transition_time: None,
+        })
+    }
+
+    async fn store_scene(&self, scene: &Scene) -> Result<(), Error> {
+        // Storing takes no body, the gateway reads the lights itself
+        self.send(self.http.put(self.api_url(&format!(
+            "groups/{}/scenes/{}/store",
+            scene.group_id, scene.id
+        ))))
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_scene_details(
+        &self,
+        scene: &Scene,
+    ) -> Result<Vec<(LightId, Option<LightState>)>, Error> {
+        /// A light as stored in a scene, the color comes as `x` and `y` instead of `xy`
+        #[derive(Deserialize)]
+        struct SceneLight {
+            id: LightId,
+            on: bool,
+            bri: Option<u8>,
+            hue: Option<u16>,
+            sat: Option<u8>,
+            ct: Option<u16>,
+            x: Option<f32>,
+            y: Option<f32>,
+            #[serde(rename = "colormode")]
+            color_mode: Option<ColorMode>,
+        }
+        #[derive(Deserialize)]
+        struct SceneDetails {
+            lights: Vec<SceneLight>,
+        }
+        #[derive(Deserialize)]
+        struct GroupLights {
+            lights: Vec<LightId>,
+        }
+
+        let (details, group) = future::try_join(
+            self.get_json::<SceneDetails>(&format!(
+                "groups/{}/scenes/{}",
+                scene.group_id, scene.id
+            )),
+            self.get_json::<GroupLights>(&format!("groups/{}", scene.group_id)),
+        )
+        .await?;
+
+        let mut stored: HashMap<LightId, LightState> = details
+            .lights
+            .into_iter()
+            .map(|light| {
+                let mut state = LightState::builder().on(light.on).build();
+                state.bri = light.bri;
+                state.hue = light.hue;
+                state.sat = light.sat;
+                state.ct = light.ct;
+                state.xy = light.x.zip(light.y).map(|(x, y)| [x, y]);
+                state.color_mode = light.color_mode;
+                (light.id, state)
+            })
+            .collect();
+
+        let mut lights: Vec<(LightId, Option<LightState>)> = group
+            .lights
+            .into_iter()
+            .map(|id| {
+                let state = stored.remove(&id);
+                (id, state)
+            })
+            .collect();
+        let mut removed: Vec<_> = stored.into_iter().map(|(id, s)| (id, Some(s))).collect();
+        removed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        lights.extend(removed);
+
+        Ok(lights)
+    }
+
+    async fn get_sensors(&self) -> Result<Vec<Sensor>, Error> {
+        sensors::parse_sensors(self.get_json("sensors").await?)
+    }
+
+    async fn get_schedules(&self) -> Result<Vec<Schedule>, Error> {
+        schedules::parse_schedules(self.get_json("schedules").await?, &self.username)
+    }
+
+    async fn create_schedule(&self, schedule: &NewSchedule) -> Result<u32, Error> {
+        let body = self
+            .post_json(
+                "schedules",
+                &schedules::CreateScheduleReq::new(schedule, &self.username),
+            )
+            .await?;
+        parse_created_id(&body)
+    }
+
+    async fn delete_schedule(&self, schedule: &Schedule) -> Result<(), Error> {
+        self.delete(&format!("schedules/{}", schedule.id)).await?;
+        Ok(())
+    }
+
+    async fn get_rules(&self) -> Result<Vec<Rule>, Error> {
+        rules::parse_rules(self.get_json("rules").await?)
+    }
+
+    async fn set_rule_enabled(&self, rule: &Rule, enabled: bool) -> Result<(), Error> {
+        #[derive(Serialize)]
+        struct RuleStatusReq {
+            status: &'static str,
+        }
+
+        let status = if enabled { "enabled" } else { "disabled" };
+        self.put_json(&format!("rules/{}", rule.id), &RuleStatusReq { status })
+            .await?;
+        Ok(())
+    }
+
+    fn subscribe_events(&self) -> impl Stream<Item = Result<LightEvent, Error>> + 'static {
+        events::subscribe(self)
+    }
+
+    fn watch_connection_state(&self) -> tokio::sync::watch::Receiver<ConnectionState> {
+        self.subscribe_connection_state()
+    }
+
+    fn subscribe_sensor_events(&self) -> impl Stream<Item = Result<SensorEvent, Error>> + 'static {
+        events::subscribe_sensors(self)
+    }
+}
+
+impl DeconzClient {
+    /// Builds the url of an endpoint below `api/<username>/`
+    fn api_url(&self, path: &str) -> Url {
+        self.url
+            .join(&format!("api/{}/{}", self.username, path))
+            .unwrap()
+    }
+
+    /// Sends a request and reads the body of the response
+    ///
+    /// Transient failures of GET (and, if configured, PUT) requests are retried with
+    /// exponential backoff according to the retry policy of the client.
+    async fn send(&self, req: reqwest::RequestBuilder) -> Result<String, Error> {
+        let request = req.build().map_err(Error::HttpError)?;
+        if self.dry_run && request.method() != Method::GET {
+            return Ok(self.skip_request(&request));
+        }
+
+        let retries = match *request.method() {
+            Method::GET => self.retry.retries,
+            Method::PUT if self.retry.retry_puts => self.retry.retries,
+            _ => 0,
+        };
+
+        let mut attempt = 0;
+        loop {
+            // Requests with a streamed body can't be repeated
+            let Some(next) = request.try_clone().filter(|_| attempt < retries) else {
+                let result = self.send_once(request).await;
+                return self.track_connection(result);
+            };
+
+            match self.send_once(next).await {
+                Err(e) if is_transient(&e) => {
+                    let delay = backoff(attempt);
+                    warn!(attempt, ?delay, "Transient error, retrying: {:?}", e);
+                    self.set_connection_state(ConnectionState::Reconnecting);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return self.track_connection(result),
+            }
+        }
+    }
+
+    async fn send_once(&self, request: reqwest::Request) -> Result<String, Error> {
+        let method = request.method().clone();
+        // The token is part of the path and shouldn't end up in logs
+        let path = request.url().path().replacen(&self.username, "<token>", 1);
+        debug!(%method, %path, "Sending request");
+
+        let started = self.request_started();
+        // Only copied for the hook, so requests without one don't pay for it
+        let sent_body = started
+            .and(request.body())
+            .and_then(|body| body.as_bytes())
+            .map(|body| String::from_utf8_lossy(body).into_owned());
+        let resp = match self.http.execute(request).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                warn!(%method, %path, "Request failed: {}", e);
+                self.request_finished(started, &method, &path, None, sent_body);
+                return Err(Error::HttpError(e));
+            }
+        };
+
+        let status = resp.status();
+        if status.is_success() {
+            debug!(%method, %path, %status, "Received response");
+        } else {
+            warn!(%method, %path, %status, "Gateway answered with an error status");
+        }
+        let body = read_body(resp).await;
+        self.request_finished(started, &method, &path, Some(status), sent_body);
+        body
+    }
+
+    /// The transition of a state change, the client's default if the call doesn't set one
+    fn transition(&self, transition_time: Option<u16>) -> Option<u16> {
+        transition_time.or(self.default_transition)
+    }
+
+    /// GETs an endpoint below `api/<username>/` and parses the response
+    async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let body = self.send(self.http.get(self.api_url(path))).await?;
+        serde_json::from_str(&body).map_err(|e| Error::ResponseParseError(e.to_string()))
+    }
+
+    /// PUTs a json body to an endpoint below `api/<username>/` and returns the raw response
+    async fn put_json<B: Serialize>(&self, path: &str, body: &B) -> Result<String, Error> {
+        let result = self
+            .send(self.http.put(self.api_url(path)).json(body))
+            .await;
+        if let Some(queue) = &self.offline_queue {
+            queue.track(path, body, &result);
+        }
+        if let (Some(memory), Ok(resp)) = (&self.brightness, &result) {
+            memory.record_response(path, resp);
+        }
+        result
+    }
+
+    /// POSTs a json body to an endpoint below `api/<username>/` and returns the raw response
+    async fn post_json<B: Serialize>(&self, path: &str, body: &B) -> Result<String, Error> {
+        self.send(self.http.post(self.api_url(path)).json(body))
+            .await
+    }
+
+    /// DELETEs an endpoint below `api/<username>/` and returns the raw response
+    async fn delete(&self, path: &str) -> Result<String, Error> {
+        self.send(self.http.delete(self.api_url(path))).await
+    }
+
+    /// PUTs arbitrary json to the state of a light and returns the raw answer of the gateway
+    ///
+    /// **Advanced and unstable:** an escape hatch for state fields which aren't modelled yet,
+    /// e.g. those of newer firmware. The body isn't checked, prefer the typed methods where
+    /// they exist. This may change or go away once the typed api covers more of the state.
+    pub async fn set_raw_state(
+        &self,
+        light: &Light,
+        body: serde_json::Value,
+    ) -> Result<serde_json::Value, Error> {
+        let resp = self
+            .put_json(&format!("lights/{}/state", light.id), &body)
+            .await?;
+        serde_json::from_str(&resp).map_err(|e| Error::ResponseParseError(e.to_string()))
+    }
+
+    /// The changes waiting for the gateway to be reachable again, `None` unless enabled with
+    /// `DeconzClientBuilder::offline_queue`
+    pub fn offline_queue(&self) -> Option<&OfflineQueue> {
+        self.offline_queue.as_deref()
+    }
+
+    /// The last brightness other than 0 the client saw for the light, which `set_on_state`
+    /// turns it on with
+    ///
+    /// Always `None` unless enabled with `DeconzClientBuilder::restore_brightness`.
+    pub fn remembered_brightness(&self, light: &Light) -> Option<u8> {
+        self.brightness.as_ref()?.get(&light.id)
+    }
+
+    /// Revokes a token (username) so it can't be used for the api anymore
+    pub async fn delete_token(&self, token: &str) -> Result<(), Error> {
+        self.delete(&format!("config/whitelist/{}", token)).await?;
+        Ok(())
+    }
+
+    /// Revokes the token of this client
+    pub async fn logout(self) -> Result<(), Error> {
+        self.delete_token(&self.username).await
+    }
+
+    /// Checks whether the gateway accepts the token of this client
+    ///
+    /// Returns `Ok(false)` if the token is unknown to the gateway, errors are only returned if the
+    /// gateway couldn't be asked.
+    pub async fn validate_token(&self) -> Result<bool, Error> {
+        #[derive(Deserialize)]
+        struct AuthorizedConfig {
+            // Only part of the config if the token is authorized
+            whitelist: Option<serde::de::IgnoredAny>,
+        }
+
+        match self.get_json::<AuthorizedConfig>("config").await {
+            Ok(config) => Ok(config.whitelist.is_some()),
+            Err(Error::ApiError { type_code: 1, .. }) => Ok(false),
+            Err(Error::HttpError(e)) if e.status() == Some(StatusCode::FORBIDDEN) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns a builder to configure the http client before logging in
+    pub fn builder() -> DeconzClientBuilder {
+        DeconzClientBuilder::new()
+    }
+
+    /// Creates a new `DeconzClient` by logging in with via the link button
+    ///
+    /// The address is normalized with `gateway_url`, so `192.168.0.2` works as well.
+    #[deprecated(note = "use `pair_with_link_button`, which also tells which gateway was paired")]
+    pub async fn login_with_link_button<U: AsRef<str>>(
+        url: U,
+    ) -> Result<DeconzClient, crate::Error> {
+        DeconzClientBuilder::new().register(url).await
+    }
+
+    /// Logs in via the link button and reads which gateway was paired, see
+    /// `DeconzClientBuilder::pair_with_link_button`
+    ///
+    /// The address is normalized with `gateway_url`, so `192.168.0.2` works as well.
+    pub async fn pair_with_link_button<U: AsRef<str>>(url: U) -> Result<LoginResult, Error> {
+        DeconzClientBuilder::new().pair_with_link_button(url).await
+    }
+
+    /// Creates a new `DeconzClient` from an existing token aka. username
+    /// <div class="warning">This method does not validate the token</div>
+    pub fn login_with_token<U: AsRef<str>>(
+        url: U,
+        token: String,
+    ) -> Result<DeconzClient, crate::Error> {
+        DeconzClientBuilder::new().login_with_token(url, token)
+    }
+}
+
+/// Turns the address of a gateway into the base url of its api
+///
+/// `http://` is assumed if the address has no scheme, other schemes than http and https are
+/// rejected. A trailing slash is added so that a path (e.g. behind a reverse proxy) is kept
+/// when endpoints are joined onto the url. IPv6 addresses need brackets if they come with a port
+/// or scheme (`http://[::1]:8080`), a bare one like `fe80::1` is bracketed here.
+pub fn gateway_url(address: &str) -> Result<Url, Error> {
+    let address = address.trim();
+    let mut url = if address.contains("://") {
+        Url::parse(address)
+    } else if address.parse::<std::net::Ipv6Addr>().is_ok() {
+        Url::parse(&format!("http://[{}]", address))
+    } else {
+        Url::parse(&format!("http://{}", address))
+    }
+    .map_err(|e| Error::InvalidUrl(format!("{}: {}", address, e)))?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Error::InvalidUrl(format!(
+            "{}: only http and https are supported",
+            address
+        )));
+    }
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+
+    Ok(url)
+}
+
+/// The timeout used for requests to the deconz server if none is configured
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long idle connections to the deconz server are kept open if nothing else is configured
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// The name a client registers with at the gateway if nothing else is configured
+pub const DEFAULT_DEVICE_TYPE: &str = concat!("deconz-client/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone)]
+/// Configures the http client of a `DeconzClient`
+///
+/// A request that runs into the timeout fails with an `Error::HttpError` for which
+/// `reqwest::Error::is_timeout` returns true.
+pub struct DeconzClientBuilder {
+    timeout: Duration,
+    retry: RetryPolicy,
+    pool_max_idle_per_host: usize,
+    pool_idle_timeout: Option<Duration>,
+    accept_invalid_certs: bool,
+    /// Files with additional CA certificates, read when the client is built
+    root_certificates: Vec<PathBuf>,
+    device_type: String,
+    on_request: Option<metrics::RequestHook>,
+    default_transition: Option<u16>,
+    dry_run: bool,
+    offline_queue: bool,
+    restore_brightness: bool,
+}
+
+/// Which requests are repeated how often if they fail for a transient reason
+#[derive(Debug, Clone, Copy, Default)]
+struct RetryPolicy {
+    retries: u32,
+    retry_puts: bool,
+    /// How often a dropped event socket is reconnected in a row, `None` for no limit
+    event_reconnects: Option<u32>,
+}
+
+impl Default for DeconzClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeconzClientBuilder {
+    pub fn new() -> Self {
+        DeconzClientBuilder {
+            timeout: DEFAULT_TIMEOUT,
+            retry: RetryPolicy::default(),
+            pool_max_idle_per_host: MAX_CONCURRENT_REQUESTS,
+            pool_idle_timeout: Some(DEFAULT_POOL_IDLE_TIMEOUT),
+            accept_invalid_certs: false,
+            root_certificates: vec![],
+            device_type: String::from(DEFAULT_DEVICE_TYPE),
+            on_request: None,
+            default_transition: None,
+            dry_run: false,
+            offline_queue: false,
+            restore_brightness: false,
+        }
+    }
+
+    /// Sets the timeout for every request made by the client (default: 10 seconds)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retries GET requests up to `retries` times if the gateway is overloaded (503) or the
+    /// connection fails, waiting exponentially longer between attempts (default: 0)
+    ///
+    /// Other errors like 400, 403 or 404 are returned right away.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retry.retries = retries;
+        self
+    }
+
+    /// Also retries PUT requests, which change the state of lights (default: false)
+    ///
+    /// Only enable this if applying a change twice is fine, e.g. not for `step_brightness`.
+    pub fn retry_puts(mut self, retry_puts: bool) -> Self {
+        self.retry.retry_puts = retry_puts;
+        self
+    }
+
+    /// Limits how often the event streams (like `LightClient::subscribe_events`) try in a row to
+    /// reconnect a dropped websocket, `None` keeps trying (default: `None`)
+    ///
+    /// The attempts back off exponentially up to a few minutes apart. Once the limit is
+    /// reached the stream yields the last error and ends, with `Some(0)` it ends right away.
+    pub fn event_reconnects(mut self, max: Option<u32>) -> Self {
+        self.retry.event_reconnects = max;
+        self
+    }
+
+    /// Calls `hook` after every request the client sends, e.g. to log slow requests or to
+    /// collect latency metrics (default: none)
+    ///
+    /// Each retry counts as a request of its own. The hook runs on the task which sent the
+    /// request, so it should return quickly.
+    pub fn on_request<F: Fn(&RequestMetric) + Send + Sync + 'static>(mut self, hook: F) -> Self {
+        self.on_request = Some(metrics::RequestHook::new(hook));
+        self
+    }
+
+    /// Only logs requests which would change something (PUT, POST and DELETE) instead of
+    /// sending them, e.g. to try out an app without touching the lights (default: false)
+    ///
+    /// The methods return the answer the gateway would give if the change succeeded, so
+    /// `set_on_state` returns the requested values as `AppliedState` and creating a group or
+    /// schedule returns the id 0. Requests which only read are still sent. The hook set with
+    /// `on_request` sees the skipped requests with their body and without a status.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Keeps changes of a light's state which fail because the gateway can't be reached and
+    /// sends them once `DeconzClient::ping` succeeds again (default: false)
+    ///
+    /// Only the last state per light is sent, e.g. a light turned off and on again while the
+    /// gateway was gone is just turned on. The failing call still returns its error. Changes
+    /// by steps (like `step_brightness`) aren't kept, as they'd apply twice if the first
+    /// request got through after all.
+    ///
+    /// <div class="warning">
+    ///
+    /// The queued changes are sent however long the gateway was gone, so they may be stale by
+    /// then, e.g. turn on a light the user doesn't care about anymore. Call
+    /// `OfflineQueue::clear` if the user gave up on them.
+    ///
+    /// </div>
+    pub fn offline_queue(mut self, enabled: bool) -> Self {
+        self.offline_queue = enabled;
+        self
+    }
+
+    /// Turns lights on with the brightness they last had, as some bulbs come on at full
+    /// brightness otherwise (default: false)
+    ///
+    /// The client remembers the last brightness other than 0 it saw for each light, from
+    /// `get_light_state`, the answers to changes and `LightClient::subscribe_events`.
+    /// `set_on_state` then sends it along with `on`, until the client saw a brightness for a
+    /// light only `on` is sent.
+    pub fn restore_brightness(mut self, restore: bool) -> Self {
+        self.restore_brightness = restore;
+        self
+    }
+
+    /// Fades every change of the on state, color or brightness over `transition` unless the
+    /// call sets a transition itself (default: none, the gateway's own default of 400 ms)
+    ///
+    /// The gateway counts in 1/10 seconds, so `transition` is rounded down to those.
+    pub fn default_transition(mut self, transition: Duration) -> Self {
+        let tenths = transition.as_millis() / 100;
+        self.default_transition = Some(tenths.min(u16::MAX as u128) as u16);
+        self
+    }
+
+    /// Sets how many idle connections to the gateway are kept open for reuse
+    /// (default: `MAX_CONCURRENT_REQUESTS`)
+    ///
+    /// All requests of a client (and its clones) share one connection pool, so rapid updates
+    /// like dragging a slider don't have to open a new connection for every request.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = max;
+        self
+    }
 
-        let resp = self
-            .http
-            .put(
-                self.url
-                    .join(&format!("api/{}/lights/{}/state", self.username, light.id))
-                    .unwrap(),
-            )
-            .json(&OnOffReq { on: state })
-            .send()
-            .await;
-        let _ = resp
-            .and_then(|r| r.error_for_status())
-            .map_err(|e| Error::HttpError(e))?;
+    /// Sets how long an idle connection is kept open, `None` keeps it until the gateway
+    /// closes it (default: 90 seconds)
+    pub fn pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
 
-        Ok(())
+    /// Accepts any certificate of an https gateway, including self-signed and expired ones
+    /// (default: false)
+    ///
+    /// <div class="warning">
+    ///
+    /// This disables the protection https offers against someone impersonating the gateway,
+    /// which could then read the token. Prefer `add_root_certificate` with the certificate of
+    /// the gateway or its reverse proxy.
+    ///
+    /// </div>
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
     }
 
-    async fn set_light_color(
-        &self,
-        light: &Light,
-        hue: Option<u16>,
-        bri: Option<u8>,
-        sat: Option<u8>,
-    ) -> Result<(), Error> {
-        #[derive(Serialize)]
-        struct ColorChangeReq {
-            hue: Option<u16>,
-            bri: Option<u8>,
-            sat: Option<u8>,
-        }
+    /// Trusts the CA certificate in the given PEM or DER file in addition to the system's ones
+    ///
+    /// The file is read when logging in, which fails with `Error::IoError` if it can't be read
+    /// and with `Error::HttpError` if it isn't a certificate.
+    pub fn add_root_certificate<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.root_certificates.push(path.as_ref().to_owned());
+        self
+    }
 
-        self.http
-            .put(
-                self.url
-                    .join(&format!("api/{}/lights/{}/state", self.username, light.id))
-                    .unwrap(),
-            )
-            .json(&ColorChangeReq { hue, bri, sat })
-            .send()
-            .await
-            .and_then(|r| r.error_for_status())
-            .map_err(|e| Error::HttpError(e))?;
+    /// Sets the name the client registers with in `login_with_link_button`
+    /// (default: `DEFAULT_DEVICE_TYPE`)
+    ///
+    /// The gateway lists the clients allowed to use it by this name, so a distinct one like
+    /// `"my-app#kitchen-pc"` helps to find the right one to revoke later.
+    pub fn device_type<S: Into<String>>(mut self, device_type: S) -> Self {
+        self.device_type = device_type.into();
+        self
+    }
 
-        Ok(())
+    /// Reads the files added with `add_root_certificate`
+    fn read_root_certificates(&self) -> Result<Vec<Vec<u8>>, crate::Error> {
+        self.root_certificates
+            .iter()
+            .map(|path| std::fs::read(path).map_err(Error::IoError))
+            .collect()
     }
 
-    async fn get_light_state(&self, light: &Light) -> Result<LightState, Error> {
-        #[derive(Deserialize)]
-        struct OuterLightState {
-            state: LightState,
+    fn build_http(&self, certificates: &[Vec<u8>]) -> Result<reqwest::Client, crate::Error> {
+        let mut builder = reqwest::ClientBuilder::new()
+            .timeout(self.timeout)
+            // Gateways are often known by their `.local` name only
+            .dns_resolver(Arc::new(mdns::MdnsFallbackResolver))
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .danger_accept_invalid_certs(self.accept_invalid_certs);
+        for bytes in certificates {
+            let certificate = if bytes.starts_with(b"-----BEGIN") {
+                reqwest::Certificate::from_pem(bytes)
+            } else {
+                reqwest::Certificate::from_der(bytes)
+            };
+            builder = builder.add_root_certificate(certificate.map_err(Error::HttpError)?);
         }
+        builder.build().map_err(Error::HttpError)
+    }
 
-        println!("Loading light state for light id {}", light.id);
-        let state = self
-            .http
-            .get(
-                self.url
-                    .join(&format!("api/{}/lights/{}", self.username, light.id))
-                    .unwrap(),
-            )
-            .send()
-            .await
-            .and_then(|r| r.error_for_status())
-            .map_err(|e| Error::HttpError(e))?
-            .json::<OuterLightState>()
-            .await
-            .map_err(|e| Error::ResponseParseError(e.to_string()))?;
+    /// Creates a new `DeconzClient` by logging in with via the link button
+    #[deprecated(note = "use `pair_with_link_button`, which also tells which gateway was paired")]
+    pub async fn login_with_link_button<U: AsRef<str>>(
+        self,
+        url: U,
+    ) -> Result<DeconzClient, crate::Error> {
+        self.register(url).await
+    }
 
-        Ok(state.state)
+    /// Logs in via the link button and reads the config of the gateway right after, e.g. to
+    /// show which gateway was paired
+    ///
+    /// If the link button wasn't pressed, this fails with an `Error::ApiError` with type 101.
+    /// The config is read with the new token, so if that fails the token is lost and the
+    /// gateway has to be paired again.
+    pub async fn pair_with_link_button<U: AsRef<str>>(self, url: U) -> Result<LoginResult, Error> {
+        let client = self.register(url).await?;
+        let config = client.get_config().await?;
+
+        Ok(LoginResult {
+            gateway_name: config.name,
+            bridge_id: config.bridge_id,
+            client,
+        })
     }
-}
 
-impl DeconzClient {
-    /// Creates a new `DeconzClient` by logging in with via the link button
-    pub async fn login_with_link_button<U: IntoUrl>(url: U) -> Result<DeconzClient, crate::Error> {
-        let http = reqwest::ClientBuilder::new()
-            .build()
-            .map_err(|e| Error::HttpError(e))?;
+    /// Asks the gateway for a new token, which it only hands out while its link button is active
+    async fn register<U: AsRef<str>>(self, url: U) -> Result<DeconzClient, Error> {
+        let certificates = self.read_root_certificates()?;
+        let http = self.build_http(&certificates)?;
+        let websocket_tls = tls::websocket_config(self.accept_invalid_certs, &certificates)?;
 
         #[derive(Serialize)]
         struct LinkButtonLoginRequest {
             devicetype: String,
         }
 
-        let url = url.into_url().map_err(|e| crate::Error::HttpError(e))?;
+        let url = gateway_url(url.as_ref())?;
 
         let resp = http
             .post(url.join("api").unwrap())
             .json(&LinkButtonLoginRequest {
-                devicetype: String::from("deconz-rs"),
+                devicetype: self.device_type,
             })
             .send()
             .await
-            .and_then(|d| d.error_for_status())
-            .map_err(|e| crate::Error::HttpError(e))?;
+            .map_err(crate::Error::HttpError)?;
+
+        // If the link button wasn't pressed, this is an `Error::ApiError` with type 101
+        let body = read_body(resp).await?;
 
         #[derive(Deserialize)]
         struct Success {
@@ -206,12 +2612,8 @@ impl DeconzClient {
             success: Success,
         }
 
-        //println!("{}", resp.text().await.unwrap());
-
-        let resp = resp
-            .json::<[LinkButtonLoginResponse; 1]>()
-            .await
-            .map_err(|e| crate::Error::HttpError(e))?;
+        let resp = serde_json::from_str::<[LinkButtonLoginResponse; 1]>(&body)
+            .map_err(|e| Error::ResponseParseError(e.to_string()))?;
 
         let username = resp.into_iter().next().unwrap().success.username;
 
@@ -219,6 +2621,15 @@ impl DeconzClient {
             http,
             url,
             username,
+            retry: self.retry,
+            config: Arc::default(),
+            connection: Arc::default(),
+            on_request: self.on_request,
+            default_transition: self.default_transition,
+            dry_run: self.dry_run,
+            offline_queue: self.offline_queue.then(Arc::default),
+            brightness: self.restore_brightness.then(Arc::default),
+            websocket_tls,
         };
 
         Ok(c)
@@ -226,131 +2637,901 @@ impl DeconzClient {
 
     /// Creates a new `DeconzClient` from an existing token aka. username
     /// <div class="warning">This method does not validate the token</div>
-    pub fn login_with_token<U: IntoUrl>(
+    pub fn login_with_token<U: AsRef<str>>(
+        self,
         url: U,
         token: String,
     ) -> Result<DeconzClient, crate::Error> {
-        let http = reqwest::ClientBuilder::new()
-            .build()
-            .map_err(|e| Error::HttpError(e))?;
+        let certificates = self.read_root_certificates()?;
+        let http = self.build_http(&certificates)?;
+        let websocket_tls = tls::websocket_config(self.accept_invalid_certs, &certificates)?;
 
-        let url = url.into_url().map_err(|e| Error::HttpError(e))?;
+        let url = gateway_url(url.as_ref())?;
 
         let c = DeconzClient {
             http,
             url,
             username: token,
+            retry: self.retry,
+            config: Arc::default(),
+            connection: Arc::default(),
+            on_request: self.on_request,
+            default_transition: self.default_transition,
+            dry_run: self.dry_run,
+            offline_queue: self.offline_queue.then(Arc::default),
+            brightness: self.restore_brightness.then(Arc::default),
+            websocket_tls,
         };
 
         Ok(c)
     }
 }
 
-struct DemoLight {
-    light: Light,
-    state: bool,
+/// The error of the demo for a light it doesn't know, like `DeconzClient` returns it
+fn unknown_light(id: &LightId) -> Error {
+    Error::NotFound(format!("There is no light with id {}", id))
+}
+
+/// Mimics the gateway's limit on the length of names
+fn check_demo_name(address: &str, name: &str) -> Result<(), Error> {
+    if name.chars().count() > 32 {
+        return Err(Error::ApiError {
+            type_code: 7,
+            address: String::from(address),
+            description: format!("invalid value, {}, for parameter, name", name),
+        });
+    }
+    Ok(())
+}
+
+/// A demo scene which sets all lights of its group to the same color
+#[derive(Clone)]
+struct DemoScene {
+    scene: Scene,
     hue: u16,
     sat: u8,
     bri: u8,
 }
 
+/// A client which keeps the state of a few made up lights in memory, for trying the app without a gateway
 pub struct DemoLightClient {
-    lights: Mutex<Vec<DemoLight>>,
+    lights: Mutex<Vec<Light>>,
+    /// The current state of every demo light, keyed by light id
+    states: Mutex<HashMap<LightId, LightState>>,
+    groups: Mutex<Vec<Group>>,
+    scenes: Mutex<Vec<DemoScene>>,
+    /// Schedules are only stored, the demo never runs them
+    schedules: Mutex<Vec<Schedule>>,
+    /// Rules can only be enabled and disabled, the demo never runs them either
+    rules: Mutex<Vec<Rule>>,
+    /// Pushes the changes of the demo lights like the websocket of a gateway
+    events: tokio::sync::broadcast::Sender<LightEvent>,
 }
 
 impl DemoLightClient {
     pub fn new() -> Self {
         DemoLightClient {
             lights: Mutex::new(vec![
-                DemoLight {
-                    light: Light {
-                        name: String::from("Bathroom light"),
+                Light {
+                    name: String::from("Bathroom light"),
+                    id: LightId::from(1),
+                    capabilities: LightCapabilities {
+                        dimmable: true,
+                        color: true,
+                        color_temp: true,
+                        min_bri: 0,
+                        gradient: false,
+                        effects: vec![
+                            Effect::None,
+                            Effect::ColorLoop,
+                            Effect::Other(String::from("candle")),
+                        ],
+                        alerts: vec![AlertMode::None, AlertMode::Select, AlertMode::LSelect],
+                        gamut: None,
+                    },
+                    light_type: Some(LightType::ExtendedColorLight),
+                    model_id: Some(String::from("LCT015")),
+                    manufacturer: Some(String::from("Signify Netherlands B.V.")),
+                    last_seen: None,
+                    last_announced: None,
+                },
+                Light {
+                    name: String::from("Outside lighting"),
+                    id: LightId::from(2),
+                    capabilities: LightCapabilities {
+                        dimmable: true,
+                        color: false,
+                        color_temp: false,
+                        min_bri: 0,
+                        gradient: false,
+                        effects: vec![],
+                        alerts: vec![AlertMode::None, AlertMode::Select],
+                        gamut: None,
+                    },
+                    light_type: Some(LightType::DimmableLight),
+                    model_id: Some(String::from("FLS-PP3")),
+                    manufacturer: Some(String::from("dresden elektronik")),
+                    last_seen: None,
+                    last_announced: None,
+                },
+                Light {
+                    name: String::from("Studio lamp"),
+                    id: LightId::from(3),
+                    capabilities: LightCapabilities {
+                        dimmable: true,
+                        color: true,
+                        color_temp: false,
+                        // Mimic a bulb that turns off at low brightness
+                        min_bri: 25,
+                        gradient: false,
+                        effects: vec![Effect::None, Effect::ColorLoop],
+                        alerts: vec![AlertMode::None, AlertMode::Select, AlertMode::LSelect],
+                        gamut: None,
+                    },
+                    light_type: Some(LightType::ColorLight),
+                    model_id: Some(String::from("TRADFRI bulb E27 CWS opal 600lm")),
+                    manufacturer: Some(String::from("IKEA of Sweden")),
+                    last_seen: None,
+                    last_announced: None,
+                },
+            ]),
+            states: Mutex::new(HashMap::from([
+                (
+                    LightId::from(1),
+                    LightState {
+                        on: true,
+                        reachable: true,
+                        hue: Some(0),
+                        bri: Some(MAX_BRIGHTNESS),
+                        sat: Some(40),
+                        ct: Some(370),
+                        xy: Some([0.3, 0.3]),
+                        effect: Some(String::from("none")),
+                        color_mode: Some(ColorMode::Ct),
+                        speed: None,
+                    },
+                ),
+                (
+                    LightId::from(2),
+                    LightState {
+                        on: true,
+                        reachable: true,
+                        hue: None,
+                        bri: Some(30),
+                        sat: None,
+                        ct: None,
+                        xy: None,
+                        effect: None,
+                        color_mode: None,
+                        speed: None,
+                    },
+                ),
+                (
+                    LightId::from(3),
+                    LightState {
+                        on: true,
+                        reachable: true,
+                        hue: Some(4567),
+                        bri: Some(MAX_BRIGHTNESS),
+                        sat: Some(MAX_SATURATION),
+                        ct: None,
+                        xy: Some([0.6, 0.38]),
+                        effect: Some(String::from("none")),
+                        color_mode: Some(ColorMode::Hs),
+                        speed: None,
+                    },
+                ),
+            ])),
+            groups: Mutex::new(vec![
+                Group {
+                    name: String::from("Inside"),
+                    id: 1,
+                    lights: vec![LightId::from(1), LightId::from(3)],
+                },
+                Group {
+                    name: String::from("Everything"),
+                    id: 2,
+                    lights: vec![LightId::from(1), LightId::from(2), LightId::from(3)],
+                },
+            ]),
+            scenes: Mutex::new(vec![
+                DemoScene {
+                    scene: Scene {
                         id: 1,
+                        name: String::from("Bright"),
+                        group_id: 1,
+                        transition_time: None,
                     },
-                    state: true,
                     hue: 0,
-                    sat: 40,
+                    sat: 0,
                     bri: 255,
                 },
-                DemoLight {
-                    light: Light {
-                        name: String::from("Outside lighting"),
+                DemoScene {
+                    scene: Scene {
                         id: 2,
+                        name: String::from("Relax"),
+                        group_id: 1,
+                        transition_time: None,
                     },
-                    state: true,
-                    hue: 0,
-                    sat: 0,
-                    bri: 30,
+                    hue: 6000,
+                    sat: 200,
+                    bri: 80,
                 },
-                DemoLight {
-                    light: Light {
-                        name: String::from("Studio lamp"),
-                        id: 3,
+                DemoScene {
+                    scene: Scene {
+                        id: 1,
+                        name: String::from("Night"),
+                        group_id: 2,
+                        transition_time: None,
                     },
-                    state: true,
-                    hue: 4567,
+                    hue: 46000,
                     sat: 255,
-                    bri: 255,
+                    bri: 20,
                 },
             ]),
+            schedules: Mutex::new(vec![]),
+            rules: Mutex::new(vec![Rule {
+                id: 1,
+                name: String::from("Dim the living room at night"),
+                enabled: true,
+                conditions: vec![RuleCondition {
+                    address: String::from("/config/localtime"),
+                    operator: String::from("in"),
+                    value: Some(serde_json::Value::from("T22:00:00/T06:00:00")),
+                }],
+                actions: vec![RuleAction {
+                    address: String::from("/groups/1/action"),
+                    method: String::from("PUT"),
+                    body: serde_json::json!({ "bri": 40 }),
+                }],
+                times_triggered: 0,
+                last_triggered: None,
+            }]),
+            events: tokio::sync::broadcast::channel(64).0,
+        }
+    }
+}
+
+impl DemoLightClient {
+    /// Changes the stored state of a light, fails with `Error::NotFound` like the gateway if the
+    /// demo doesn't know the light
+    fn update_state(
+        &self,
+        light: &Light,
+        update: impl FnOnce(&mut LightState),
+    ) -> Result<(), Error> {
+        let mut states = self.states.lock().unwrap();
+        let state = states
+            .get_mut(&light.id)
+            .ok_or_else(|| unknown_light(&light.id))?;
+        let old = state.clone();
+        update(state);
+        self.notify(&light.id, &old, state);
+        Ok(())
+    }
+
+    /// Changes the stored state of all lights of a group
+    ///
+    /// Nothing is changed if the group has a light the demo doesn't know.
+    fn update_group_state(
+        &self,
+        group: &Group,
+        update: impl Fn(&mut LightState),
+    ) -> Result<(), Error> {
+        let mut states = self.states.lock().unwrap();
+        let ids: Vec<LightId> = if group.id == Group::ALL_LIGHTS_ID {
+            states.keys().cloned().collect()
+        } else {
+            group.lights.clone()
+        };
+        if let Some(unknown) = ids.iter().find(|id| !states.contains_key(*id)) {
+            return Err(unknown_light(unknown));
+        }
+        for id in ids {
+            let state = states.get_mut(&id).unwrap();
+            let old = state.clone();
+            update(state);
+            self.notify(&id, &old, state);
+        }
+        Ok(())
+    }
+
+    /// The stored group with `id`, or `Error::NotFound` if there is none
+    fn find_group(&self, id: u32) -> Result<Group, Error> {
+        self.groups
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|g| g.id == id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("There is no group with id {}", id)))
+    }
+
+    /// The stored demo scene of `scene`, or `Error::NotFound` if there is none
+    fn find_scene(&self, scene: &Scene) -> Result<DemoScene, Error> {
+        self.scenes
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|s| s.scene.group_id == scene.group_id && s.scene.id == scene.id)
+            .cloned()
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "There is no scene {} in group {}",
+                    scene.id, scene.group_id
+                ))
+            })
+    }
+
+    /// The color a demo scene stores for a group, demo scenes give all lights the same color so
+    /// the first light of the group decides
+    fn demo_scene_colors(&self, group_id: u32) -> (u16, u8, u8) {
+        let first = self
+            .groups
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|g| g.id == group_id)
+            .and_then(|g| g.lights.first().cloned());
+        let states = self.states.lock().unwrap();
+        let state = first.and_then(|id| states.get(&id));
+        (
+            state.and_then(|s| s.hue).unwrap_or_default(),
+            state.and_then(|s| s.sat).unwrap_or_default(),
+            state.and_then(|s| s.bri).unwrap_or(MAX_BRIGHTNESS),
+        )
+    }
+
+    /// Sends an event if the state of a light changed
+    fn notify(&self, id: &LightId, old: &LightState, new: &LightState) {
+        if let Some(change) = LightStateChange::between(old, new) {
+            // Fails only if nobody subscribed, which is fine
+            _ = self.events.send(LightEvent::StateChanged {
+                id: id.clone(),
+                change,
+            });
         }
     }
 }
 
+impl Default for DemoLightClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LightClient for DemoLightClient {
     async fn get_light_list(&self) -> Result<Vec<Light>, crate::Error> {
-        Ok(self.lights.lock().unwrap().iter().map(|l| l.light.clone()).collect())
+        Ok(self.lights.lock().unwrap().clone())
     }
 
-    async fn set_on_state(&self, light: &Light, state: bool) -> Result<(), Error> {
-        println!(
-            "Demo request triggered:\n    {} was set to {}",
+    async fn set_on_state_with_transition(
+        &self,
+        light: &Light,
+        state: bool,
+        transition_time: Option<u16>,
+    ) -> Result<AppliedState, Error> {
+        info!(
+            "Demo request: {} was set to {} (transition: {:?})",
             light.name,
-            if state { "on" } else { "off" }
+            if state { "on" } else { "off" },
+            transition_time
         );
-        let mut lights = self.lights.lock().unwrap();
-        let sel_light = lights.iter_mut().find(|l| l.light.id == light.id).unwrap();
-
-        sel_light.state = state;
-        Ok(())
+        self.update_state(light, |s| s.on = state)?;
+        Ok(AppliedState {
+            on: Some(state),
+            ..Default::default()
+        })
     }
 
-    async fn set_light_color(
+    async fn set_light_color_with_transition(
         &self,
         light: &Light,
         hue: Option<u16>,
         bri: Option<u8>,
         sat: Option<u8>,
+        transition_time: Option<u16>,
+    ) -> Result<AppliedState, Error> {
+        info!(
+            "Demo request: {} was set to color hue: {:?}, sat: {:?}, bri: {:?} (transition: {:?})",
+            light.name, hue, sat, bri, transition_time
+        );
+        check_color_ranges(bri, sat)?;
+
+        self.update_state(light, |s| {
+            s.hue = hue.or(s.hue);
+            s.sat = sat.or(s.sat);
+            s.bri = bri.or(s.bri);
+            if hue.is_some() || sat.is_some() {
+                s.color_mode = Some(ColorMode::Hs);
+            }
+        })?;
+        Ok(AppliedState {
+            hue,
+            bri,
+            sat,
+            ..Default::default()
+        })
+    }
+
+    async fn set_color_temp(&self, light: &Light, ct: u16) -> Result<(), Error> {
+        info!(
+            "Demo request: {} was set to color temperature {}",
+            light.name, ct
+        );
+
+        if !light.capabilities.color_temp {
+            return Err(Error::Unsupported(format!(
+                "{} does not support color temperature",
+                light.name
+            )));
+        }
+        self.update_state(light, |s| {
+            s.ct = Some(ct);
+            s.color_mode = Some(ColorMode::Ct);
+        })?;
+        Ok(())
+    }
+
+    async fn set_light_xy(&self, light: &Light, x: f32, y: f32) -> Result<(), Error> {
+        info!(
+            "Demo request: {} was set to color x: {}, y: {}",
+            light.name, x, y
+        );
+
+        if !light.capabilities.color {
+            return Err(Error::Unsupported(format!(
+                "{} does not support colors",
+                light.name
+            )));
+        }
+        self.update_state(light, |s| {
+            s.xy = Some([x, y]);
+            s.color_mode = Some(ColorMode::Xy);
+        })?;
+        Ok(())
+    }
+
+    async fn get_light_state(&self, light: &Light) -> Result<LightState, Error> {
+        self.states
+            .lock()
+            .unwrap()
+            .get(&light.id)
+            .cloned()
+            .ok_or_else(|| unknown_light(&light.id))
+    }
+
+    async fn get_light_reachability(&self) -> Result<HashMap<LightId, bool>, Error> {
+        let states = self.states.lock().unwrap();
+        Ok(states
+            .iter()
+            .map(|(id, s)| (id.clone(), s.reachable))
+            .collect())
+    }
+
+    async fn step_brightness(&self, light: &Light, delta: i16) -> Result<(), Error> {
+        info!(
+            "Demo request: {} brightness was stepped by {}",
+            light.name, delta
+        );
+
+        self.update_state(light, |s| {
+            let bri = s.bri.unwrap_or_default() as i16 + delta;
+            s.bri = Some(bri.clamp(0, MAX_BRIGHTNESS as i16) as u8);
+        })?;
+        Ok(())
+    }
+
+    async fn step_hue(&self, light: &Light, delta: i32) -> Result<(), Error> {
+        info!("Demo request: {} hue was stepped by {}", light.name, delta);
+
+        if !light.capabilities.color {
+            return Err(Error::Unsupported(format!(
+                "{} does not support colors",
+                light.name
+            )));
+        }
+        self.update_state(light, |s| {
+            let hue = s.hue.unwrap_or_default() as i32 + delta;
+            s.hue = Some(hue.rem_euclid(u16::MAX as i32 + 1) as u16);
+            s.color_mode = Some(ColorMode::Hs);
+        })?;
+        Ok(())
+    }
+
+    async fn step_saturation(&self, light: &Light, delta: i16) -> Result<(), Error> {
+        info!(
+            "Demo request: {} saturation was stepped by {}",
+            light.name, delta
+        );
+
+        if !light.capabilities.color {
+            return Err(Error::Unsupported(format!(
+                "{} does not support colors",
+                light.name
+            )));
+        }
+        self.update_state(light, |s| {
+            let sat = s.sat.unwrap_or_default() as i16 + delta;
+            s.sat = Some(sat.clamp(0, MAX_SATURATION as i16) as u8);
+            s.color_mode = Some(ColorMode::Hs);
+        })?;
+        Ok(())
+    }
+
+    async fn alert(&self, light: &Light, mode: AlertMode) -> Result<(), Error> {
+        info!("Demo request: {} alert was set to {:?}", light.name, mode);
+        Ok(())
+    }
+
+    async fn set_effect_with_speed(
+        &self,
+        light: &Light,
+        effect: Effect,
+        color_loop_speed: Option<u8>,
     ) -> Result<(), Error> {
-        println!(
-            "Demo request triggered:\n    {} was set to color hue: {:?}, sat: {:?}, bri: {:?}",
-            light.name, hue, sat, bri
+        info!(
+            "Demo request: {} was set to effect {:?} (speed {:?})",
+            light.name, effect, color_loop_speed
         );
 
-        let mut lights = self.lights.lock().unwrap();
-        let sel_light = lights.iter_mut().find(|l| l.light.id == light.id).unwrap();
+        if !light.capabilities.color {
+            return Err(Error::Unsupported(format!(
+                "{} does not support effects",
+                light.name
+            )));
+        }
+        self.update_state(light, |s| s.effect = Some(String::from(effect.as_str())))?;
+        Ok(())
+    }
+
+    async fn set_speed(&self, light: &Light, speed: u8) -> Result<(), Error> {
+        info!("Demo request: {} was set to speed {}", light.name, speed);
+
+        if !light.capabilities.gradient {
+            return Err(Error::Unsupported(format!(
+                "{} does not support a speed",
+                light.name
+            )));
+        }
+        self.update_state(light, |s| s.speed = Some(speed))?;
+        Ok(())
+    }
+
+    async fn set_power_on_behavior(
+        &self,
+        light: &Light,
+        behavior: PowerOnBehavior,
+    ) -> Result<(), Error> {
+        info!(
+            "Demo request: {} power on behavior was set to {:?}",
+            light.name, behavior
+        );
 
-        if let Some(hue) = hue{
-            sel_light.hue = hue;
+        // Mimic a bulb with an older firmware
+        if !light.capabilities.color {
+            return Err(Error::Unsupported(format!(
+                "{} does not support configuring its power on state",
+                light.name
+            )));
         }
-        if let Some(sat) = sat{
-            sel_light.sat = sat;
+        Ok(())
+    }
+
+    async fn set_light_name(&self, light: &Light, name: &str) -> Result<(), Error> {
+        info!("Demo request: {} was renamed to {}", light.name, name);
+        check_demo_name(&format!("/lights/{}", light.id), name)?;
+
+        let mut lights = self.lights.lock().unwrap();
+        let sel_light = lights
+            .iter_mut()
+            .find(|l| l.id == light.id)
+            .ok_or_else(|| unknown_light(&light.id))?;
+        sel_light.name = String::from(name);
+        Ok(())
+    }
+
+    async fn set_group_name(&self, group: &Group, name: &str) -> Result<(), Error> {
+        info!("Demo request: group {} was renamed to {}", group.name, name);
+        check_demo_name(&format!("/groups/{}", group.id), name)?;
+
+        let mut groups = self.groups.lock().unwrap();
+        if let Some(g) = groups.iter_mut().find(|g| g.id == group.id) {
+            g.name = String::from(name);
         }
-        if let Some(bri) = bri{
-            sel_light.bri = bri;
+        Ok(())
+    }
+
+    async fn set_scene_name(&self, scene: &Scene, name: &str) -> Result<(), Error> {
+        info!("Demo request: scene {} was renamed to {}", scene.name, name);
+        let address = format!("/groups/{}/scenes/{}", scene.group_id, scene.id);
+        check_demo_name(&address, name)?;
+
+        let mut scenes = self.scenes.lock().unwrap();
+        let demo_scene = scenes
+            .iter_mut()
+            .find(|s| s.scene.group_id == scene.group_id && s.scene.id == scene.id);
+        if let Some(demo_scene) = demo_scene {
+            demo_scene.scene.name = String::from(name);
         }
         Ok(())
     }
+    async fn get_group_list(&self) -> Result<Vec<Group>, Error> {
+        Ok(self.groups.lock().unwrap().clone())
+    }
 
-    async fn get_light_state(&self, light: &Light) -> Result<LightState, Error> {
-        let lights = self.lights.lock().unwrap();
-        let light = lights.iter().find(|l| l.light.id == light.id).unwrap();
-        Ok(LightState {
-            on: light.state,
-            reachable: true,
-            hue: Some(light.hue),
-            bri: Some(light.bri),
-            sat: Some(light.sat),
+    async fn get_group_state(&self, group: &Group) -> Result<GroupState, Error> {
+        let states = self.states.lock().unwrap();
+        let members: Vec<&LightState> = if group.id == Group::ALL_LIGHTS_ID {
+            states.values().collect()
+        } else {
+            group
+                .lights
+                .iter()
+                .filter_map(|id| states.get(id))
+                .collect()
+        };
+
+        // The demo doesn't remember what was sent to the group, the first light stands in for it
+        let action = match members.first() {
+            Some(state) => LightState {
+                reachable: false,
+                ..(*state).clone()
+            },
+            None => LightState::builder().reachable(false).build(),
+        };
+        Ok(GroupState {
+            action,
+            any_on: members.iter().any(|state| state.on),
+            all_on: !members.is_empty() && members.iter().all(|state| state.on),
         })
     }
+
+    async fn set_group_on_state(&self, group: &Group, state: bool) -> Result<(), Error> {
+        info!(
+            "Demo request: group {} was set to {}",
+            group.name,
+            if state { "on" } else { "off" }
+        );
+
+        self.update_group_state(group, |s| s.on = state)?;
+        Ok(())
+    }
+
+    async fn set_group_lights(&self, group: &Group, light_ids: &[LightId]) -> Result<(), Error> {
+        info!(
+            "Demo request: lights of group {} were set to {:?}",
+            group.name, light_ids
+        );
+
+        let mut groups = self.groups.lock().unwrap();
+        let Some(stored) = groups.iter_mut().find(|g| g.id == group.id) else {
+            return Err(Error::Unsupported(format!(
+                "The group {} can't be changed",
+                group.name
+            )));
+        };
+        stored.lights = light_ids.to_vec();
+        Ok(())
+    }
+
+    async fn create_group(&self, name: &str) -> Result<Group, Error> {
+        info!("Demo request: group {} was created", name);
+
+        let mut groups = self.groups.lock().unwrap();
+        let group = Group {
+            name: String::from(name),
+            id: groups.iter().map(|g| g.id).max().unwrap_or_default() + 1,
+            lights: vec![],
+        };
+        groups.push(group.clone());
+        Ok(group)
+    }
+
+    async fn delete_group(&self, group: &Group) -> Result<(), Error> {
+        info!("Demo request: group {} was deleted", group.name);
+        self.groups.lock().unwrap().retain(|g| g.id != group.id);
+        // The gateway deletes the scenes of a group with it
+        self.scenes
+            .lock()
+            .unwrap()
+            .retain(|s| s.scene.group_id != group.id);
+        Ok(())
+    }
+
+    async fn set_group_color(
+        &self,
+        group: &Group,
+        hue: Option<u16>,
+        bri: Option<u8>,
+        sat: Option<u8>,
+    ) -> Result<(), Error> {
+        info!(
+            "Demo request: group {} was set to color hue: {:?}, sat: {:?}, bri: {:?}",
+            group.name, hue, sat, bri
+        );
+        check_color_ranges(bri, sat)?;
+
+        self.update_group_state(group, |s| {
+            s.bri = bri.or(s.bri);
+            // Lights without color only take the brightness
+            if s.hue.is_some() {
+                s.hue = hue.or(s.hue);
+                s.sat = sat.or(s.sat);
+                if hue.is_some() || sat.is_some() {
+                    s.color_mode = Some(ColorMode::Hs);
+                }
+            }
+        })?;
+        Ok(())
+    }
+    async fn get_scenes(&self, group: &Group) -> Result<Vec<Scene>, Error> {
+        Ok(self
+            .scenes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|s| s.scene.group_id == group.id)
+            .map(|s| s.scene.clone())
+            .collect())
+    }
+
+    async fn recall_scene(&self, scene: &Scene) -> Result<(), Error> {
+        info!("Demo request: scene {} was recalled", scene.name);
+
+        let demo_scene = self.find_scene(scene)?;
+        let group = self.find_group(scene.group_id)?;
+
+        self.update_group_state(&group, |s| {
+            s.on = true;
+            s.bri = Some(demo_scene.bri);
+            if s.hue.is_some() {
+                s.hue = Some(demo_scene.hue);
+                s.sat = Some(demo_scene.sat);
+                s.color_mode = Some(ColorMode::Hs);
+            }
+        })?;
+        Ok(())
+    }
+
+    async fn create_scene(&self, group: &Group, name: &str) -> Result<Scene, Error> {
+        info!("Demo request: scene {} was created in {}", name, group.name);
+
+        let (hue, sat, bri) = self.demo_scene_colors(group.id);
+        let mut scenes = self.scenes.lock().unwrap();
+        let scene = Scene {
+            id: scenes
+                .iter()
+                .filter(|s| s.scene.group_id == group.id)
+                .map(|s| s.scene.id)
+                .max()
+                .unwrap_or_default()
+                + 1,
+            name: String::from(name),
+            group_id: group.id,
+            transition_time: None,
+        };
+        scenes.push(DemoScene {
+            scene: scene.clone(),
+            hue,
+            sat,
+            bri,
+        });
+        Ok(scene)
+    }
+
+    async fn store_scene(&self, scene: &Scene) -> Result<(), Error> {
+        info!("Demo request: scene {} was stored", scene.name);
+
+        let (hue, sat, bri) = self.demo_scene_colors(scene.group_id);
+        if let Some(stored) = self
+            .scenes
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|s| s.scene.group_id == scene.group_id && s.scene.id == scene.id)
+        {
+            stored.hue = hue;
+            stored.sat = sat;
+            stored.bri = bri;
+        }
+        Ok(())
+    }
+
+    async fn get_scene_details(
+        &self,
+        scene: &Scene,
+    ) -> Result<Vec<(LightId, Option<LightState>)>, Error> {
+        let demo_scene = self.find_scene(scene)?;
+        let lights = self.find_group(scene.group_id)?.lights;
+
+        // Like `recall_scene`, which colors only the lights that have a color
+        let states = self.states.lock().unwrap();
+        Ok(lights
+            .into_iter()
+            .map(|id| {
+                let mut state = LightState::builder().on(true).bri(demo_scene.bri).build();
+                if states.get(&id).is_some_and(|s| s.hue.is_some()) {
+                    state.hue = Some(demo_scene.hue);
+                    state.sat = Some(demo_scene.sat);
+                    state.color_mode = Some(ColorMode::Hs);
+                }
+                (id, Some(state))
+            })
+            .collect())
+    }
+
+    async fn get_sensors(&self) -> Result<Vec<Sensor>, Error> {
+        Ok(vec![
+            Sensor {
+                id: 1,
+                name: String::from("Living room thermometer"),
+                sensor_type: String::from("ZHATemperature"),
+                reading: SensorReading::Temperature(21.5),
+                last_seen: None,
+            },
+            Sensor {
+                id: 2,
+                name: String::from("Living room humidity"),
+                sensor_type: String::from("ZHAHumidity"),
+                reading: SensorReading::Humidity(45.0),
+                last_seen: None,
+            },
+        ])
+    }
+
+    async fn get_schedules(&self) -> Result<Vec<Schedule>, Error> {
+        Ok(self.schedules.lock().unwrap().clone())
+    }
+
+    async fn create_schedule(&self, schedule: &NewSchedule) -> Result<u32, Error> {
+        info!(
+            "Demo request: schedule {} was created for {}",
+            schedule.name, schedule.time
+        );
+
+        let mut schedules = self.schedules.lock().unwrap();
+        let id = schedules.iter().map(|s| s.id).max().unwrap_or_default() + 1;
+        schedules.push(Schedule {
+            id,
+            name: schedule.name.clone(),
+            description: schedule.description.clone(),
+            command: schedule.command.clone(),
+            time: schedule.time.clone(),
+            enabled: true,
+            autodelete: schedule.autodelete,
+        });
+        Ok(id)
+    }
+
+    async fn delete_schedule(&self, schedule: &Schedule) -> Result<(), Error> {
+        info!("Demo request: schedule {} was deleted", schedule.name);
+        self.schedules
+            .lock()
+            .unwrap()
+            .retain(|s| s.id != schedule.id);
+        Ok(())
+    }
+
+    async fn get_rules(&self) -> Result<Vec<Rule>, Error> {
+        Ok(self.rules.lock().unwrap().clone())
+    }
+
+    async fn set_rule_enabled(&self, rule: &Rule, enabled: bool) -> Result<(), Error> {
+        info!(
+            "Demo request: rule {} was {}",
+            rule.name,
+            if enabled { "enabled" } else { "disabled" }
+        );
+
+        let mut rules = self.rules.lock().unwrap();
+        if let Some(rule) = rules.iter_mut().find(|r| r.id == rule.id) {
+            rule.enabled = enabled;
+        }
+        Ok(())
+    }
+
+    fn subscribe_events(&self) -> impl Stream<Item = Result<LightEvent, Error>> + 'static {
+        events::receive(self.events.subscribe())
+    }
 }
@@ -0,0 +1,119 @@
+use std::{convert::Infallible, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// The deconz type of a light (its `type` field), which tells what the light can do
+///
+/// Serializes as the string the gateway uses, types this crate doesn't know are kept as `Other`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum LightType {
+    /// Hue, saturation, xy and color temperature, e.g. most RGBW bulbs
+    ExtendedColorLight,
+    /// Hue, saturation and xy but no color temperature
+    ColorLight,
+    /// White light with an adjustable color temperature
+    ColorTemperatureLight,
+    DimmableLight,
+    DimmablePlugInUnit,
+    OnOffLight,
+    OnOffPlugInUnit,
+    /// A switched output, e.g. of a relay
+    OnOffOutput,
+    SmartPlug,
+    Other(String),
+}
+
+impl LightType {
+    /// The type as the gateway names it, e.g. `Extended color light`
+    pub fn as_str(&self) -> &str {
+        match self {
+            LightType::ExtendedColorLight => "Extended color light",
+            LightType::ColorLight => "Color light",
+            LightType::ColorTemperatureLight => "Color temperature light",
+            LightType::DimmableLight => "Dimmable light",
+            LightType::DimmablePlugInUnit => "Dimmable plug-in unit",
+            LightType::OnOffLight => "On/Off light",
+            LightType::OnOffPlugInUnit => "On/Off plug-in unit",
+            LightType::OnOffOutput => "On/Off output",
+            LightType::SmartPlug => "Smart plug",
+            LightType::Other(name) => name,
+        }
+    }
+
+    /// Whether lights of this type can show colors (hue and saturation or xy)
+    pub fn supports_color(&self) -> bool {
+        matches!(self, LightType::ExtendedColorLight | LightType::ColorLight)
+    }
+
+    /// Whether lights of this type have an adjustable color temperature
+    pub fn supports_ct(&self) -> bool {
+        matches!(
+            self,
+            LightType::ExtendedColorLight | LightType::ColorTemperatureLight
+        )
+    }
+
+    /// Whether lights of this type can be dimmed
+    pub fn is_dimmable(&self) -> bool {
+        self.supports_color()
+            || self.supports_ct()
+            || matches!(
+                self,
+                LightType::DimmableLight | LightType::DimmablePlugInUnit
+            )
+    }
+
+    /// Whether this is a plug rather than a lamp, e.g. to show a different icon
+    pub fn is_plug(&self) -> bool {
+        matches!(
+            self,
+            LightType::DimmablePlugInUnit | LightType::OnOffPlugInUnit | LightType::SmartPlug
+        )
+    }
+}
+
+impl FromStr for LightType {
+    type Err = Infallible;
+
+    /// Maps the known type names, any other name becomes `LightType::Other`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let light_type = match s {
+            "Extended color light" => LightType::ExtendedColorLight,
+            "Color light" => LightType::ColorLight,
+            "Color temperature light" => LightType::ColorTemperatureLight,
+            "Dimmable light" => LightType::DimmableLight,
+            "Dimmable plug-in unit" => LightType::DimmablePlugInUnit,
+            "On/Off light" => LightType::OnOffLight,
+            "On/Off plug-in unit" => LightType::OnOffPlugInUnit,
+            "On/Off output" => LightType::OnOffOutput,
+            "Smart plug" => LightType::SmartPlug,
+            other => LightType::Other(String::from(other)),
+        };
+        Ok(light_type)
+    }
+}
+
+impl From<String> for LightType {
+    fn from(s: String) -> Self {
+        match s.parse() {
+            Ok(light_type) => light_type,
+            Err(never) => match never {},
+        }
+    }
+}
+
+impl From<LightType> for String {
+    fn from(light_type: LightType) -> Self {
+        match light_type {
+            LightType::Other(name) => name,
+            known => String::from(known.as_str()),
+        }
+    }
+}
+
+impl fmt::Display for LightType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
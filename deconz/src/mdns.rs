@@ -0,0 +1,203 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use tokio::{net::UdpSocket, time::Instant};
+use tracing::debug;
+
+const MDNS_ADDRESS: &str = "224.0.0.251:5353";
+/// How long to wait for the owner of a `.local` name to answer
+const MDNS_WAIT: Duration = Duration::from_secs(2);
+
+/// Resolves host names with the resolver of the system and falls back to asking via mDNS for
+/// `.local` names, which the system only resolves if an mDNS service (like Avahi) runs
+#[derive(Debug, Default)]
+pub(crate) struct MdnsFallbackResolver;
+
+impl Resolve for MdnsFallbackResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = String::from(name.as_str());
+        Box::pin(async move {
+            let addresses = resolve_host(&host).await?;
+            // The port is replaced by the one of the url
+            let addrs: Addrs = Box::new(
+                addresses
+                    .into_iter()
+                    .map(|address| SocketAddr::new(address, 0)),
+            );
+            Ok(addrs)
+        })
+    }
+}
+
+/// Looks up the addresses of a host, see `MdnsFallbackResolver`
+pub(crate) async fn resolve_host(host: &str) -> io::Result<Vec<IpAddr>> {
+    let system = tokio::net::lookup_host((host, 0))
+        .await
+        .map(|addrs| addrs.map(|addr| addr.ip()).collect::<Vec<IpAddr>>());
+    match system {
+        Ok(addresses) if !addresses.is_empty() => Ok(addresses),
+        _ if is_local_name(host) => {
+            let addresses = query_mdns(host).await?;
+            debug!(host, ?addresses, "Resolved via mDNS");
+            if addresses.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("No device answered for {} via mDNS", host),
+                ));
+            }
+            Ok(addresses)
+        }
+        result => result,
+    }
+}
+
+/// Whether a host name is one of the local network, which only mDNS can resolve
+pub(crate) fn is_local_name(host: &str) -> bool {
+    host.trim_end_matches('.')
+        .to_ascii_lowercase()
+        .ends_with(".local")
+}
+
+/// Asks the local network for the IPv4 addresses of a `.local` name
+///
+/// The query is sent from an ordinary port, so responders answer it directly instead of to the
+/// multicast group (a "legacy unicast" query in RFC 6762).
+async fn query_mdns(host: &str) -> io::Result<Vec<IpAddr>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket
+        .send_to(&mdns_query(host.trim_end_matches('.')), MDNS_ADDRESS)
+        .await?;
+
+    let deadline = Instant::now() + MDNS_WAIT;
+    let mut buf = [0; 1500];
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, _) = received?;
+        let addresses = answered_addresses(&buf[..len]);
+        if !addresses.is_empty() {
+            return Ok(addresses);
+        }
+    }
+    Ok(vec![])
+}
+
+/// A DNS query for the A record of a name
+fn mdns_query(host: &str) -> Vec<u8> {
+    // Id 0, no flags, one question
+    let mut query = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in host.split('.') {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    // End of the name, type A, class IN
+    query.extend_from_slice(&[0, 0, 1, 0, 1]);
+    query
+}
+
+/// Reads the IPv4 addresses of the A records in the answer section of a DNS response
+fn answered_addresses(packet: &[u8]) -> Vec<IpAddr> {
+    let read_u16 = |pos: usize| -> Option<u16> {
+        Some(u16::from_be_bytes([
+            *packet.get(pos)?,
+            *packet.get(pos + 1)?,
+        ]))
+    };
+    let parse = || -> Option<Vec<IpAddr>> {
+        // Only responses count, other hosts' queries arrive as well
+        if packet.get(2)? & 0x80 == 0 {
+            return None;
+        }
+        let questions = read_u16(4)?;
+        let answers = read_u16(6)?;
+
+        let mut pos = 12;
+        for _ in 0..questions {
+            pos = skip_name(packet, pos)? + 4;
+        }
+        let mut addresses = vec![];
+        for _ in 0..answers {
+            pos = skip_name(packet, pos)?;
+            let record_type = read_u16(pos)?;
+            // The top bit of the class is the cache-flush flag of mDNS
+            let class = read_u16(pos + 2)? & 0x7fff;
+            let length = read_u16(pos + 8)? as usize;
+            let data = packet.get(pos + 10..pos + 10 + length)?;
+            if record_type == 1 && class == 1 && length == 4 {
+                addresses.push(IpAddr::V4(Ipv4Addr::new(
+                    data[0], data[1], data[2], data[3],
+                )));
+            }
+            pos += 10 + length;
+        }
+        Some(addresses)
+    };
+    parse().unwrap_or_default()
+}
+
+/// Returns the position after a name, which ends with an empty label or a pointer to another name
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let length = *packet.get(pos)?;
+        match length {
+            0 => return Some(pos + 1),
+            _ if length & 0xc0 == 0xc0 => return Some(pos + 2),
+            _ => pos += 1 + length as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The answer of a responder to a legacy unicast query for `deconz.local`, with the question
+    /// repeated, an AAAA record first and the names of the records compressed
+    #[rustfmt::skip]
+    const ANSWER: &[u8] = &[
+        // Id 0, response + authoritative, one question, two answers
+        0x00, 0x00, 0x84, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00,
+        // deconz.local, type A, class IN
+        6, b'd', b'e', b'c', b'o', b'n', b'z', 5, b'l', b'o', b'c', b'a', b'l', 0,
+        0x00, 0x01, 0x00, 0x01,
+        // Pointer to the name, type AAAA, class IN with cache-flush, ttl 120, fe80::1
+        0xc0, 0x0c, 0x00, 0x1c, 0x80, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x10,
+        0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01,
+        // Pointer to the name, type A, class IN with cache-flush, ttl 120, 192.168.1.20
+        0xc0, 0x0c, 0x00, 0x01, 0x80, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x04,
+        192, 168, 1, 20,
+    ];
+
+    #[test]
+    fn a_records_of_an_answer_are_read() {
+        assert_eq!(
+            answered_addresses(ANSWER),
+            [IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20))]
+        );
+    }
+
+    #[test]
+    fn queries_and_truncated_answers_have_no_addresses() {
+        assert!(answered_addresses(&mdns_query("deconz.local")).is_empty());
+        for len in 0..ANSWER.len() {
+            assert!(answered_addresses(&ANSWER[..len]).is_empty());
+        }
+    }
+
+    #[test]
+    fn names_end_with_an_empty_label_or_a_pointer() {
+        assert_eq!(skip_name(ANSWER, 12), Some(26));
+        assert_eq!(skip_name(ANSWER, 30), Some(32));
+        assert_eq!(skip_name(&ANSWER[..20], 12), None);
+    }
+
+    #[test]
+    fn query_asks_for_the_a_record() {
+        let query = mdns_query("deconz.local");
+        assert_eq!(&query[12..], &ANSWER[12..30]);
+        assert!(is_local_name("deconz.local.") && is_local_name("Deconz.LOCAL"));
+        assert!(!is_local_name("deconz.example.com"));
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use reqwest::{Method, StatusCode};
+
+use crate::DeconzClient;
+
+/// A request the client sent to the gateway, see `DeconzClientBuilder::on_request`
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RequestMetric {
+    pub method: Method,
+    /// The path with the token replaced by `<token>`, e.g. `/api/<token>/lights/1/state`
+    pub path: String,
+    /// What the gateway answered with, `None` if no answer arrived (e.g. a timeout) or the
+    /// request was skipped by a dry run
+    pub status: Option<StatusCode>,
+    /// The json body of the request, `None` for requests without one like GETs
+    pub body: Option<String>,
+    /// From sending the request until the whole answer was read
+    pub duration: Duration,
+}
+
+/// The hook set with `DeconzClientBuilder::on_request`, shared by all clones of a client
+#[derive(Clone)]
+pub(crate) struct RequestHook(Arc<dyn Fn(&RequestMetric) + Send + Sync>);
+
+impl RequestHook {
+    pub(crate) fn new<F: Fn(&RequestMetric) + Send + Sync + 'static>(hook: F) -> Self {
+        RequestHook(Arc::new(hook))
+    }
+}
+
+impl fmt::Debug for RequestHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RequestHook")
+    }
+}
+
+impl DeconzClient {
+    /// When a request starts, `None` if there is no hook so requests cost nothing extra
+    pub(crate) fn request_started(&self) -> Option<Instant> {
+        self.on_request.as_ref().map(|_| Instant::now())
+    }
+
+    /// Passes a finished request to the hook, if there is one
+    pub(crate) fn request_finished(
+        &self,
+        started: Option<Instant>,
+        method: &Method,
+        path: &str,
+        status: Option<StatusCode>,
+        body: Option<String>,
+    ) {
+        let (Some(hook), Some(started)) = (&self.on_request, started) else {
+            return;
+        };
+        (hook.0)(&RequestMetric {
+            method: method.clone(),
+            path: path.to_owned(),
+            status,
+            body,
+            duration: started.elapsed(),
+        });
+    }
+}
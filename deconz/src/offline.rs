@@ -0,0 +1,145 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+use crate::{connection::is_connection_error, DeconzClient, Error, LightId};
+
+/// Fields which set a light to an absolute value, so sending them late has the same effect
+const REPLAYABLE_FIELDS: &[&str] = &["on", "bri", "hue", "sat", "xy", "ct", "transitiontime"];
+
+/// The color mode a field sets, fields of different modes replace each other
+fn color_mode(field: &str) -> Option<&'static str> {
+    match field {
+        "hue" | "sat" => Some("hs"),
+        "xy" => Some("xy"),
+        "ct" => Some("ct"),
+        _ => None,
+    }
+}
+
+/// Combines a newer state change into an older one, the newer values win
+fn merge(older: &mut Map<String, Value>, newer: Map<String, Value>) {
+    for (field, value) in newer {
+        if let Some(mode) = color_mode(&field) {
+            older.retain(|f, _| color_mode(f).is_none_or(|m| m == mode));
+        }
+        older.insert(field, value);
+    }
+}
+
+/// Keeps the last desired state of each light whose changes couldn't reach the gateway, see
+/// `DeconzClientBuilder::offline_queue`
+///
+/// Changes for the same light are coalesced, so only the final state gets sent.
+#[derive(Debug, Default)]
+pub struct OfflineQueue {
+    pending: Mutex<HashMap<LightId, Map<String, Value>>>,
+}
+
+impl OfflineQueue {
+    /// The lights with changes waiting for the gateway
+    pub fn pending_lights(&self) -> Vec<LightId> {
+        let mut lights: Vec<LightId> = self.pending.lock().unwrap().keys().cloned().collect();
+        lights.sort();
+        lights
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.lock().unwrap().is_empty()
+    }
+
+    /// Drops the queued changes, e.g. once the user gave up on them
+    pub fn clear(&self) {
+        self.pending.lock().unwrap().clear();
+    }
+
+    /// Updates the queue from the outcome of a PUT request
+    ///
+    /// Changes of a light's state which failed to reach the gateway are queued, those which
+    /// reached it replace what was queued for the same fields.
+    pub(crate) fn track<B: Serialize>(&self, path: &str, body: &B, result: &Result<String, Error>) {
+        let Some(id) = path
+            .strip_prefix("lights/")
+            .and_then(|path| path.strip_suffix("/state"))
+        else {
+            return;
+        };
+        let Ok(Value::Object(fields)) = serde_json::to_value(body) else {
+            return;
+        };
+
+        match result {
+            Err(e) if is_connection_error(e) => {
+                // Relative changes like `bri_inc` would apply twice if the first one got through
+                if fields
+                    .keys()
+                    .all(|f| REPLAYABLE_FIELDS.contains(&f.as_str()))
+                {
+                    info!(light = id, "Gateway unreachable, queueing the change");
+                    self.push(LightId::from(id), fields);
+                }
+            }
+            Err(_) => {}
+            Ok(_) => self.forget(&LightId::from(id), &fields),
+        }
+    }
+
+    fn push(&self, id: LightId, fields: Map<String, Value>) {
+        let mut pending = self.pending.lock().unwrap();
+        merge(pending.entry(id).or_default(), fields);
+    }
+
+    /// Queues a change again which failed to replay, unless newer values were queued meanwhile
+    fn requeue(&self, id: LightId, mut fields: Map<String, Value>) {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(newer) = pending.remove(&id) {
+            merge(&mut fields, newer);
+        }
+        pending.insert(id, fields);
+    }
+
+    /// Drops the queued values which a change that reached the gateway replaced
+    fn forget(&self, id: &LightId, fields: &Map<String, Value>) {
+        let mut pending = self.pending.lock().unwrap();
+        let Some(queued) = pending.get_mut(id) else {
+            return;
+        };
+        for field in fields.keys() {
+            queued.remove(field);
+            if let Some(mode) = color_mode(field) {
+                queued.retain(|f, _| color_mode(f).is_none_or(|m| m == mode));
+            }
+        }
+        if queued.keys().all(|f| f == "transitiontime") {
+            pending.remove(id);
+        }
+    }
+
+    fn take(&self) -> HashMap<LightId, Map<String, Value>> {
+        std::mem::take(&mut self.pending.lock().unwrap())
+    }
+}
+
+impl DeconzClient {
+    /// Sends the changes which were queued while the gateway couldn't be reached
+    pub(crate) async fn replay_offline_queue(&self) {
+        let Some(queue) = &self.offline_queue else {
+            return;
+        };
+
+        for (id, fields) in queue.take() {
+            info!(light = %id, "Sending queued change");
+            let request = self
+                .http
+                .put(self.api_url(&format!("lights/{}/state", id)))
+                .json(&fields);
+            match self.send(request).await {
+                Ok(_) => {}
+                Err(e) if is_connection_error(&e) => queue.requeue(id, fields),
+                Err(e) => warn!(light = %id, "Failed to send queued change: {}", e),
+            }
+        }
+    }
+}
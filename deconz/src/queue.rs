@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{infer_color_mode, Error, Light, LightClient, LightId, LightStateChange};
+
+/// A color change for a light. Fields which are `None` are left unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ColorCommand {
+    pub hue: Option<u16>,
+    pub sat: Option<u8>,
+    pub bri: Option<u8>,
+    /// Color as CIE xy coordinates, sent instead of hue and saturation
+    pub xy: Option<[f32; 2]>,
+    /// Color temperature in mireds, replaces the other color values
+    pub ct: Option<u16>,
+    /// Transition time in 1/10 seconds
+    pub transition_time: Option<u16>,
+}
+
+impl ColorCommand {
+    /// Combines a newer command into this one, the newer values win
+    fn merge(&mut self, newer: ColorCommand) {
+        if newer.xy.is_some() {
+            self.xy = newer.xy;
+            self.hue = None;
+            self.sat = None;
+            self.ct = None;
+        }
+        if newer.hue.is_some() || newer.sat.is_some() {
+            self.xy = None;
+            self.ct = None;
+        }
+        if newer.ct.is_some() {
+            self.ct = newer.ct;
+            self.xy = None;
+            self.hue = None;
+            self.sat = None;
+        }
+        self.hue = newer.hue.or(self.hue);
+        self.sat = newer.sat.or(self.sat);
+        self.bri = newer.bri.or(self.bri);
+        self.transition_time = newer.transition_time.or(self.transition_time);
+    }
+
+    /// The change of a light's state this command makes once it was sent
+    pub fn as_state_change(&self) -> LightStateChange {
+        let color_mode = infer_color_mode(self.ct, self.xy, self.hue, self.sat);
+
+        LightStateChange {
+            hue: self.hue,
+            bri: self.bri,
+            sat: self.sat,
+            ct: self.ct,
+            xy: self.xy,
+            color_mode,
+            ..Default::default()
+        }
+    }
+}
+
+/// What became of a command passed to `LightCommandQueue::set_color`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandOutcome {
+    /// The command was sent and no newer one for the light was queued in the meantime
+    ///
+    /// Holds what was actually sent, which includes the older commands merged into it.
+    Sent(ColorCommand),
+    /// A newer command for the light was queued, so this one was merged into it or its
+    /// response is outdated
+    Superseded,
+}
+
+#[derive(Default)]
+struct QueueEntry {
+    /// Incremented for every queued command, the call holding the latest one sends
+    generation: u64,
+    pending: ColorCommand,
+    last_sent: Option<Instant>,
+    /// Held while sending, so the requests for a light reach the gateway in order
+    send_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// Coalesces bursts of color changes (e.g. from dragging a slider) so that at most one
+/// request per interval is sent for each light
+///
+/// Commands queued while waiting are merged and the last value of a burst always gets sent.
+pub struct LightCommandQueue {
+    interval: Duration,
+    entries: Mutex<HashMap<LightId, QueueEntry>>,
+}
+
+impl Default for LightCommandQueue {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100))
+    }
+}
+
+impl LightCommandQueue {
+    pub fn new(interval: Duration) -> Self {
+        LightCommandQueue {
+            interval,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Queues a color change for a light
+    ///
+    /// Resolves once the command was sent, or with `CommandOutcome::Superseded` as soon as a
+    /// newer command for the same light replaces it. Responses to superseded commands,
+    /// including errors, are dropped since the newer command determines the final state. If a
+    /// superseded command failed to send, the values the newer command doesn't replace are sent
+    /// along with it.
+    pub async fn set_color<C: LightClient>(
+        &self,
+        client: &C,
+        light: &Light,
+        command: ColorCommand,
+    ) -> Result<CommandOutcome, Error> {
+        let (generation, wait, send_lock) = {
+            let mut entries = self.entries.lock().unwrap();
+            let entry = entries.entry(light.id.clone()).or_default();
+            entry.generation += 1;
+            entry.pending.merge(command);
+
+            let wait = entry
+                .last_sent
+                .map(|t| (t + self.interval).saturating_duration_since(Instant::now()))
+                .unwrap_or_default();
+            (entry.generation, wait, entry.send_lock.clone())
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        let _sending = send_lock.lock().await;
+
+        let command = {
+            let mut entries = self.entries.lock().unwrap();
+            let entry = entries.entry(light.id.clone()).or_default();
+            if entry.generation != generation {
+                // A newer call will send the merged command
+                return Ok(CommandOutcome::Superseded);
+            }
+            entry.last_sent = Some(Instant::now());
+            std::mem::take(&mut entry.pending)
+        };
+
+        let result = Self::send(client, light, &command).await;
+
+        {
+            let mut entries = self.entries.lock().unwrap();
+            let entry = entries.get_mut(&light.id).unwrap();
+            if entry.generation != generation {
+                if result.is_err() {
+                    // The newer call waits for the send lock, so it hasn't taken the pending
+                    // command yet
+                    let mut unsent = command;
+                    unsent.merge(entry.pending);
+                    entry.pending = unsent;
+                }
+                return Ok(CommandOutcome::Superseded);
+            }
+        }
+        result.map(|()| CommandOutcome::Sent(command))
+    }
+
+    async fn send<C: LightClient>(
+        client: &C,
+        light: &Light,
+        command: &ColorCommand,
+    ) -> Result<(), Error> {
+        if let Some([x, y]) = command.xy {
+            client.set_light_xy(light, x, y).await?;
+        }
+        if let Some(ct) = command.ct {
+            client.set_color_temp(light, ct).await?;
+        }
+        if command.hue.is_some() || command.sat.is_some() || command.bri.is_some() {
+            client
+                .set_light_color_with_transition(
+                    light,
+                    command.hue,
+                    command.bri,
+                    command.sat,
+                    command.transition_time,
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
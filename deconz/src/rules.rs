@@ -0,0 +1,105 @@
+use std::{collections::HashMap, fmt};
+
+use serde::Deserialize;
+
+use crate::{Error, Timestamp};
+
+/// An automation stored on the gateway, e.g. turning on lights when a motion sensor fires
+///
+/// Rules are read-only apart from enabling and disabling them, see
+/// `LightClient::set_rule_enabled`. The conditions and actions are kept close to how the
+/// gateway describes them.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub id: u32,
+    pub name: String,
+    pub enabled: bool,
+    /// The rule runs its actions once all of them are met
+    pub conditions: Vec<RuleCondition>,
+    pub actions: Vec<RuleAction>,
+    /// How often the rule ran its actions
+    pub times_triggered: u32,
+    /// When the rule last ran its actions, `None` if it never did
+    pub last_triggered: Option<Timestamp>,
+}
+
+/// A condition of a rule, e.g. that `/sensors/5/state/presence` `eq` `true`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RuleCondition {
+    /// The resource attribute that is checked, e.g. `/sensors/5/state/presence`
+    pub address: String,
+    /// How it is checked, e.g. `eq`, `gt`, `dx` (changed) or `ddx` (changed a while ago)
+    pub operator: String,
+    /// What it is compared with, operators like `dx` have none
+    #[serde(default)]
+    pub value: Option<serde_json::Value>,
+}
+
+/// A request the gateway sends to itself when a rule triggers
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RuleAction {
+    /// The endpoint of the request, e.g. `/groups/1/action`
+    pub address: String,
+    /// The http method like `PUT`, or `BIND`/`UNBIND` for actions on zigbee bindings
+    pub method: String,
+    #[serde(default)]
+    pub body: serde_json::Value,
+}
+
+impl fmt::Display for RuleCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.address, self.operator)?;
+        match &self.value {
+            // Values are mostly strings like "true", which read better without quotes
+            Some(serde_json::Value::String(value)) => write!(f, " {}", value),
+            Some(value) => write!(f, " {}", value),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for RuleAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.method, self.address, self.body)
+    }
+}
+
+/// A rule as returned by `GET api/<user>/rules`, whose ids are the keys of the object
+#[derive(Deserialize)]
+pub(crate) struct RawRule {
+    name: String,
+    status: String,
+    #[serde(default)]
+    conditions: Vec<RuleCondition>,
+    #[serde(default)]
+    actions: Vec<RuleAction>,
+    #[serde(rename = "timestriggered", default)]
+    times_triggered: u32,
+    #[serde(rename = "lasttriggered", default)]
+    last_triggered: Option<String>,
+}
+
+/// Converts the rules object returned by `GET api/<user>/rules`, sorted by id
+pub(crate) fn parse_rules(rules: HashMap<String, RawRule>) -> Result<Vec<Rule>, Error> {
+    let mut rules = rules
+        .into_iter()
+        .map(|(id, rule)| {
+            Ok(Rule {
+                id: id.parse().map_err(Error::IdParseError)?,
+                name: rule.name,
+                enabled: rule.status != "disabled",
+                conditions: rule.conditions,
+                actions: rule.actions,
+                times_triggered: rule.times_triggered,
+                // The gateway writes "none" for rules which never triggered
+                last_triggered: rule
+                    .last_triggered
+                    .filter(|time| time != "none")
+                    .map(Timestamp::from),
+            })
+        })
+        .collect::<Result<Vec<Rule>, Error>>()?;
+    rules.sort_by_key(|r| r.id);
+
+    Ok(rules)
+}
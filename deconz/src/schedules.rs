@@ -0,0 +1,340 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Error, Group, Light};
+
+/// A timer or recurring action stored on the gateway
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    pub command: ScheduleCommand,
+    pub time: ScheduleTime,
+    pub enabled: bool,
+    /// Whether the gateway deletes the schedule after it ran
+    pub autodelete: bool,
+}
+
+/// A schedule which is about to be created, see `LightClient::create_schedule`
+#[derive(Debug, Clone)]
+pub struct NewSchedule {
+    pub name: String,
+    pub description: String,
+    pub command: ScheduleCommand,
+    pub time: ScheduleTime,
+    pub autodelete: bool,
+}
+
+/// The request the gateway sends to itself when a schedule is due
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleCommand {
+    /// The endpoint below `api/<username>/`, e.g. `lights/1/state`
+    pub address: String,
+    pub method: CommandMethod,
+    pub body: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CommandMethod {
+    Put,
+    Post,
+    Delete,
+}
+
+impl ScheduleCommand {
+    /// Turns a light on or off
+    pub fn light_on_state(light: &Light, on: bool) -> Self {
+        ScheduleCommand {
+            address: format!("lights/{}/state", light.id),
+            method: CommandMethod::Put,
+            body: serde_json::json!({ "on": on }),
+        }
+    }
+
+    /// Turns all lights of a group on or off
+    pub fn group_on_state(group: &Group, on: bool) -> Self {
+        ScheduleCommand {
+            address: format!("groups/{}/action", group.id),
+            method: CommandMethod::Put,
+            body: serde_json::json!({ "on": on }),
+        }
+    }
+}
+
+/// A local time of day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeOfDay {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// A calendar date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+/// A set of days of the week, as the bitmask deconz uses (`0MTWTFSS`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Weekdays(pub u8);
+
+impl Weekdays {
+    pub const MONDAY: Weekdays = Weekdays(0b0100_0000);
+    pub const TUESDAY: Weekdays = Weekdays(0b0010_0000);
+    pub const WEDNESDAY: Weekdays = Weekdays(0b0001_0000);
+    pub const THURSDAY: Weekdays = Weekdays(0b0000_1000);
+    pub const FRIDAY: Weekdays = Weekdays(0b0000_0100);
+    pub const SATURDAY: Weekdays = Weekdays(0b0000_0010);
+    pub const SUNDAY: Weekdays = Weekdays(0b0000_0001);
+    pub const WORKDAYS: Weekdays = Weekdays(0b0111_1100);
+    pub const WEEKEND: Weekdays = Weekdays(0b0000_0011);
+    pub const EVERY_DAY: Weekdays = Weekdays(0b0111_1111);
+
+    pub fn contains(self, days: Weekdays) -> bool {
+        self.0 & days.0 == days.0
+    }
+}
+
+impl std::ops::BitOr for Weekdays {
+    type Output = Weekdays;
+
+    fn bitor(self, rhs: Weekdays) -> Weekdays {
+        Weekdays(self.0 | rhs.0)
+    }
+}
+
+/// How often a timer runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    Once,
+    Times(u8),
+    Forever,
+}
+
+/// When a schedule runs, the `localtime` of a schedule in deconz' ISO-8601 based syntax
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleTime {
+    /// Once at a local date and time (`YYYY-MM-DDThh:mm:ss`)
+    At { date: Date, time: TimeOfDay },
+    /// Every week on the given days (`W<days>/Thh:mm:ss`)
+    Weekly { days: Weekdays, time: TimeOfDay },
+    /// After a duration from the creation of the schedule (`PThh:mm:ss`, repeated with `R<nn>/`)
+    Timer { duration: Duration, repeat: Repeat },
+    /// A time in a syntax this crate doesn't model, e.g. with a random offset
+    Other(String),
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+}
+
+impl fmt::Display for ScheduleTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleTime::At { date, time } => write!(
+                f,
+                "{:04}-{:02}-{:02}T{}",
+                date.year, date.month, date.day, time
+            ),
+            ScheduleTime::Weekly { days, time } => write!(f, "W{}/T{}", days.0, time),
+            ScheduleTime::Timer { duration, repeat } => {
+                match repeat {
+                    Repeat::Once => {}
+                    Repeat::Times(n) => write!(f, "R{:02}/", n)?,
+                    Repeat::Forever => write!(f, "R/")?,
+                }
+                let secs = duration.as_secs();
+                write!(
+                    f,
+                    "PT{:02}:{:02}:{:02}",
+                    secs / 3600,
+                    secs / 60 % 60,
+                    secs % 60
+                )
+            }
+            ScheduleTime::Other(raw) => write!(f, "{}", raw),
+        }
+    }
+}
+
+/// Parses `hh:mm:ss`
+fn parse_time_of_day(s: &str) -> Option<TimeOfDay> {
+    let mut parts = s.splitn(3, ':').map(|p| p.parse::<u8>().ok());
+    Some(TimeOfDay {
+        hour: parts.next()??,
+        minute: parts.next()??,
+        second: parts.next()??,
+    })
+}
+
+/// Parses `yyyy-mm-dd`
+fn parse_date(s: &str) -> Option<Date> {
+    let mut parts = s.splitn(3, '-');
+    Some(Date {
+        year: parts.next()?.parse().ok()?,
+        month: parts.next()?.parse().ok()?,
+        day: parts.next()?.parse().ok()?,
+    })
+}
+
+/// Parses `PThh:mm:ss`, the hours of a timer may exceed a day
+fn parse_timer(s: &str, repeat: Repeat) -> Option<ScheduleTime> {
+    let mut parts = s.strip_prefix("PT")?.splitn(3, ':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u8 = parts.next()?.parse().ok()?;
+    let seconds: u8 = parts.next()?.parse().ok()?;
+    Some(ScheduleTime::Timer {
+        duration: Duration::from_secs(hours * 3600 + minutes as u64 * 60 + seconds as u64),
+        repeat,
+    })
+}
+
+impl From<&str> for ScheduleTime {
+    fn from(value: &str) -> Self {
+        let parsed = if let Some(rest) = value.strip_prefix('W') {
+            rest.split_once("/T").and_then(|(days, time)| {
+                Some(ScheduleTime::Weekly {
+                    days: Weekdays(days.parse().ok()?),
+                    time: parse_time_of_day(time)?,
+                })
+            })
+        } else if let Some(rest) = value.strip_prefix('R') {
+            rest.split_once('/').and_then(|(times, timer)| {
+                let repeat = if times.is_empty() {
+                    Repeat::Forever
+                } else {
+                    Repeat::Times(times.parse().ok()?)
+                };
+                parse_timer(timer, repeat)
+            })
+        } else if value.starts_with("PT") {
+            parse_timer(value, Repeat::Once)
+        } else {
+            value.split_once('T').and_then(|(date, time)| {
+                Some(ScheduleTime::At {
+                    date: parse_date(date)?,
+                    time: parse_time_of_day(time)?,
+                })
+            })
+        };
+
+        parsed.unwrap_or_else(|| ScheduleTime::Other(String::from(value)))
+    }
+}
+
+impl Serialize for ScheduleTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ScheduleTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(ScheduleTime::from(
+            String::deserialize(deserializer)?.as_str(),
+        ))
+    }
+}
+
+/// The command of a schedule as the gateway stores it, with the full address
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RawCommand {
+    address: String,
+    method: CommandMethod,
+    body: serde_json::Value,
+}
+
+impl RawCommand {
+    pub(crate) fn new(command: &ScheduleCommand, username: &str) -> Self {
+        RawCommand {
+            address: format!("/api/{}/{}", username, command.address),
+            method: command.method,
+            body: command.body.clone(),
+        }
+    }
+
+    fn into_command(self, username: &str) -> ScheduleCommand {
+        let prefix = format!("/api/{}/", username);
+        ScheduleCommand {
+            address: self
+                .address
+                .strip_prefix(&prefix)
+                .map(String::from)
+                .unwrap_or(self.address),
+            method: self.method,
+            body: self.body,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RawSchedule {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    description: String,
+    command: RawCommand,
+    /// Current gateways send both, older ones only have the time in UTC
+    time: Option<ScheduleTime>,
+    localtime: Option<ScheduleTime>,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    autodelete: bool,
+}
+
+/// The body of `POST api/<user>/schedules`
+#[derive(Serialize)]
+pub(crate) struct CreateScheduleReq<'a> {
+    name: &'a str,
+    description: &'a str,
+    command: RawCommand,
+    localtime: &'a ScheduleTime,
+    autodelete: bool,
+}
+
+impl<'a> CreateScheduleReq<'a> {
+    pub(crate) fn new(schedule: &'a NewSchedule, username: &str) -> Self {
+        CreateScheduleReq {
+            name: &schedule.name,
+            description: &schedule.description,
+            command: RawCommand::new(&schedule.command, username),
+            localtime: &schedule.time,
+            autodelete: schedule.autodelete,
+        }
+    }
+}
+
+/// Converts the schedules object returned by `GET api/<user>/schedules`, sorted by id
+pub(crate) fn parse_schedules(
+    schedules: HashMap<String, RawSchedule>,
+    username: &str,
+) -> Result<Vec<Schedule>, Error> {
+    let mut schedules = schedules
+        .into_iter()
+        .map(|(id, schedule)| {
+            Ok(Schedule {
+                id: id.parse().map_err(Error::IdParseError)?,
+                name: schedule.name,
+                description: schedule.description,
+                command: schedule.command.into_command(username),
+                time: schedule.localtime.or(schedule.time).ok_or_else(|| {
+                    Error::ResponseParseError(format!("Schedule {} has no time", id))
+                })?,
+                enabled: schedule.status != "disabled",
+                autodelete: schedule.autodelete,
+            })
+        })
+        .collect::<Result<Vec<Schedule>, Error>>()?;
+    schedules.sort_by_key(|s| s.id);
+
+    Ok(schedules)
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{Error, Timestamp};
+
+/// A sensor connected to the gateway
+#[derive(Debug, Clone)]
+pub struct Sensor {
+    pub id: u32,
+    pub name: String,
+    /// The deconz type of the sensor, e.g. `ZHATemperature`
+    pub sensor_type: String,
+    pub reading: SensorReading,
+    /// When the gateway last heard from the sensor, `None` if never or if it doesn't tell
+    pub last_seen: Option<Timestamp>,
+}
+
+/// The last value reported by a sensor
+#[derive(Debug, Clone)]
+pub enum SensorReading {
+    /// Temperature in degrees celsius
+    Temperature(f32),
+    /// Relative humidity in percent
+    Humidity(f32),
+    /// Whether presence (motion) is detected
+    Presence(bool),
+    /// Illuminance in lux
+    LightLevel(u32),
+    /// The last button event of a switch
+    Switch(Option<u32>),
+    /// Any reading of a sensor type this crate doesn't know, as the raw `state` object
+    Other(serde_json::Value),
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RawSensor {
+    name: String,
+    #[serde(rename = "type")]
+    sensor_type: String,
+    #[serde(default)]
+    state: serde_json::Value,
+    #[serde(rename = "lastseen", default)]
+    last_seen: Option<Timestamp>,
+}
+
+impl SensorReading {
+    /// Parses the `state` object of a sensor based on its type
+    ///
+    /// Readings that don't look like expected for the type end up as `SensorReading::Other`.
+    fn from_state(sensor_type: &str, state: serde_json::Value) -> Self {
+        let field = |name: &str| state.get(name).cloned().unwrap_or_default();
+        let reading = match sensor_type {
+            // deconz reports temperature and humidity in hundredths
+            "ZHATemperature" | "CLIPTemperature" => field("temperature")
+                .as_f64()
+                .map(|t| SensorReading::Temperature(t as f32 / 100.0)),
+            "ZHAHumidity" | "CLIPHumidity" => field("humidity")
+                .as_f64()
+                .map(|h| SensorReading::Humidity(h as f32 / 100.0)),
+            "ZHAPresence" | "CLIPPresence" => {
+                field("presence").as_bool().map(SensorReading::Presence)
+            }
+            "ZHALightLevel" | "CLIPLightLevel" => field("lux")
+                .as_u64()
+                .map(|lux| SensorReading::LightLevel(lux as u32)),
+            "ZHASwitch" | "CLIPSwitch" => Some(SensorReading::Switch(
+                field("buttonevent").as_u64().map(|e| e as u32),
+            )),
+            _ => None,
+        };
+
+        reading.unwrap_or(SensorReading::Other(state))
+    }
+}
+
+impl SensorReading {
+    /// Parses the `state` of a sensor event, which doesn't tell the type of the sensor
+    ///
+    /// The type is guessed from the fields of the state instead.
+    pub(crate) fn from_event_state(state: serde_json::Value) -> Self {
+        let sensor_type = [
+            ("temperature", "ZHATemperature"),
+            ("humidity", "ZHAHumidity"),
+            ("presence", "ZHAPresence"),
+            ("lux", "ZHALightLevel"),
+            ("buttonevent", "ZHASwitch"),
+        ]
+        .into_iter()
+        .find(|(field, _)| state.get(field).is_some())
+        .map(|(_, sensor_type)| sensor_type)
+        .unwrap_or_default();
+
+        Self::from_state(sensor_type, state)
+    }
+}
+
+/// Converts the sensors object returned by `GET api/<user>/sensors`, sorted by id
+pub(crate) fn parse_sensors(sensors: HashMap<String, RawSensor>) -> Result<Vec<Sensor>, Error> {
+    let mut sensors = sensors
+        .into_iter()
+        .map(|(id, sensor)| {
+            Ok(Sensor {
+                id: id.parse().map_err(Error::IdParseError)?,
+                reading: SensorReading::from_state(&sensor.sensor_type, sensor.state),
+                name: sensor.name,
+                sensor_type: sensor.sensor_type,
+                last_seen: sensor.last_seen,
+            })
+        })
+        .collect::<Result<Vec<Sensor>, Error>>()?;
+    sensors.sort_by_key(|s| s.id);
+
+    Ok(sensors)
+}
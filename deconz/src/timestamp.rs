@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+/// A point in time as reported by the gateway, e.g. the `lastseen` of a light
+///
+/// The gateway mixes formats: `lastseen` is in UTC with minute precision (`2020-11-22T11:27Z`),
+/// `lastannounced` has seconds (`2020-11-22T10:59:19Z`) and the `localtime` of the gateway has
+/// no time zone at all (`2020-11-22T12:27:19`). The raw value is kept, the `chrono` feature adds
+/// parsing it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Timestamp(String);
+
+impl Timestamp {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Parses the timestamp, `None` if it has an unknown format
+    ///
+    /// Timestamps without a time zone are in the local time of the gateway, which is assumed
+    /// to be the same as the one of this machine.
+    #[cfg(feature = "chrono")]
+    pub fn to_utc(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        use chrono::{DateTime, Local, NaiveDateTime, Utc};
+
+        if let Ok(time) = DateTime::parse_from_rfc3339(&self.0) {
+            return Some(time.with_timezone(&Utc));
+        }
+        // RFC 3339 requires seconds, which `lastseen` leaves out
+        if let Ok(time) = NaiveDateTime::parse_from_str(&self.0, "%Y-%m-%dT%H:%MZ") {
+            return Some(time.and_utc());
+        }
+
+        let local = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(&self.0, format).ok())?;
+        local
+            .and_local_timezone(Local)
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+    }
+
+    /// How long ago the timestamp was, `None` if it can't be parsed or is in the future
+    #[cfg(feature = "chrono")]
+    pub fn elapsed(&self) -> Option<std::time::Duration> {
+        (chrono::Utc::now() - self.to_utc()?).to_std().ok()
+    }
+}
+
+impl From<String> for Timestamp {
+    fn from(timestamp: String) -> Self {
+        Timestamp(timestamp)
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{self, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+
+use crate::Error;
+
+/// The TLS config of the websocket to an https gateway, which trusts the same certificates as
+/// the http client
+///
+/// `certificates` are the PEM or DER files added with `DeconzClientBuilder::add_root_certificate`.
+pub(crate) fn websocket_config(
+    accept_invalid_certs: bool,
+    certificates: &[Vec<u8>],
+) -> Result<Arc<ClientConfig>, Error> {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(invalid_certificate)?;
+
+    let config = if accept_invalid_certs {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+            .with_no_client_auth()
+    } else {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        for bytes in certificates {
+            let certificate = if bytes.starts_with(b"-----BEGIN") {
+                CertificateDer::from_pem_slice(bytes).map_err(invalid_certificate)?
+            } else {
+                CertificateDer::from(bytes.clone())
+            };
+            roots.add(certificate).map_err(invalid_certificate)?;
+        }
+        builder.with_root_certificates(roots).with_no_client_auth()
+    };
+    Ok(Arc::new(config))
+}
+
+fn invalid_certificate<E: std::fmt::Display>(e: E) -> Error {
+    Error::IoError(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Invalid certificate: {}", e),
+    ))
+}
+
+/// Skips the validation of the certificate, see `DeconzClientBuilder::danger_accept_invalid_certs`
+///
+/// The handshake signatures are still checked, as reqwest does.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
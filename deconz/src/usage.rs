@@ -0,0 +1,124 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{LightEvent, LightId, LightState};
+
+#[derive(Debug, Clone, Copy, Default)]
+struct LightUsage {
+    /// On time of the periods which already ended
+    total: Duration,
+    /// When the light was turned on, if it is on
+    on_since: Option<SystemTime>,
+}
+
+/// Adds up how long each light was on, as the gateway doesn't keep track of it
+///
+/// Feed it the states and events of the lights, e.g. from `LightClient::subscribe_events` or
+/// `LightClient::watch_light_states`. Only time in which the tracker was fed is counted.
+///
+/// The tracker (de)serializes to the on time per light, a light which is on while the tracker is
+/// stored counts as turned off at that moment. It is turned on again by the next state fed.
+#[derive(Debug, Default)]
+pub struct LightUsageTracker {
+    lights: Mutex<HashMap<LightId, LightUsage>>,
+}
+
+impl LightUsageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that a light is on or off now
+    pub fn record(&self, id: &LightId, on: bool) {
+        self.record_at(id, on, SystemTime::now());
+    }
+
+    /// Records that a light was on or off at the given time
+    pub fn record_at(&self, id: &LightId, on: bool, time: SystemTime) {
+        let mut lights = self.lights.lock().unwrap();
+        let usage = lights.entry(id.clone()).or_default();
+        match (usage.on_since, on) {
+            (None, true) => usage.on_since = Some(time),
+            (Some(since), false) => {
+                usage.total += time.duration_since(since).unwrap_or_default();
+                usage.on_since = None;
+            }
+            // Still on or still off
+            _ => {}
+        }
+    }
+
+    /// Records the on state of several lights, e.g. from `LightClient::get_all_light_states`
+    pub fn record_states(&self, states: &HashMap<LightId, LightState>) {
+        for (id, state) in states {
+            self.record(id, state.on);
+        }
+    }
+
+    /// Records the on state if the event changed it
+    pub fn record_event(&self, event: &LightEvent) {
+        if let LightEvent::StateChanged {
+            id,
+            change: crate::LightStateChange { on: Some(on), .. },
+        } = event
+        {
+            self.record(id, *on);
+        }
+    }
+
+    /// How long the light was on in total, including the time since it was turned on if it is on
+    pub fn on_duration(&self, id: &LightId) -> Duration {
+        self.on_duration_at(id, SystemTime::now())
+    }
+
+    fn on_duration_at(&self, id: &LightId, now: SystemTime) -> Duration {
+        let lights = self.lights.lock().unwrap();
+        let Some(usage) = lights.get(id) else {
+            return Duration::ZERO;
+        };
+        let running = usage
+            .on_since
+            .and_then(|since| now.duration_since(since).ok())
+            .unwrap_or_default();
+        usage.total + running
+    }
+}
+
+impl Serialize for LightUsageTracker {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let now = SystemTime::now();
+        let ids: Vec<LightId> = self.lights.lock().unwrap().keys().cloned().collect();
+        let totals: HashMap<LightId, Duration> = ids
+            .into_iter()
+            .map(|id| {
+                let duration = self.on_duration_at(&id, now);
+                (id, duration)
+            })
+            .collect();
+        totals.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LightUsageTracker {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let totals = HashMap::<LightId, Duration>::deserialize(deserializer)?;
+        let lights = totals
+            .into_iter()
+            .map(|(id, total)| {
+                let usage = LightUsage {
+                    total,
+                    on_since: None,
+                };
+                (id, usage)
+            })
+            .collect();
+        Ok(LightUsageTracker {
+            lights: Mutex::new(lights),
+        })
+    }
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "blocking")]
+
+mod common;
+
+use common::MockGateway;
+use deconz::{BlockingDeconzClient, LightId};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_json, method, path},
+    Mock, ResponseTemplate,
+};
+
+#[tokio::test]
+async fn blocking_client_works_outside_of_a_runtime() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "on": false })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!([{ "success": { "/lights/1/state/on": false } }])),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+
+    // The blocking client must not run on a thread of the test's runtime
+    tokio::task::spawn_blocking(move || {
+        let client = BlockingDeconzClient::new(client).unwrap();
+
+        let lights = client.get_light_list().unwrap();
+        let ceiling = &lights[0];
+        assert_eq!(ceiling.id, LightId::from(1));
+        assert!(client.get_light_state(ceiling).unwrap().on);
+        client.set_on_state(ceiling, false).unwrap();
+    })
+    .await
+    .unwrap();
+}
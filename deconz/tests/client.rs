@@ -0,0 +1,1494 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::MockGateway;
+use deconz::{
+    AlertMode, ColorMode, ConnectionState, Controllable, DeconzClient, DemoLightClient, Effect,
+    Error, Group, LightClient, LightId, LightListExt, LightState, LightType, RequestMetric,
+    MAX_BRIGHTNESS,
+};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_json, method, path},
+    Mock, ResponseTemplate,
+};
+
+#[tokio::test]
+async fn light_list_is_sorted_and_parsed() {
+    let gateway = MockGateway::start().await;
+
+    let lights = gateway.client().get_light_list().await.unwrap();
+
+    assert_eq!(lights.len(), 3);
+    assert_eq!(lights[0].id, LightId::from(1));
+    assert_eq!(lights[0].name, "Ceiling");
+    assert!(lights[0].capabilities.color);
+    assert!(lights[0].capabilities.color_temp);
+    assert_eq!(lights[0].capabilities.min_bri, 26);
+    assert_eq!(
+        lights[0].capabilities.effects,
+        [
+            Effect::None,
+            Effect::ColorLoop,
+            Effect::Other(String::from("candle"))
+        ]
+    );
+    // Alerts this crate doesn't know are skipped
+    assert_eq!(
+        lights[0].capabilities.alerts,
+        [AlertMode::None, AlertMode::Select, AlertMode::LSelect]
+    );
+    assert_eq!(lights[0].model_id.as_deref(), Some("LCT015"));
+    assert_eq!(
+        lights[0].last_seen.as_ref().map(|t| t.as_str()),
+        Some("2020-11-22T11:27Z")
+    );
+
+    assert_eq!(lights[1].id, LightId::from(2));
+    assert!(lights[1].capabilities.dimmable);
+    assert!(!lights[1].capabilities.color);
+    assert_eq!(
+        lights[1].manufacturer.as_deref(),
+        Some("dresden elektronik")
+    );
+    assert!(lights[1].last_seen.is_none());
+    // Older firmware doesn't report effects
+    assert!(lights[1].capabilities.effects.is_empty());
+
+    assert_eq!(lights[2].id.as_str(), "0x1234");
+    assert_eq!(lights[2].id.as_u32(), None);
+    assert!(!lights[2].capabilities.dimmable);
+    assert_eq!(lights[2].light_type, Some(LightType::OnOffPlugInUnit));
+    assert!(lights[2].light_type.as_ref().unwrap().is_plug());
+}
+
+#[test]
+fn plugs_are_neither_dimmable_nor_colored() {
+    let plug: LightState =
+        serde_json::from_value(json!({ "on": true, "reachable": true })).unwrap();
+    assert!(!plug.is_dimmable() && !plug.is_color_capable());
+
+    let white: LightState =
+        serde_json::from_value(json!({ "on": true, "bri": 120, "ct": 300 })).unwrap();
+    assert!(white.is_dimmable() && !white.is_color_capable());
+
+    let color: LightState =
+        serde_json::from_value(json!({ "on": true, "bri": 120, "xy": [0.3, 0.3] })).unwrap();
+    assert!(color.is_dimmable() && color.is_color_capable());
+}
+
+#[test]
+fn light_types_keep_the_gateway_names() {
+    let ct: LightType = "Color temperature light".parse().unwrap();
+    assert_eq!(ct, LightType::ColorTemperatureLight);
+    assert!(ct.supports_ct() && !ct.supports_color() && ct.is_dimmable());
+    assert!(!ct.is_plug());
+
+    let unknown: LightType = serde_json::from_value(json!("Window covering device")).unwrap();
+    assert_eq!(
+        unknown,
+        LightType::Other(String::from("Window covering device"))
+    );
+    assert!(!unknown.is_dimmable());
+    assert_eq!(
+        serde_json::to_value(&unknown).unwrap(),
+        json!("Window covering device")
+    );
+    assert_eq!(
+        serde_json::to_value(LightType::ExtendedColorLight).unwrap(),
+        json!("Extended color light")
+    );
+}
+
+#[tokio::test]
+async fn light_state_is_parsed() {
+    let gateway = MockGateway::start().await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+
+    let state = client.get_light_state(&light).await.unwrap();
+
+    assert!(state.on);
+    assert!(state.reachable);
+    assert_eq!(state.bri, Some(200));
+    assert_eq!(state.hue, Some(1000));
+    assert_eq!(state.color_mode, Some(ColorMode::Ct));
+}
+
+#[test]
+fn color_modes_are_parsed() {
+    let modes = [
+        ("hs", ColorMode::Hs),
+        ("xy", ColorMode::Xy),
+        ("ct", ColorMode::Ct),
+        ("effect", ColorMode::Unknown),
+    ];
+
+    for (raw, mode) in modes {
+        assert_eq!(
+            serde_json::from_value::<ColorMode>(json!(raw)).unwrap(),
+            mode
+        );
+    }
+}
+
+#[tokio::test]
+async fn default_transition_is_sent_unless_overridden() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "on": true, "transitiontime": 3 })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!([{ "success": { "/lights/1/state/on": true } }])),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "bri": 100, "transitiontime": 0 })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!([{ "success": { "/lights/1/state/bri": 100 } }])),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client_with(
+        DeconzClient::builder().default_transition(std::time::Duration::from_millis(300)),
+    );
+    let light = client.get_light_list().await.unwrap().remove(0);
+
+    client.set_on_state(&light, true).await.unwrap();
+    client
+        .set_light_color_with_transition(&light, None, Some(100), None, Some(0))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn set_on_state_sends_put() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "on": true })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!([{ "success": { "/lights/1/state/on": true } }])),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+
+    let applied = client.set_on_state(&light, true).await.unwrap();
+
+    assert_eq!(applied.on, Some(true));
+}
+
+#[tokio::test]
+async fn color_change_returns_the_applied_values() {
+    let gateway = MockGateway::start().await;
+    // The transition time the gateway echoes isn't part of the applied values
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "success": { "/lights/1/state/hue": 46000 } },
+            { "success": { "/lights/1/state/bri": 254 } },
+            { "success": { "/lights/1/state/transitiontime": 4 } }
+        ])))
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+    let mut state = client.get_light_state(&light).await.unwrap();
+
+    let applied = client
+        .set_light_color(&light, Some(46000), Some(254), None)
+        .await
+        .unwrap();
+
+    assert_eq!(applied.hue, Some(46000));
+    assert_eq!(applied.bri, Some(254));
+    assert_eq!(applied.sat, None);
+    applied.apply_to(&mut state);
+    assert_eq!(state.bri, Some(254));
+    assert_eq!(state.sat, Some(50));
+    assert_eq!(state.color_mode, Some(ColorMode::Hs));
+}
+
+#[tokio::test]
+async fn out_of_range_values_are_not_sent() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(0)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+    let groups = client.get_group_list().await.unwrap();
+
+    let result = client.set_light_color(&light, None, Some(255), None).await;
+    assert!(matches!(
+        result,
+        Err(Error::InvalidValue {
+            field: "bri",
+            value: 255
+        })
+    ));
+    let result = client
+        .set_group_color(&groups[0], Some(0), None, Some(255))
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::InvalidValue {
+            field: "sat",
+            value: 255
+        })
+    ));
+}
+
+#[tokio::test]
+async fn non_numeric_id_is_used_in_the_path() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/0x1234/state")))
+        .and(body_json(json!({ "on": false })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(2);
+
+    client.set_on_state(&light, false).await.unwrap();
+}
+
+#[tokio::test]
+async fn error_body_is_api_error() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+            "error": {
+                "type": 201,
+                "address": "/lights/1/state/bri",
+                "description": "parameter, bri, is not modifiable. Device is set to off."
+            }
+        }])))
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+
+    let result = client.set_light_color(&light, None, Some(10), None).await;
+
+    assert!(matches!(
+        result,
+        Err(Error::ApiError { type_code: 201, ref address, .. }) if address == "/lights/1/state/bri"
+    ));
+}
+
+#[tokio::test]
+async fn bad_request_is_unsupported() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/2/state")))
+        .respond_with(ResponseTemplate::new(400))
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(1);
+
+    let result = client.set_color_temp(&light, 300).await;
+
+    assert!(matches!(result, Err(Error::Unsupported(_))));
+}
+
+#[tokio::test]
+async fn http_error_has_a_message_and_source() {
+    let gateway = MockGateway::start().await;
+    let client = gateway.client();
+    let mut light = client.get_light_list().await.unwrap().remove(0);
+    light.id = LightId::from(9);
+
+    let error = client.get_light_state(&light).await.unwrap_err();
+
+    assert_eq!(error.to_string(), "The gateway answered with 404 Not Found");
+    assert!(std::error::Error::source(&error).is_some());
+}
+
+#[tokio::test]
+async fn missing_light_is_http_error() {
+    let gateway = MockGateway::start().await;
+    let client = gateway.client();
+    let mut light = client.get_light_list().await.unwrap().remove(0);
+    light.id = LightId::from(9);
+
+    let result = client.get_light_state(&light).await;
+
+    assert!(
+        matches!(result, Err(Error::HttpError(e)) if e.status().map(|s| s.as_u16()) == Some(404))
+    );
+}
+
+#[tokio::test]
+async fn xy_is_clamped_to_the_gamut_of_the_light() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("lights")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "1": {
+                "name": "Ceiling",
+                "type": "Extended color light",
+                "capabilities": {
+                    "color": {
+                        "xy": {
+                            "red": [0.6915, 0.3083],
+                            "green": [0.17, 0.7],
+                            "blue": [0.1532, 0.0475]
+                        }
+                    }
+                },
+                "state": { "on": true, "xy": [0.3, 0.3], "reachable": true }
+            }
+        })))
+        .with_priority(1)
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "xy": [0.6915, 0.3083] })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!([{ "success": { "/lights/1/state/xy": [0.6915, 0.3083] } }])),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+    assert!(light.capabilities.gamut.is_some());
+
+    client.set_light_xy(&light, 0.8, 0.25).await.unwrap();
+}
+
+#[tokio::test]
+async fn single_light_is_loaded_by_id() {
+    let gateway = MockGateway::start().await;
+    let client = gateway.client();
+
+    let light = client.get_light(&LightId::from(1)).await.unwrap();
+    assert_eq!(light.name, "Ceiling");
+    assert_eq!(light.light_type, Some(LightType::ExtendedColorLight));
+    assert!(light.capabilities.color);
+
+    let missing = client.get_light(&LightId::from(9)).await;
+    assert!(matches!(missing, Err(Error::NotFound(_))));
+}
+
+#[tokio::test]
+async fn speed_is_unsupported_without_gradients() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "speed": 100 })))
+        .respond_with(ResponseTemplate::new(400))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+
+    let result = client.set_speed(&light, 100).await;
+
+    assert!(!light.capabilities.gradient);
+    assert!(matches!(result, Err(Error::Unsupported(_))));
+}
+
+#[tokio::test]
+async fn toggle_turns_a_light_off_which_is_on() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "on": false })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+
+    let on = client.toggle_on_state(&light).await.unwrap();
+
+    assert!(!on);
+}
+
+#[tokio::test]
+async fn unlisted_effect_is_unsupported() {
+    let gateway = MockGateway::start().await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+
+    let result = client
+        .set_effect(&light, Effect::Other(String::from("fireworks")))
+        .await;
+
+    assert!(matches!(result, Err(Error::Unsupported(_))));
+}
+
+#[tokio::test]
+async fn invalid_body_is_parse_error() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("sensors")))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("not json", "application/json"))
+        .mount(&gateway.server)
+        .await;
+
+    let result = gateway.client().get_sensors().await;
+
+    assert!(matches!(result, Err(Error::ResponseParseError(_))));
+}
+
+#[tokio::test]
+async fn html_body_is_not_a_gateway() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw("<html>Please log in</html>", "text/html"),
+        )
+        .mount(&gateway.server)
+        .await;
+
+    let result = DeconzClient::pair_with_link_button(gateway.server.uri()).await;
+
+    assert!(matches!(result, Err(Error::NotADeconzGateway)));
+}
+
+#[tokio::test]
+async fn link_button_login_sends_the_device_type() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api"))
+        .and(body_json(json!({ "devicetype": "test-app#kitchen" })))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!([{ "success": { "username": "new" } }])),
+        )
+        .mount(&gateway.server)
+        .await;
+
+    #[allow(deprecated)]
+    let client = DeconzClient::builder()
+        .device_type("test-app#kitchen")
+        .login_with_link_button(gateway.server.uri())
+        .await
+        .unwrap();
+
+    assert_eq!(client.username, "new");
+}
+
+#[tokio::test]
+async fn pairing_tells_which_gateway_was_paired() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!([{ "success": { "username": "new" } }])),
+        )
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/new/config"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Phoscon-GW",
+            "bridgeid": "00212EFFFF012345",
+            "swversion": "2.28.1",
+            "apiversion": "1.16.0",
+            "ipaddress": "192.168.0.2",
+            "websocketport": 443
+        })))
+        .mount(&gateway.server)
+        .await;
+
+    let result = DeconzClient::pair_with_link_button(gateway.server.uri())
+        .await
+        .unwrap();
+
+    assert_eq!(result.client.username, "new");
+    assert_eq!(result.gateway_name, "Phoscon-GW");
+    assert_eq!(result.bridge_id, "00212EFFFF012345");
+}
+
+#[tokio::test]
+async fn group_list_is_parsed() {
+    let gateway = MockGateway::start().await;
+
+    let groups = gateway.client().get_group_list().await.unwrap();
+
+    let living_room = groups.iter().find(|g| g.id == 1).unwrap();
+    assert_eq!(living_room.name, "Living room");
+    assert_eq!(living_room.lights, vec![LightId::from(1), LightId::from(2)]);
+}
+
+#[tokio::test]
+async fn scene_details_list_every_light_of_the_group() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("groups/1/scenes")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "2": { "name": "Reading", "lightcount": 2, "transitiontime": 10 }
+        })))
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("groups/1")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Living room", "lights": ["1", "2"]
+        })))
+        .mount(&gateway.server)
+        .await;
+    // Light 2 isn't stored, light 3 was removed from the group after storing the scene
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("groups/1/scenes/2")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Reading",
+            "lights": [
+                { "id": "3", "on": false, "transitiontime": 10 },
+                {
+                    "id": "1", "on": true, "bri": 180, "x": 0.45, "y": 0.4,
+                    "colormode": "xy", "transitiontime": 10
+                }
+            ],
+            "state": 0
+        })))
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let groups = client.get_group_list().await.unwrap();
+    let scenes = client.get_scenes(&groups[0]).await.unwrap();
+    assert_eq!(scenes[0].transition_time, Some(10));
+
+    let lights = client.get_scene_details(&scenes[0]).await.unwrap();
+
+    let ids: Vec<&LightId> = lights.iter().map(|(id, _)| id).collect();
+    assert_eq!(
+        ids,
+        [&LightId::from(1), &LightId::from(2), &LightId::from(3)]
+    );
+    let ceiling = lights[0].1.as_ref().unwrap();
+    assert!(ceiling.on);
+    assert_eq!(ceiling.bri, Some(180));
+    assert_eq!(ceiling.xy, Some([0.45, 0.4]));
+    assert_eq!(ceiling.color_mode, Some(ColorMode::Xy));
+    assert!(lights[1].1.is_none());
+    assert!(!lights[2].1.as_ref().unwrap().on);
+}
+
+#[tokio::test]
+async fn groups_and_scenes_are_renamed() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("groups/1/scenes")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "2": { "name": "Reading" }
+        })))
+        .mount(&gateway.server)
+        .await;
+    for (endpoint, name) in [("groups/1", "Kitchen"), ("groups/1/scenes/2", "Cooking")] {
+        Mock::given(method("PUT"))
+            .and(path(MockGateway::api_path(endpoint)))
+            .and(body_json(json!({ "name": name })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!([{ "success": { format!("/{}/name", endpoint): name } }])),
+            )
+            .expect(1)
+            .mount(&gateway.server)
+            .await;
+    }
+    let client = gateway.client();
+    let group = client.get_group_list().await.unwrap().remove(0);
+    let scene = client.get_scenes(&group).await.unwrap().remove(0);
+
+    client.set_group_name(&group, "Kitchen").await.unwrap();
+    client.set_scene_name(&scene, "Cooking").await.unwrap();
+}
+
+#[tokio::test]
+async fn demo_rejects_long_group_names() {
+    let client = DemoLightClient::new();
+    let group = client.get_group_list().await.unwrap().remove(0);
+
+    let result = client.set_group_name(&group, &"x".repeat(33)).await;
+
+    assert!(matches!(result, Err(Error::ApiError { type_code: 7, .. })));
+}
+
+#[tokio::test]
+async fn demo_deletes_the_scenes_of_a_group() {
+    let client = DemoLightClient::new();
+    let group = client
+        .get_group_list()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|g| g.id != Group::ALL_LIGHTS_ID)
+        .unwrap();
+    let scene = client.create_scene(&group, "Evening").await.unwrap();
+
+    client.delete_group(&group).await.unwrap();
+
+    assert!(client.get_scenes(&group).await.unwrap().is_empty());
+    let result = client.recall_scene(&scene).await;
+    assert!(matches!(result, Err(Error::NotFound(_))));
+    let result = client.get_scene_details(&scene).await;
+    assert!(matches!(result, Err(Error::NotFound(_))));
+}
+
+#[tokio::test]
+async fn demo_reports_unknown_lights() {
+    let client = DemoLightClient::new();
+    let mut light = client.get_light_list().await.unwrap().remove(0);
+    light.id = LightId::from("99");
+
+    let state = client.get_light_state(&light).await;
+    assert!(matches!(state, Err(Error::NotFound(_))));
+    let result = client.set_on_state(&light, true).await;
+    assert!(matches!(result, Err(Error::NotFound(_))));
+
+    let group = client.get_group_list().await.unwrap().remove(0);
+    client
+        .set_group_lights(&group, &[light.id.clone()])
+        .await
+        .unwrap();
+    let group = client.get_group_list().await.unwrap().remove(0);
+    let result = client.set_group_on_state(&group, true).await;
+    assert!(matches!(result, Err(Error::NotFound(_))));
+}
+
+#[tokio::test]
+async fn scenes_are_created_and_stored() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("POST"))
+        .and(path(MockGateway::api_path("groups/1/scenes")))
+        .and(body_json(json!({ "name": "Evening" })))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!([{ "success": { "id": "3" } }])),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("groups/1/scenes/3/store")))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!([{ "success": { "id": "3" } }])),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let group = client.get_group_list().await.unwrap().remove(0);
+
+    let scene = client.create_scene(&group, "Evening").await.unwrap();
+    assert_eq!((scene.id, scene.group_id), (3, 1));
+    assert_eq!(scene.name, "Evening");
+
+    client.store_scene(&scene).await.unwrap();
+}
+
+#[tokio::test]
+async fn full_scene_table_is_reported() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("POST"))
+        .and(path(MockGateway::api_path("groups/1/scenes")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+            "error": {
+                "type": 402, "address": "/groups/1/scenes",
+                "description": "Scene could not be created. Scene buffer in bridge full"
+            }
+        }])))
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let group = client.get_group_list().await.unwrap().remove(0);
+
+    let result = client.create_scene(&group, "One too many").await;
+
+    assert!(matches!(result, Err(Error::LimitReached(_))));
+}
+
+#[tokio::test]
+async fn controllables_use_their_own_endpoint() {
+    async fn switch_on(target: &impl Controllable, client: &DeconzClient) -> String {
+        target.set_on(client, true).await.unwrap();
+        String::from(target.display_name())
+    }
+
+    let gateway = MockGateway::start().await;
+    for endpoint in ["lights/1/state", "groups/1/action"] {
+        Mock::given(method("PUT"))
+            .and(path(MockGateway::api_path(endpoint)))
+            .and(body_json(json!({ "on": true })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+            .expect(1)
+            .mount(&gateway.server)
+            .await;
+    }
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+    let group = client.get_group_list().await.unwrap().remove(0);
+
+    assert_eq!(switch_on(&light, &client).await, "Ceiling");
+    assert_eq!(switch_on(&group, &client).await, "Living room");
+}
+
+#[tokio::test]
+async fn group_state_is_parsed() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("groups/1")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Living room",
+            "lights": ["1", "2"],
+            "action": {
+                "on": true, "bri": 127, "hue": 8000, "sat": 200, "effect": "none",
+                "xy": [0.5, 0.4], "ct": 300, "alert": "none", "colormode": "hs"
+            },
+            "state": { "any_on": true, "all_on": false }
+        })))
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let group = client.get_group_list().await.unwrap().remove(0);
+
+    let state = client.get_group_state(&group).await.unwrap();
+
+    assert!(state.any_on);
+    assert!(!state.all_on);
+    assert_eq!(state.action.bri, Some(127));
+    assert_eq!(state.action.hue, Some(8000));
+    assert_eq!(state.action.color_mode, Some(ColorMode::Hs));
+}
+
+#[tokio::test]
+async fn probe_reads_the_public_config() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/config"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Phoscon-GW",
+            "bridgeid": "00212EFFFF012345",
+            "modelid": "deCONZ",
+            "swversion": "2.28.1",
+            "apiversion": "1.16.0",
+            "factorynew": false
+        })))
+        .mount(&gateway.server)
+        .await;
+
+    let probe = DeconzClient::probe(gateway.server.uri()).await.unwrap();
+
+    assert_eq!(probe.name, "Phoscon-GW");
+    assert_eq!(probe.api_version.as_deref(), Some("1.16.0"));
+}
+
+#[tokio::test]
+async fn probe_rejects_other_servers() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/config"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "name": "Hue Bridge", "modelid": "BSB002" })),
+        )
+        .mount(&gateway.server)
+        .await;
+
+    let result = DeconzClient::probe(gateway.server.uri()).await;
+
+    assert!(matches!(result, Err(Error::NotADeconzGateway)));
+}
+
+#[test]
+fn missing_root_certificate_fails_the_login() {
+    let result = DeconzClient::builder()
+        .add_root_certificate("/nonexistent/gateway-ca.pem")
+        .login_with_token("https://192.168.0.2", String::from("token"));
+
+    assert!(matches!(result, Err(Error::IoError(_))));
+}
+
+#[test]
+fn invalid_root_certificate_fails_the_login() {
+    let path = std::env::temp_dir().join("deconz-test-invalid-ca.pem");
+    std::fs::write(
+        &path,
+        "-----BEGIN CERTIFICATE-----\nnot a certificate\n-----END CERTIFICATE-----\n",
+    )
+    .unwrap();
+
+    let result = DeconzClient::builder()
+        .add_root_certificate(&path)
+        .login_with_token("https://192.168.0.2", String::from("token"));
+
+    assert!(matches!(result, Err(Error::HttpError(_))));
+}
+
+#[tokio::test]
+async fn builder_probe_accepting_invalid_certs_still_speaks_http() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/config"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Phoscon-GW",
+            "modelid": "deCONZ",
+            "swversion": "2.28.1"
+        })))
+        .mount(&gateway.server)
+        .await;
+
+    let probe = DeconzClient::builder()
+        .danger_accept_invalid_certs(true)
+        .probe(gateway.server.uri())
+        .await
+        .unwrap();
+
+    assert_eq!(probe.name, "Phoscon-GW");
+}
+
+#[tokio::test]
+async fn address_without_scheme_is_http() {
+    let gateway = MockGateway::start().await;
+    let address = gateway.server.address().to_string();
+
+    let client = DeconzClient::login_with_token(address, String::from(common::TOKEN)).unwrap();
+
+    assert_eq!(client.get_light_list().await.unwrap().len(), 3);
+}
+
+#[test]
+fn gateway_url_is_normalized() {
+    assert_eq!(
+        deconz::gateway_url("192.168.0.2").unwrap().as_str(),
+        "http://192.168.0.2/"
+    );
+    assert_eq!(
+        deconz::gateway_url("https://example.com/deconz")
+            .unwrap()
+            .as_str(),
+        "https://example.com/deconz/"
+    );
+    assert!(matches!(
+        deconz::gateway_url("ftp://192.168.0.2"),
+        Err(Error::InvalidUrl(_))
+    ));
+    assert!(matches!(
+        deconz::gateway_url("http://"),
+        Err(Error::InvalidUrl(_))
+    ));
+}
+
+#[test]
+fn gateway_url_accepts_ipv6_and_local_names() {
+    for (address, url) in [
+        ("::1", "http://[::1]/"),
+        ("fe80::1", "http://[fe80::1]/"),
+        ("[::1]:8080", "http://[::1]:8080/"),
+        ("http://[::1]:8080", "http://[::1]:8080/"),
+        (
+            "https://[2001:db8::2]/deconz",
+            "https://[2001:db8::2]/deconz/",
+        ),
+        ("phoscon.local", "http://phoscon.local/"),
+        ("phoscon.local:8080", "http://phoscon.local:8080/"),
+    ] {
+        assert_eq!(deconz::gateway_url(address).unwrap().as_str(), url);
+    }
+    let discovered = deconz::DiscoveredGateway {
+        id: String::from("00212EFFFF012345"),
+        internal_ip: String::from("fe80::1"),
+        internal_port: 80,
+    };
+    assert_eq!(discovered.url(), "http://[fe80::1]:80");
+    // Without brackets the port can't be told apart from the address
+    assert!(matches!(
+        deconz::gateway_url("http://::1:8080"),
+        Err(Error::InvalidUrl(_))
+    ));
+}
+
+#[tokio::test]
+async fn host_names_are_resolved() {
+    let gateway = MockGateway::start().await;
+    let address = gateway.server.uri().replace("127.0.0.1", "localhost");
+    let client = DeconzClient::login_with_token(address, String::from(common::TOKEN)).unwrap();
+
+    assert_eq!(client.get_light_list().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn remove_light_from_group_writes_back_the_rest() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("groups/1")))
+        .and(body_json(json!({ "lights": ["1"] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let group = client.get_group_list().await.unwrap().remove(0);
+    let light = client.get_light_list().await.unwrap().remove(1);
+
+    client
+        .remove_light_from_group(&group, &light)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn lights_are_found_by_id_and_name() {
+    let gateway = MockGateway::start().await;
+
+    let lights = gateway.client().get_light_list().await.unwrap();
+
+    let hallway = lights.find_light_by_id(&LightId::from("0x1234")).unwrap();
+    assert_eq!(hallway.name, "Hallway");
+    assert_eq!(
+        lights.find_light_by_name("Desk lamp").unwrap().id,
+        LightId::from(2)
+    );
+    assert!(lights.find_light_by_id(&LightId::from(9)).is_none());
+}
+
+#[tokio::test]
+async fn raw_state_is_sent_as_is() {
+    let gateway = MockGateway::start().await;
+    let answer = json!([{ "success": { "/lights/1/state/speed": 3 } }]);
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "speed": 3 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(answer.clone()))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+
+    let resp = client
+        .set_raw_state(&light, json!({ "speed": 3 }))
+        .await
+        .unwrap();
+
+    assert_eq!(resp, answer);
+}
+
+#[tokio::test]
+async fn create_group_returns_the_new_id() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("POST"))
+        .and(path(MockGateway::api_path("groups")))
+        .and(body_json(json!({ "name": "Kitchen" })))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!([{ "success": { "id": "7" } }])),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+
+    let group = gateway.client().create_group("Kitchen").await.unwrap();
+
+    assert_eq!(group.id, 7);
+    assert_eq!(group.name, "Kitchen");
+    assert!(group.lights.is_empty());
+}
+
+#[tokio::test]
+async fn ping_measures_a_config_request() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("config")))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "name": "Phoscon-GW" }))
+                .set_delay(std::time::Duration::from_millis(50)),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+
+    let latency = gateway.client().ping().await.unwrap();
+
+    assert!(latency >= std::time::Duration::from_millis(50));
+}
+
+#[tokio::test]
+async fn connection_state_follows_the_requests() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("lights")))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let mut state = client.watch_connection_state();
+    assert_eq!(*state.borrow(), ConnectionState::Connected);
+
+    client.get_light_list().await.unwrap_err();
+
+    assert!(state.has_changed().unwrap());
+    assert!(matches!(
+        *state.borrow_and_update(),
+        ConnectionState::Disconnected(_)
+    ));
+
+    let mut light = client.get_light_list().await.unwrap().remove(0);
+
+    assert!(state.has_changed().unwrap());
+    assert_eq!(*state.borrow_and_update(), ConnectionState::Connected);
+
+    // Errors the gateway answers with don't change the state
+    light.id = LightId::from(9);
+    client.get_light_state(&light).await.unwrap_err();
+    assert!(!state.has_changed().unwrap());
+}
+
+#[tokio::test]
+async fn request_hook_sees_every_request() {
+    let gateway = MockGateway::start().await;
+    let metrics = Arc::new(Mutex::new(Vec::new()));
+    let client =
+        {
+            let metrics = metrics.clone();
+            gateway.client_with(DeconzClient::builder().on_request(
+                move |metric: &RequestMetric| metrics.lock().unwrap().push(metric.clone()),
+            ))
+        };
+
+    let mut light = client.get_light_list().await.unwrap().remove(0);
+    light.id = LightId::from(9);
+    client.get_light_state(&light).await.unwrap_err();
+
+    let metrics = metrics.lock().unwrap();
+    let requests: Vec<(String, Option<u16>)> = metrics
+        .iter()
+        .map(|metric| (metric.path.clone(), metric.status.map(|s| s.as_u16())))
+        .collect();
+    assert_eq!(
+        requests,
+        [
+            (String::from("/api/<token>/lights"), Some(200)),
+            (String::from("/api/<token>/lights/9"), Some(404)),
+        ]
+    );
+    assert!(metrics.iter().all(|metric| metric.method == "GET"));
+}
+
+#[tokio::test]
+async fn dry_run_only_reads() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&gateway.server)
+        .await;
+    let metrics = Arc::new(Mutex::new(Vec::new()));
+    let client =
+        {
+            let metrics = metrics.clone();
+            gateway.client_with(DeconzClient::builder().dry_run(true).on_request(
+                move |metric: &RequestMetric| metrics.lock().unwrap().push(metric.clone()),
+            ))
+        };
+
+    let light = client.get_light_list().await.unwrap().remove(0);
+    let applied = client.set_on_state(&light, true).await.unwrap();
+    assert_eq!(applied.on, Some(true));
+    let applied = client
+        .set_light_color(&light, Some(1000), Some(100), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        (applied.hue, applied.bri, applied.sat),
+        (Some(1000), Some(100), None)
+    );
+
+    let metrics = metrics.lock().unwrap();
+    let skipped = &metrics[1];
+    assert_eq!(skipped.method, "PUT");
+    assert_eq!(skipped.path, "/api/<token>/lights/1/state");
+    assert_eq!(skipped.status, None);
+    assert_eq!(skipped.body.as_deref(), Some(r#"{"on":true}"#));
+}
+
+#[tokio::test]
+async fn offline_queue_replays_the_last_state_once_the_gateway_is_back() {
+    let gateway = MockGateway::start().await;
+    // Answers too late for the client, as if the gateway was gone
+    Mock::given(method("PUT"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(2)))
+        .up_to_n_times(3)
+        .with_priority(1)
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "on": false, "xy": [0.5, 0.25] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("config")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "name": "Phoscon-GW" })))
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client_with(
+        DeconzClient::builder()
+            .timeout(std::time::Duration::from_millis(200))
+            .offline_queue(true),
+    );
+    let light = client.get_light_list().await.unwrap().remove(0);
+
+    assert!(client.set_on_state(&light, false).await.is_err());
+    assert!(client
+        .set_light_color(&light, Some(1000), None, Some(100))
+        .await
+        .is_err());
+    // Replaces the hue and saturation
+    assert!(client.set_light_xy(&light, 0.5, 0.25).await.is_err());
+    let queue = client.offline_queue().unwrap();
+    assert_eq!(queue.pending_lights(), vec![LightId::from(1)]);
+
+    client.ping().await.unwrap();
+
+    assert!(queue.is_empty());
+}
+
+#[tokio::test]
+async fn lights_are_turned_on_with_their_last_brightness() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "bri": 50 })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!([{ "success": { "/lights/1/state/bri": 50 } }])),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "on": true, "bri": 50 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client_with(DeconzClient::builder().restore_brightness(true));
+    let light = client.get_light_list().await.unwrap().remove(0);
+    assert_eq!(client.remembered_brightness(&light), None);
+
+    client.get_light_state(&light).await.unwrap();
+    assert_eq!(client.remembered_brightness(&light), Some(200));
+    client
+        .set_light_color(&light, None, Some(50), None)
+        .await
+        .unwrap();
+    client.set_on_state(&light, true).await.unwrap();
+}
+
+#[tokio::test]
+async fn remembered_brightness_is_within_range() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("lights/1")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Ceiling",
+            "state": { "on": false, "bri": 255, "reachable": true }
+        })))
+        .with_priority(1)
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "on": true, "bri": 254 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client_with(DeconzClient::builder().restore_brightness(true));
+    let light = client.get_light_list().await.unwrap().remove(0);
+
+    client.get_light_state(&light).await.unwrap();
+    client.set_on_state(&light, true).await.unwrap();
+}
+
+#[tokio::test]
+async fn whitelist_lists_the_apps_most_recently_used_first() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("config")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Phoscon-GW",
+            "swversion": "2.28.1",
+            "websocketport": 443,
+            "ipaddress": "192.168.0.2",
+            "whitelist": {
+                "old-token": {
+                    "name": "forgotten-app",
+                    "create date": "2019-02-01T10:00:00",
+                    "last use date": "2019-02-03T12:00:00"
+                },
+                "test-token": {
+                    "name": "deconz-client desktop#pc",
+                    "create date": "2024-05-01T09:30:00",
+                    "last use date": "2025-08-19T00:13:11"
+                }
+            }
+        })))
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path(MockGateway::api_path("config/whitelist/old-token")))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!([{ "success": "/config/whitelist/old-token deleted." }])),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+
+    let whitelist = client.get_whitelist().await.unwrap();
+
+    let tokens: Vec<&str> = whitelist.iter().map(|e| e.token.as_str()).collect();
+    assert_eq!(tokens, ["test-token", "old-token"]);
+    assert_eq!(whitelist[1].name, "forgotten-app");
+    assert_eq!(whitelist[1].last_use_date, "2019-02-03T12:00:00");
+    // The entries of the config carry their token as well
+    let config = client.get_config().await.unwrap();
+    assert_eq!(config.whitelist["old-token"].token, "old-token");
+    client.delete_token(&whitelist[1].token).await.unwrap();
+}
+
+#[tokio::test]
+async fn gateway_name_is_fetched_once() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("config")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Living Room",
+            "swversion": "2.28.1",
+            "apiversion": "1.16.0",
+            "ipaddress": "192.168.0.2",
+            "websocketport": 443
+        })))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+
+    assert_eq!(client.gateway_name().await.unwrap(), "Living Room");
+    assert_eq!(client.gateway_name().await.unwrap(), "Living Room");
+}
+
+#[tokio::test]
+async fn hue_step_sends_only_the_increment() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("config")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Living Room",
+            "swversion": "2.28.1",
+            "apiversion": "1.16.0",
+            "ipaddress": "192.168.0.2",
+            "websocketport": 443
+        })))
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "hue_inc": -4096 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+
+    client.step_hue(&light, -4096).await.unwrap();
+}
+
+#[tokio::test]
+async fn demo_hue_step_wraps_around() {
+    let client = DemoLightClient::new();
+    let light = client.get_light_list().await.unwrap().remove(0);
+    client
+        .set_light_color(&light, Some(65000), None, Some(100))
+        .await
+        .unwrap();
+
+    client.step_hue(&light, 1000).await.unwrap();
+    client.step_saturation(&light, 200).await.unwrap();
+
+    let state = client.get_light_state(&light).await.unwrap();
+    assert_eq!(state.hue, Some(464));
+    assert_eq!(state.sat, Some(254));
+}
+
+#[tokio::test]
+async fn demo_brightness_stays_in_range() {
+    let client = DemoLightClient::new();
+    let light = client.get_light_list().await.unwrap().remove(0);
+    assert_eq!(
+        client.get_light_state(&light).await.unwrap().bri,
+        Some(MAX_BRIGHTNESS)
+    );
+
+    client.step_brightness(&light, 100).await.unwrap();
+
+    let state = client.get_light_state(&light).await.unwrap();
+    assert_eq!(state.bri, Some(MAX_BRIGHTNESS));
+}
+
+#[tokio::test]
+async fn failing_light_does_not_stop_the_others() {
+    let gateway = MockGateway::start().await;
+    for id in ["1", "0x1234"] {
+        Mock::given(method("PUT"))
+            .and(path(MockGateway::api_path(&format!("lights/{}/state", id))))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+            .expect(1)
+            .mount(&gateway.server)
+            .await;
+    }
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/2/state")))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let lights = client.get_light_list().await.unwrap();
+    let lights: Vec<&deconz::Light> = lights.iter().collect();
+
+    let result = client
+        .set_lights_color(&lights, Some(8000), Some(200), Some(100))
+        .await;
+
+    let Err(Error::PartialFailure(failures)) = result else {
+        panic!("expected a partial failure, got {:?}", result);
+    };
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, LightId::from(2));
+}
+
+#[tokio::test]
+async fn full_config_is_saved_as_backup() {
+    let gateway = MockGateway::start().await;
+    let full_config = json!({
+        "config": { "name": "Phoscon-GW", "whitelist": {} },
+        "lights": { "1": { "name": "Ceiling" } },
+        "groups": {},
+        "scenes": {},
+        "schedules": {},
+        "rules": {},
+        "sensors": {}
+    });
+    Mock::given(method("GET"))
+        .and(path(format!("/api/{}", common::TOKEN)))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&full_config))
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let backup =
+        std::env::temp_dir().join(format!("deconz-test-backup-{}.json", std::process::id()));
+
+    assert_eq!(client.export_full_config().await.unwrap(), full_config);
+    client.save_backup(&backup).await.unwrap();
+
+    let saved = std::fs::read_to_string(&backup).unwrap();
+    std::fs::remove_file(&backup).unwrap();
+    assert!(saved.contains('\n'));
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&saved).unwrap(),
+        full_config
+    );
+}
+
+#[tokio::test]
+async fn rules_are_parsed_and_can_be_disabled() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("rules")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "3": {
+                "name": "Motion hallway",
+                "status": "enabled",
+                "owner": "a1b2c3",
+                "periodic": 0,
+                "timestriggered": 4,
+                "lasttriggered": "2020-11-22T21:03:12",
+                "conditions": [
+                    { "address": "/sensors/5/state/presence", "operator": "eq", "value": "true" },
+                    { "address": "/sensors/5/state/presence", "operator": "dx" }
+                ],
+                "actions": [
+                    { "address": "/groups/1/action", "method": "PUT", "body": { "on": true } }
+                ]
+            },
+            "1": {
+                "name": "Old rule",
+                "status": "disabled",
+                "timestriggered": 0,
+                "lasttriggered": "none",
+                "conditions": [],
+                "actions": []
+            }
+        })))
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("rules/3")))
+        .and(body_json(json!({ "status": "disabled" })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!([{ "success": { "/rules/3/status": "disabled" } }])),
+        )
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+
+    let rules = client.get_rules().await.unwrap();
+
+    assert_eq!(rules.len(), 2);
+    assert!(!rules[0].enabled);
+    assert_eq!(rules[0].last_triggered, None);
+    let motion = &rules[1];
+    assert_eq!(motion.name, "Motion hallway");
+    assert!(motion.enabled);
+    assert_eq!(motion.times_triggered, 4);
+    assert_eq!(
+        motion.conditions[0].to_string(),
+        "/sensors/5/state/presence eq true"
+    );
+    assert_eq!(
+        motion.conditions[1].to_string(),
+        "/sensors/5/state/presence dx"
+    );
+    assert_eq!(
+        motion.actions[0].to_string(),
+        r#"PUT /groups/1/action {"on":true}"#
+    );
+    client.set_rule_enabled(motion, false).await.unwrap();
+}
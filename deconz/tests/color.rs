@@ -0,0 +1,244 @@
+use deconz::{
+    bri_to_percent, bri_to_perceptual, color_roundtrip_error, contrasting_text_color,
+    deconz_hsv_to_rgb, percent_to_bri, perceptual_to_bri, rgb_to_deconz_hsv, rgb_to_xy, xy_to_rgb,
+    ColorGamut, MAX_BRIGHTNESS,
+};
+use palette::Srgb;
+
+fn assert_rgb_eq(actual: Srgb, expected: Srgb) {
+    let close = |a: f32, b: f32| (a - b).abs() < 0.01;
+    assert!(
+        close(actual.red, expected.red)
+            && close(actual.green, expected.green)
+            && close(actual.blue, expected.blue),
+        "{:?} != {:?}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn primary_colors_to_deconz_hsv() {
+    assert_eq!(rgb_to_deconz_hsv(Srgb::new(1.0, 0.0, 0.0)), (0, 254, 254));
+    assert_eq!(
+        rgb_to_deconz_hsv(Srgb::new(0.0, 1.0, 0.0)),
+        (21845, 254, 254)
+    );
+    assert_eq!(
+        rgb_to_deconz_hsv(Srgb::new(0.0, 0.0, 1.0)),
+        (43690, 254, 254)
+    );
+}
+
+#[test]
+fn white_and_black_to_deconz_hsv() {
+    assert_eq!(rgb_to_deconz_hsv(Srgb::new(1.0, 1.0, 1.0)), (0, 0, 254));
+    assert_eq!(rgb_to_deconz_hsv(Srgb::new(0.0, 0.0, 0.0)), (0, 0, 0));
+}
+
+#[test]
+fn both_ends_of_the_hue_scale_are_red() {
+    let red = Srgb::new(1.0, 0.0, 0.0);
+    assert_rgb_eq(deconz_hsv_to_rgb(0, 255, 255), red);
+    assert_rgb_eq(deconz_hsv_to_rgb(u16::MAX, 255, 255), red);
+}
+
+#[test]
+fn saturation_and_brightness_bounds() {
+    assert_rgb_eq(deconz_hsv_to_rgb(12345, 0, 255), Srgb::new(1.0, 1.0, 1.0));
+    assert_rgb_eq(deconz_hsv_to_rgb(12345, 255, 0), Srgb::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn deconz_hsv_round_trip() {
+    for (hue, sat, bri) in [
+        (0, 254, 254),
+        (10000, 128, 200),
+        (43690, 254, 1),
+        (65000, 40, 254),
+    ] {
+        let (h, s, b) = rgb_to_deconz_hsv(deconz_hsv_to_rgb(hue, sat, bri));
+        // A single step of 0-255 in rgb covers a few hundred steps of the hue scale
+        assert!(
+            (h as i32 - hue as i32).abs() <= 300 || bri < 10,
+            "hue {} != {}",
+            h,
+            hue
+        );
+        assert!((s as i32 - sat as i32).abs() <= 1, "sat {} != {}", s, sat);
+        assert!((b as i32 - bri as i32).abs() <= 1, "bri {} != {}", b, bri);
+    }
+}
+
+#[test]
+fn primary_colors_survive_the_round_trip() {
+    for rgb in [
+        Srgb::new(1.0, 0.0, 0.0),
+        Srgb::new(0.0, 1.0, 0.0),
+        Srgb::new(0.0, 0.0, 1.0),
+        Srgb::new(1.0, 1.0, 0.0),
+        Srgb::new(0.0, 1.0, 1.0),
+        Srgb::new(1.0, 0.0, 1.0),
+        Srgb::new(1.0, 1.0, 1.0),
+    ] {
+        let error = color_roundtrip_error(rgb);
+        // Hardly noticeable, the cap of saturation and brightness at 254 costs the most
+        assert!(error < 1.0, "{:?} drifts by {}", rgb, error);
+    }
+}
+
+#[test]
+fn dim_colors_survive_the_round_trip() {
+    for rgb in [Srgb::new(0.2, 0.05, 0.1), Srgb::new(0.5, 0.4, 0.3)] {
+        let error = color_roundtrip_error(rgb);
+        assert!(error < 1.0, "{:?} drifts by {}", rgb, error);
+    }
+}
+
+#[test]
+fn text_on_light_colors_is_black_and_on_dark_ones_white() {
+    let black = Srgb::new(0.0, 0.0, 0.0);
+    let white = Srgb::new(1.0, 1.0, 1.0);
+    assert_eq!(contrasting_text_color(white), black);
+    assert_eq!(contrasting_text_color(Srgb::new(1.0, 1.0, 0.0)), black);
+    assert_eq!(contrasting_text_color(Srgb::new(0.0, 1.0, 0.0)), black);
+    // Full red is just bright enough for black to win
+    assert_eq!(contrasting_text_color(Srgb::new(1.0, 0.0, 0.0)), black);
+    assert_eq!(contrasting_text_color(black), white);
+    assert_eq!(contrasting_text_color(Srgb::new(0.0, 0.0, 1.0)), white);
+}
+
+#[test]
+fn text_color_flips_at_the_wcag_threshold() {
+    // Gray has the same contrast with black and white at a luminance of about 0.179,
+    // which is about 0.46 in sRGB
+    let black = Srgb::new(0.0, 0.0, 0.0);
+    let white = Srgb::new(1.0, 1.0, 1.0);
+    assert_eq!(
+        contrasting_text_color(Srgb::new(0.455, 0.455, 0.455)),
+        white
+    );
+    assert_eq!(
+        contrasting_text_color(Srgb::new(0.465, 0.465, 0.465)),
+        black
+    );
+}
+
+#[test]
+fn white_is_the_d65_white_point() {
+    let [x, y] = rgb_to_xy(Srgb::new(1.0, 1.0, 1.0));
+    assert!((x - 0.3127).abs() < 0.001 && (y - 0.3290).abs() < 0.001);
+}
+
+#[test]
+fn xy_round_trip() {
+    for rgb in [
+        Srgb::new(1.0, 0.0, 0.0),
+        Srgb::new(0.0, 1.0, 0.0),
+        Srgb::new(0.0, 0.0, 1.0),
+        Srgb::new(1.0, 1.0, 1.0),
+        Srgb::new(1.0, 0.5, 0.0),
+    ] {
+        assert_rgb_eq(xy_to_rgb(rgb_to_xy(rgb), 255), rgb);
+    }
+}
+
+#[test]
+fn xy_brightness_scales_the_color() {
+    assert_rgb_eq(xy_to_rgb([0.3127, 0.3290], 0), Srgb::new(0.0, 0.0, 0.0));
+    let half = xy_to_rgb([0.3127, 0.3290], 128);
+    assert!((half.red - 128.0 / 255.0).abs() < 0.01);
+}
+
+#[test]
+fn brightness_percent_bounds() {
+    assert_eq!(percent_to_bri(100.0), MAX_BRIGHTNESS);
+    assert_eq!(percent_to_bri(150.0), MAX_BRIGHTNESS);
+    assert_eq!(percent_to_bri(0.0), 0);
+    assert_eq!(percent_to_bri(-5.0), 0);
+    assert_eq!(percent_to_bri(0.1), 1);
+    assert_eq!(bri_to_percent(MAX_BRIGHTNESS), 100.0);
+    assert_eq!(bri_to_percent(255), 100.0);
+    assert_eq!(bri_to_percent(0), 0.0);
+}
+
+#[test]
+fn brightness_percent_round_trip() {
+    for bri in 0..=MAX_BRIGHTNESS {
+        assert_eq!(percent_to_bri(bri_to_percent(bri)), bri);
+    }
+}
+
+#[test]
+fn perceptual_brightness_endpoints() {
+    assert_eq!(perceptual_to_bri(0.0), 0);
+    assert_eq!(perceptual_to_bri(-0.5), 0);
+    assert_eq!(perceptual_to_bri(0.001), 1);
+    assert_eq!(perceptual_to_bri(1.0), MAX_BRIGHTNESS);
+    assert_eq!(perceptual_to_bri(2.0), MAX_BRIGHTNESS);
+    assert_eq!(bri_to_perceptual(0), 0.0);
+    assert_eq!(bri_to_perceptual(MAX_BRIGHTNESS), 1.0);
+    assert_eq!(bri_to_perceptual(255), 1.0);
+    // Half of the perceived brightness is far less than half of the light output
+    assert!(perceptual_to_bri(0.5) < MAX_BRIGHTNESS / 4);
+}
+
+#[test]
+fn perceptual_brightness_is_monotonic() {
+    let mut last = 0;
+    for step in 0..=1000 {
+        let bri = perceptual_to_bri(step as f32 / 1000.0);
+        assert!(
+            bri >= last,
+            "{} is darker than {} at step {}",
+            bri,
+            last,
+            step
+        );
+        last = bri;
+    }
+
+    for bri in 1..=MAX_BRIGHTNESS {
+        assert!(bri_to_perceptual(bri) > bri_to_perceptual(bri - 1));
+    }
+}
+
+#[test]
+fn perceptual_brightness_round_trip() {
+    for bri in 0..=MAX_BRIGHTNESS {
+        assert_eq!(perceptual_to_bri(bri_to_perceptual(bri)), bri);
+    }
+}
+
+/// The gamut of Philips Hue bulbs like the LCT015
+const GAMUT_C: ColorGamut = ColorGamut {
+    red: [0.6915, 0.3083],
+    green: [0.17, 0.7],
+    blue: [0.1532, 0.0475],
+};
+
+#[test]
+fn colors_inside_the_gamut_are_kept() {
+    assert!(GAMUT_C.contains(0.3, 0.3));
+    assert_eq!(GAMUT_C.clamp_to_gamut(0.3, 0.3), (0.3, 0.3));
+    assert_eq!(GAMUT_C.clamp_to_gamut(0.17, 0.7), (0.17, 0.7));
+}
+
+#[test]
+fn colors_outside_the_gamut_move_to_the_closest_edge() {
+    let close = |(x, y): (f32, f32), expected: (f32, f32)| {
+        assert!(
+            (x - expected.0).abs() < 0.001 && (y - expected.1).abs() < 0.001,
+            "{:?} != {:?}",
+            (x, y),
+            expected
+        );
+    };
+
+    // Beyond the red corner
+    close(GAMUT_C.clamp_to_gamut(0.8, 0.25), (0.6915, 0.3083));
+    // Below the edge between blue and red
+    let clamped = GAMUT_C.clamp_to_gamut(0.4, 0.1);
+    close(clamped, (0.3737, 0.1543));
+    assert!(GAMUT_C.contains(clamped.0, clamped.1));
+}
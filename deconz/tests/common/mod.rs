@@ -0,0 +1,99 @@
+//! A mock gateway for testing `DeconzClient` without real hardware
+
+use deconz::{DeconzClient, DeconzClientBuilder};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+pub const TOKEN: &str = "test-token";
+
+/// A mock http server answering like a gateway with three lights and one group
+///
+/// One of the lights has a non-numeric id like those of some third-party bridges.
+pub struct MockGateway {
+    pub server: MockServer,
+}
+
+impl MockGateway {
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path(format!("/api/{}/lights", TOKEN)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "0x1234": {
+                    "name": "Hallway",
+                    "type": "On/Off plug-in unit",
+                    "state": { "on": false, "reachable": true }
+                },
+                "2": {
+                    "name": "Desk lamp",
+                    "type": "Dimmable light",
+                    "modelid": "FLS-PP3",
+                    "manufacturername": "dresden elektronik",
+                    "lastseen": null,
+                    "state": { "on": false, "bri": 120, "reachable": false }
+                },
+                "1": {
+                    "name": "Ceiling",
+                    "type": "Extended color light",
+                    "modelid": "LCT015",
+                    "manufacturername": "Signify Netherlands B.V.",
+                    "capabilities": {
+                        "alerts": ["none", "select", "lselect", "blink"],
+                        "bri": { "min_dim_level": 0.1 },
+                        "color": { "effects": ["none", "colorloop", "candle"] }
+                    },
+                    "lastseen": "2020-11-22T11:27Z",
+                    "state": {
+                        "on": true, "bri": 200, "hue": 1000, "sat": 50, "ct": 300,
+                        "xy": [0.3, 0.3], "reachable": true
+                    }
+                }
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!("/api/{}/lights/1", TOKEN)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "name": "Ceiling",
+                "type": "Extended color light",
+                "state": {
+                    "on": true, "bri": 200, "hue": 1000, "sat": 50, "ct": 300,
+                    "xy": [0.3, 0.3], "effect": "none", "colormode": "ct", "reachable": true
+                }
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!("/api/{}/groups", TOKEN)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "1": { "name": "Living room", "lights": ["1", "2"] }
+            })))
+            .mount(&server)
+            .await;
+
+        MockGateway { server }
+    }
+
+    /// A client logged in to the mock gateway
+    pub fn client(&self) -> DeconzClient {
+        self.client_with(DeconzClient::builder())
+    }
+
+    /// A client configured by `builder` logged in to the mock gateway
+    pub fn client_with(&self, builder: DeconzClientBuilder) -> DeconzClient {
+        builder
+            .login_with_token(self.server.uri(), String::from(TOKEN))
+            .unwrap()
+    }
+
+    /// The path of an endpoint below `api/<token>/`
+    pub fn api_path(endpoint: &str) -> String {
+        format!("/api/{}/{}", TOKEN, endpoint)
+    }
+}
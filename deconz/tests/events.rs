@@ -0,0 +1,264 @@
+mod common;
+
+use std::time::Duration;
+
+use common::MockGateway;
+use deconz::{
+    ButtonAction, ButtonEvent, ColorMode, DeconzClient, LightClient, LightEvent, LightId,
+    LightState, LightStateChange, SensorReading,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::{handshake::server::Request, Message};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+/// Serves a websocket which pushes the events of each connection in turn and then closes it
+///
+/// The config of the mock gateway points the client to it. After the last connection the
+/// websocket refuses connections.
+async fn serve_events(gateway: &MockGateway, connections: Vec<Vec<serde_json::Value>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("config")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Phoscon-GW",
+            "swversion": "2.28.1",
+            "ipaddress": "127.0.0.1",
+            "websocketport": listener.local_addr().unwrap().port()
+        })))
+        .mount(&gateway.server)
+        .await;
+
+    tokio::spawn(async move {
+        for events in connections {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            for event in events {
+                socket.send(Message::text(event.to_string())).await.unwrap();
+            }
+            socket.close(None).await.unwrap();
+        }
+    });
+}
+
+fn light_state() -> LightState {
+    LightState::builder()
+        .on(true)
+        .hue(1000)
+        .bri(200)
+        .sat(50)
+        .color_mode(ColorMode::Hs)
+        .build()
+}
+
+#[test]
+fn equal_states_have_no_change() {
+    assert!(LightStateChange::between(&light_state(), &light_state()).is_none());
+}
+
+#[test]
+fn change_only_has_the_differing_fields() {
+    let mut new = light_state();
+    new.on = false;
+    new.bri = Some(10);
+
+    let change = LightStateChange::between(&light_state(), &new).unwrap();
+
+    assert_eq!(change.on, Some(false));
+    assert_eq!(change.bri, Some(10));
+    assert_eq!(change.hue, None);
+    assert_eq!(change.reachable, None);
+}
+
+#[tokio::test]
+async fn polling_emits_changed_states() {
+    let gateway = MockGateway::start().await;
+    for id in ["2", "0x1234"] {
+        Mock::given(method("GET"))
+            .and(path(MockGateway::api_path(&format!("lights/{}", id))))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "state": { "on": false, "reachable": true }
+            })))
+            .mount(&gateway.server)
+            .await;
+    }
+    // The ceiling light is off during the first poll and on afterwards
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("lights/1")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "state": {
+                "on": false, "bri": 200, "hue": 1000, "sat": 50, "ct": 300,
+                "xy": [0.3, 0.3], "effect": "none", "colormode": "ct", "reachable": true
+            }
+        })))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+
+    let events = client.watch_light_states(Duration::from_millis(10));
+    let mut events = std::pin::pin!(events);
+    let event = events.next().await.unwrap().unwrap();
+
+    let LightEvent::StateChanged { id, change } = event else {
+        panic!("Expected a state change, got {:?}", event);
+    };
+    assert_eq!(id, LightId::from(1));
+    assert_eq!(change.on, Some(true));
+    assert_eq!(change.bri, None);
+}
+
+#[tokio::test]
+async fn sensor_events_are_parsed() {
+    let gateway = MockGateway::start().await;
+    serve_events(
+        &gateway,
+        vec![vec![
+            json!({ "t": "event", "e": "changed", "r": "lights", "id": "1", "state": { "on": true } }),
+            json!({ "t": "event", "e": "changed", "r": "sensors", "id": "4", "config": { "battery": 90 } }),
+            json!({ "t": "event", "e": "changed", "r": "sensors", "id": "3", "state": { "temperature": 2150 } }),
+        ]],
+    )
+    .await;
+    let client = gateway.client_with(DeconzClient::builder().event_reconnects(Some(0)));
+
+    let events: Vec<_> = client.subscribe_sensor_events().collect().await;
+
+    assert_eq!(events.len(), 1);
+    let event = events[0].as_ref().unwrap();
+    assert_eq!(event.id, 3);
+    assert!(matches!(event.reading, SensorReading::Temperature(t) if t == 21.5));
+}
+
+#[tokio::test]
+async fn presence_events_are_filtered() {
+    let gateway = MockGateway::start().await;
+    serve_events(
+        &gateway,
+        vec![vec![
+            json!({ "t": "event", "e": "changed", "r": "sensors", "id": "3", "state": { "temperature": 2150 } }),
+            json!({ "t": "event", "e": "changed", "r": "sensors", "id": "5", "state": { "presence": true, "lastupdated": "2020-11-22T11:27:19.123" } }),
+            json!({ "t": "event", "e": "changed", "r": "sensors", "id": "6", "state": { "buttonevent": 1002 } }),
+        ]],
+    )
+    .await;
+    let client = gateway.client_with(DeconzClient::builder().event_reconnects(Some(0)));
+
+    let events: Vec<_> = client.subscribe_presence_events().collect().await;
+
+    assert_eq!(events.len(), 1);
+    let event = events[0].as_ref().unwrap();
+    assert_eq!(event.id, 5);
+    assert!(matches!(event.reading, SensorReading::Presence(true)));
+}
+
+#[test]
+fn button_events_are_decoded() {
+    let decoded = |code| ButtonEvent::from_code(code);
+    assert_eq!(
+        decoded(1002),
+        ButtonEvent {
+            button: 1,
+            action: ButtonAction::ShortRelease
+        }
+    );
+    assert_eq!(decoded(4001).action, ButtonAction::Hold);
+    assert_eq!(decoded(2003).action, ButtonAction::LongRelease);
+    assert_eq!(decoded(3004).action, ButtonAction::DoublePress);
+    assert_eq!(decoded(1099).action, ButtonAction::Other(1099));
+    // Without a button in the thousands the event can't be decoded
+    assert_eq!(
+        decoded(7),
+        ButtonEvent {
+            button: 0,
+            action: ButtonAction::Other(7)
+        }
+    );
+}
+
+#[tokio::test]
+async fn button_events_are_filtered() {
+    let gateway = MockGateway::start().await;
+    serve_events(
+        &gateway,
+        vec![vec![
+            json!({ "t": "event", "e": "changed", "r": "sensors", "id": "5", "state": { "presence": true } }),
+            json!({ "t": "event", "e": "changed", "r": "sensors", "id": "6", "state": { "buttonevent": 2002 } }),
+        ]],
+    )
+    .await;
+    let client = gateway.client_with(DeconzClient::builder().event_reconnects(Some(0)));
+
+    let events: Vec<_> = client.subscribe_button_events().collect().await;
+
+    assert_eq!(events.len(), 1);
+    let (sensor, event) = events[0].as_ref().unwrap();
+    assert_eq!(*sensor, 6);
+    assert_eq!(event.button, 2);
+    assert_eq!(event.action, ButtonAction::ShortRelease);
+}
+
+#[tokio::test]
+async fn dropped_socket_is_reconnected() {
+    let gateway = MockGateway::start().await;
+    let turned_on =
+        json!({ "t": "event", "e": "changed", "r": "lights", "id": "1", "state": { "on": true } });
+    serve_events(&gateway, vec![vec![turned_on.clone()], vec![turned_on]]).await;
+    let client = gateway.client_with(DeconzClient::builder().event_reconnects(Some(1)));
+
+    let events: Vec<_> = client.subscribe_events().collect().await;
+
+    assert_eq!(events.len(), 4);
+    assert!(matches!(events[0], Ok(LightEvent::StateChanged { .. })));
+    assert!(matches!(events[1], Ok(LightEvent::Reconnected)));
+    assert!(matches!(events[2], Ok(LightEvent::StateChanged { .. })));
+    // The websocket is gone after the second connection, so the client gives up
+    assert!(events[3].is_err());
+}
+
+#[tokio::test]
+async fn websocket_keeps_the_host_name() {
+    let gateway = MockGateway::start().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("config")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "name": "Phoscon-GW",
+            "swversion": "2.28.1",
+            "ipaddress": "127.0.0.1",
+            "websocketport": port
+        })))
+        .mount(&gateway.server)
+        .await;
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut host = None;
+        // The signature of the callback is given by tungstenite
+        #[allow(clippy::result_large_err)]
+        let socket = tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response| {
+            host = request.headers().get("host").cloned();
+            Ok(response)
+        })
+        .await
+        .unwrap();
+        drop(socket);
+        host
+    });
+    // Named like a gateway behind a reverse proxy, which needs the name to route the request
+    let url = gateway.server.uri().replace("127.0.0.1", "localhost");
+    let client = DeconzClient::builder()
+        .event_reconnects(Some(0))
+        .login_with_token(url, String::from(common::TOKEN))
+        .unwrap();
+
+    let _: Vec<_> = client.subscribe_events().collect().await;
+
+    let host = server.await.unwrap().unwrap();
+    assert_eq!(host.to_str().unwrap(), format!("localhost:{}", port));
+}
@@ -0,0 +1,95 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use deconz::{DeconzClientBuilder, Light, LightCapabilities, LightClient, LightId};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Answers every request on a connection with an empty success body, keeping the connection open
+async fn serve_connection(mut stream: TcpStream) {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 1024];
+    loop {
+        let Some(header_end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+            match stream.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            }
+            continue;
+        };
+
+        let headers = String::from_utf8_lossy(&buf[..header_end]).to_lowercase();
+        let content_length = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map(|len| len.trim().parse::<usize>().unwrap())
+            .unwrap_or_default();
+        let request_end = header_end + 4 + content_length;
+        if buf.len() < request_end {
+            match stream.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            }
+            continue;
+        }
+        buf.drain(..request_end);
+
+        let response =
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 2\r\n\r\n[]";
+        if stream.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[tokio::test]
+async fn sequential_requests_reuse_one_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    let accepted = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            accepted.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(serve_connection(stream));
+        }
+    });
+
+    let client = DeconzClientBuilder::new()
+        .pool_max_idle_per_host(1)
+        .login_with_token(url, String::from("token"))
+        .unwrap();
+    let light = Light {
+        name: String::from("Ceiling"),
+        id: LightId::from(1),
+        capabilities: LightCapabilities {
+            dimmable: true,
+            color: true,
+            color_temp: true,
+            min_bri: 0,
+            gradient: false,
+            effects: vec![],
+            alerts: vec![],
+            gamut: None,
+        },
+        light_type: None,
+        model_id: None,
+        manufacturer: None,
+        last_seen: None,
+        last_announced: None,
+    };
+
+    for i in 0..50 {
+        client
+            .set_light_color(&light, Some(i * 1000), Some(200), Some(100))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
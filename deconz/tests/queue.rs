@@ -0,0 +1,133 @@
+mod common;
+
+use std::time::Duration;
+
+use common::MockGateway;
+use deconz::{ColorCommand, CommandOutcome, LightClient, LightCommandQueue};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_json, method, path},
+    Mock, ResponseTemplate,
+};
+
+#[tokio::test]
+async fn newer_command_supersedes_one_in_flight() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!([]))
+                .set_delay(Duration::from_millis(200)),
+        )
+        .expect(2)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+    let queue = LightCommandQueue::new(Duration::from_millis(10));
+
+    let older = ColorCommand {
+        bri: Some(10),
+        ..Default::default()
+    };
+    let newer = ColorCommand {
+        hue: Some(1000),
+        ..Default::default()
+    };
+    let (older_outcome, newer_outcome) =
+        tokio::join!(queue.set_color(&client, &light, older), async {
+            // Queue the newer command while the older one is being sent
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            queue.set_color(&client, &light, newer).await
+        });
+
+    assert_eq!(older_outcome.unwrap(), CommandOutcome::Superseded);
+    assert_eq!(newer_outcome.unwrap(), CommandOutcome::Sent(newer));
+}
+
+#[tokio::test]
+async fn waiting_commands_are_merged() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(2)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+    let queue = LightCommandQueue::new(Duration::from_millis(100));
+
+    let first = ColorCommand {
+        bri: Some(10),
+        ..Default::default()
+    };
+    let second = ColorCommand {
+        hue: Some(1000),
+        ..Default::default()
+    };
+    let third = ColorCommand {
+        sat: Some(20),
+        ..Default::default()
+    };
+    let (first, second, third) = tokio::join!(
+        queue.set_color(&client, &light, first),
+        queue.set_color(&client, &light, second),
+        queue.set_color(&client, &light, third),
+    );
+
+    // The first command is sent right away, but its response is outdated by the others
+    assert_eq!(first.unwrap(), CommandOutcome::Superseded);
+    assert_eq!(second.unwrap(), CommandOutcome::Superseded);
+    let CommandOutcome::Sent(sent) = third.unwrap() else {
+        panic!("The last command wasn't sent");
+    };
+    assert_eq!(sent.hue, Some(1000));
+    assert_eq!(sent.sat, Some(20));
+}
+
+#[tokio::test]
+async fn failed_superseded_command_is_sent_with_the_newer_one() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .respond_with(ResponseTemplate::new(500).set_delay(Duration::from_millis(200)))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(MockGateway::api_path("lights/1/state")))
+        .and(body_json(json!({ "hue": 1000, "bri": 20 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .expect(1)
+        .mount(&gateway.server)
+        .await;
+    let client = gateway.client();
+    let light = client.get_light_list().await.unwrap().remove(0);
+    let queue = LightCommandQueue::new(Duration::from_millis(10));
+
+    let color = ColorCommand {
+        hue: Some(1000),
+        bri: Some(10),
+        ..Default::default()
+    };
+    let brightness = ColorCommand {
+        bri: Some(20),
+        ..Default::default()
+    };
+    let (color_outcome, brightness_outcome) =
+        tokio::join!(queue.set_color(&client, &light, color), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            queue.set_color(&client, &light, brightness).await
+        });
+
+    // The hue of the failed command isn't lost
+    assert_eq!(color_outcome.unwrap(), CommandOutcome::Superseded);
+    let CommandOutcome::Sent(sent) = brightness_outcome.unwrap() else {
+        panic!("The newer command wasn't sent");
+    };
+    assert_eq!(sent.hue, Some(1000));
+    assert_eq!(sent.bri, Some(20));
+}
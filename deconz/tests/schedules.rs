@@ -0,0 +1,114 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{MockGateway, TOKEN};
+use deconz::{CommandMethod, Date, LightClient, Repeat, ScheduleTime, TimeOfDay, Weekdays};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+#[tokio::test]
+async fn local_time_is_preferred_over_utc() {
+    let gateway = MockGateway::start().await;
+    Mock::given(method("GET"))
+        .and(path(MockGateway::api_path("schedules")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "2": {
+                "name": "Wake up",
+                "command": {
+                    "address": format!("/api/{}/groups/1/action", TOKEN),
+                    "method": "PUT",
+                    "body": { "on": true }
+                },
+                "time": "W124/T05:30:00",
+                "localtime": "W124/T06:30:00",
+                "status": "enabled"
+            },
+            "1": {
+                "name": "Old gateway",
+                "command": {
+                    "address": format!("/api/{}/lights/1/state", TOKEN),
+                    "method": "PUT",
+                    "body": { "on": false }
+                },
+                "time": "PT00:10:00",
+                "status": "disabled",
+                "autodelete": true
+            }
+        })))
+        .mount(&gateway.server)
+        .await;
+
+    let schedules = gateway.client().get_schedules().await.unwrap();
+
+    assert_eq!(schedules[0].id, 1);
+    assert_eq!(
+        schedules[0].time,
+        ScheduleTime::Timer {
+            duration: Duration::from_secs(600),
+            repeat: Repeat::Once
+        }
+    );
+    assert!(!schedules[0].enabled);
+    assert_eq!(schedules[1].command.address, "groups/1/action");
+    assert_eq!(schedules[1].command.method, CommandMethod::Put);
+    assert_eq!(
+        schedules[1].time,
+        ScheduleTime::Weekly {
+            days: Weekdays::WORKDAYS,
+            time: TimeOfDay {
+                hour: 6,
+                minute: 30,
+                second: 0
+            }
+        }
+    );
+}
+
+#[test]
+fn schedule_times_round_trip() {
+    let times = [
+        (
+            "2020-11-22T07:05:00",
+            ScheduleTime::At {
+                date: Date {
+                    year: 2020,
+                    month: 11,
+                    day: 22,
+                },
+                time: TimeOfDay {
+                    hour: 7,
+                    minute: 5,
+                    second: 0,
+                },
+            },
+        ),
+        (
+            "R/PT00:01:30",
+            ScheduleTime::Timer {
+                duration: Duration::from_secs(90),
+                repeat: Repeat::Forever,
+            },
+        ),
+        (
+            "R03/PT300:00:00",
+            ScheduleTime::Timer {
+                duration: Duration::from_secs(300 * 3600),
+                repeat: Repeat::Times(3),
+            },
+        ),
+        (
+            "W127/T22:00:00A00:30:00",
+            ScheduleTime::Other(String::from("W127/T22:00:00A00:30:00")),
+        ),
+    ];
+
+    for (raw, time) in times {
+        let parsed: ScheduleTime = serde_json::from_value(json!(raw)).unwrap();
+        assert_eq!(parsed, time);
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), json!(raw));
+    }
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "chrono")]
+
+use chrono::{TimeZone, Utc};
+use deconz::Timestamp;
+
+#[test]
+fn last_seen_has_minute_precision() {
+    let timestamp = Timestamp::from(String::from("2020-11-22T11:27Z"));
+
+    assert_eq!(
+        timestamp.to_utc(),
+        Some(Utc.with_ymd_and_hms(2020, 11, 22, 11, 27, 0).unwrap())
+    );
+}
+
+#[test]
+fn last_announced_has_seconds() {
+    let timestamp = Timestamp::from(String::from("2020-11-22T10:59:19Z"));
+
+    assert_eq!(
+        timestamp.to_utc(),
+        Some(Utc.with_ymd_and_hms(2020, 11, 22, 10, 59, 19).unwrap())
+    );
+}
+
+#[test]
+fn local_time_is_parsed() {
+    let timestamp = Timestamp::from(String::from("2020-11-22T12:27:19"));
+
+    assert!(timestamp.to_utc().is_some());
+}
+
+#[test]
+fn unknown_format_is_none() {
+    let timestamp = Timestamp::from(String::from("yesterday"));
+
+    assert_eq!(timestamp.to_utc(), None);
+    assert_eq!(timestamp.elapsed(), None);
+}
+
+#[test]
+fn past_timestamp_has_elapsed() {
+    let timestamp = Timestamp::from(String::from("2020-11-22T11:27Z"));
+
+    assert!(timestamp.elapsed().unwrap().as_secs() > 0);
+}
+
+#[test]
+fn whitelist_dates_are_utc() {
+    let entry: deconz::WhitelistEntry = serde_json::from_value(serde_json::json!({
+        "name": "Phoscon#B1920x1080",
+        "create date": "2020-11-22T10:59:19",
+        "last use date": "2020-11-23T08:01:02"
+    }))
+    .unwrap();
+
+    assert_eq!(
+        entry.created(),
+        Some(Utc.with_ymd_and_hms(2020, 11, 22, 10, 59, 19).unwrap())
+    );
+    assert_eq!(
+        entry.last_used(),
+        Some(Utc.with_ymd_and_hms(2020, 11, 23, 8, 1, 2).unwrap())
+    );
+    assert!(entry.unused_for().is_some());
+}
@@ -0,0 +1,57 @@
+use std::time::{Duration, SystemTime};
+
+use deconz::{DemoLightClient, LightClient, LightId, LightUsageTracker};
+use futures_util::StreamExt;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+#[test]
+fn adds_up_the_on_periods() {
+    let tracker = LightUsageTracker::new();
+    let light = LightId::from("1");
+    let now = SystemTime::now();
+
+    tracker.record_at(&light, true, now - 5 * HOUR);
+    tracker.record_at(&light, true, now - 4 * HOUR);
+    tracker.record_at(&light, false, now - 3 * HOUR);
+    tracker.record_at(&light, false, now - 2 * HOUR);
+
+    assert_eq!(tracker.on_duration(&light), 2 * HOUR);
+    assert_eq!(tracker.on_duration(&LightId::from("2")), Duration::ZERO);
+}
+
+#[test]
+fn running_period_is_kept_when_stored() {
+    let tracker = LightUsageTracker::new();
+    let light = LightId::from("1");
+    tracker.record_at(&light, true, SystemTime::now() - HOUR);
+
+    let json = serde_json::to_string(&tracker).unwrap();
+    let restored: LightUsageTracker = serde_json::from_str(&json).unwrap();
+
+    let on_time = restored.on_duration(&light);
+    assert!(on_time >= HOUR && on_time < HOUR + Duration::from_secs(60));
+    // The light counts as off until it is recorded again
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(restored.on_duration(&light), on_time);
+}
+
+#[tokio::test]
+async fn counts_the_events_of_the_demo_client() {
+    let client = DemoLightClient::new();
+    let tracker = LightUsageTracker::new();
+    let light = client.get_light_list().await.unwrap().remove(0);
+    client.set_on_state(&light, false).await.unwrap();
+    let mut events = std::pin::pin!(client.subscribe_events());
+
+    client.set_on_state(&light, true).await.unwrap();
+    tracker.record_event(&events.next().await.unwrap().unwrap());
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    client.set_on_state(&light, false).await.unwrap();
+    tracker.record_event(&events.next().await.unwrap().unwrap());
+
+    let on_time = tracker.on_duration(&light.id);
+    assert!(on_time >= Duration::from_millis(20));
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(tracker.on_duration(&light.id), on_time);
+}
@@ -0,0 +1,44 @@
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, File},
+    path::PathBuf,
+};
+
+use deconz::{Light, LightId, LightState};
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+
+/// The last known lights of a gateway, used to show something before the gateway answers
+#[derive(Serialize, Deserialize)]
+pub struct LightCache {
+    /// The url of the gateway the lights belong to
+    pub gateway: String,
+    pub lights: Vec<Light>,
+    /// The last known state of each light, keyed by light id
+    pub states: HashMap<LightId, LightState>,
+    /// The light that was selected, which is selected again on the next start
+    #[serde(default)]
+    pub selected: Option<LightId>,
+}
+
+fn cache_file_path() -> PathBuf {
+    glib::user_cache_dir()
+        .join("deconz-client")
+        .join("lights.json")
+}
+
+/// Loads the cached lights if they belong to the given gateway
+pub fn load_cache(gateway: &str) -> Option<LightCache> {
+    File::open(cache_file_path())
+        .ok()
+        .and_then(|file| serde_json::from_reader::<_, LightCache>(file).ok())
+        .filter(|cache| cache.gateway == gateway)
+}
+
+/// Stores the lights, failing silently as the cache is only a nicety
+pub fn store_cache(cache: &LightCache) {
+    _ = create_dir_all(cache_file_path().parent().unwrap());
+    if let Ok(file) = File::create(cache_file_path()) {
+        _ = serde_json::to_writer(file, cache);
+    }
+}
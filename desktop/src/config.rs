@@ -0,0 +1,255 @@
+use std::{
+    fs::{create_dir, remove_file, File, OpenOptions},
+    path::PathBuf,
+};
+
+use deconz::{DeconzClient, LightId};
+use gtk::glib;
+use serde::{Deserialize, Serialize};
+
+/// The credentials for one deconz gateway
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GatewayConfig {
+    pub url: String,
+    pub username: String,
+    /// The name the gateway reported when it was paired, missing in older configs
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Lights pinned to the top of the light list, light ids are only unique per gateway
+    #[serde(default)]
+    pub favorites: Vec<LightId>,
+    /// Whether a self-signed certificate of an https gateway is accepted
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+}
+
+impl GatewayConfig {
+    /// The name of the gateway with its address, or just the address if the name isn't known
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} ({})", name, self.url),
+            None => self.url.clone(),
+        }
+    }
+
+    /// A client for the gateway, which isn't checked to be reachable yet
+    pub fn client(&self) -> Result<DeconzClient, deconz::Error> {
+        DeconzClient::builder()
+            .danger_accept_invalid_certs(self.accept_invalid_certs)
+            // Changes made during a short Wi-Fi hiccup are sent once the gateway answers pings again
+            .offline_queue(true)
+            // Some bulbs come on at full brightness otherwise
+            .restore_brightness(true)
+            .login_with_token(&self.url, self.username.clone())
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Config {
+    pub gateways: Vec<GatewayConfig>,
+    /// Index of the gateway which is opened on startup
+    #[serde(default)]
+    pub default: usize,
+}
+
+impl Config {
+    pub fn default_gateway(&self) -> Option<&GatewayConfig> {
+        self.gateways.get(self.default)
+    }
+}
+
+/// Everything that can be found in a config file, including older formats
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredConfig {
+    Current(Config),
+    /// Before multiple gateways were supported, the file only held a single gateway
+    SingleGateway(GatewayConfig),
+}
+
+impl From<StoredConfig> for Config {
+    fn from(stored: StoredConfig) -> Self {
+        match stored {
+            StoredConfig::Current(config) => config,
+            StoredConfig::SingleGateway(gateway) => Config {
+                gateways: vec![gateway],
+                default: 0,
+            },
+        }
+    }
+}
+
+/// Where the config with the gateway credentials is kept
+pub trait CredentialStore {
+    fn load(&self) -> Option<Config>;
+    fn store(&self, config: &Config);
+    fn delete(&self);
+}
+
+/// Keeps the config in `config.json` in the user's config directory
+///
+/// The tokens are saved in clear text, the file is only readable by the user on unix.
+pub struct FileStore;
+
+impl FileStore {
+    fn path() -> PathBuf {
+        glib::user_config_dir()
+            .join("deconz-client")
+            .join("config.json")
+    }
+}
+
+impl CredentialStore for FileStore {
+    fn load(&self) -> Option<Config> {
+        File::open(Self::path())
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, StoredConfig>(file).ok())
+            .map(Config::from)
+    }
+
+    fn store(&self, config: &Config) {
+        // lets just ignore the result to allow for it failing because the directory already exists
+        _ = create_dir(Self::path().parent().unwrap());
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(Self::path()).unwrap();
+
+        // The mode only applies to new files, so restrict files written by older versions too
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))
+                .unwrap();
+        }
+
+        serde_json::to_writer_pretty(file, config).unwrap();
+    }
+
+    fn delete(&self) {
+        _ = remove_file(Self::path());
+    }
+}
+
+/// Keeps the config in the keyring of the OS (Secret Service, Keychain or Credential Manager)
+#[cfg(feature = "keyring")]
+pub struct KeyringStore;
+
+#[cfg(feature = "keyring")]
+impl KeyringStore {
+    fn entry() -> keyring::Entry {
+        keyring::Entry::new("deconz-client", "config").expect("Failed to access the keyring")
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl CredentialStore for KeyringStore {
+    fn load(&self) -> Option<Config> {
+        match Self::entry().get_password() {
+            Ok(json) => serde_json::from_str::<StoredConfig>(&json)
+                .ok()
+                .map(Config::from),
+            // Credentials stored before the keyring was used are moved over on the next store
+            Err(keyring::Error::NoEntry) => FileStore.load(),
+            Err(e) => {
+                tracing::warn!("Failed to read the keyring: {:?}", e);
+                None
+            }
+        }
+    }
+
+    fn store(&self, config: &Config) {
+        let json = serde_json::to_string(config).unwrap();
+        Self::entry()
+            .set_password(&json)
+            .expect("Failed to store the credentials in the keyring");
+        FileStore.delete();
+    }
+
+    fn delete(&self) {
+        _ = Self::entry().delete_credential();
+        FileStore.delete();
+    }
+}
+
+/// The store used by the app, the keyring if the `keyring` feature is enabled
+pub fn credential_store() -> Box<dyn CredentialStore> {
+    #[cfg(feature = "keyring")]
+    return Box::new(KeyringStore);
+    #[cfg(not(feature = "keyring"))]
+    return Box::new(FileStore);
+}
+
+pub fn store_config(config: &Config) {
+    credential_store().store(config);
+}
+
+/// Adds the credentials of a gateway (replacing older ones for the same url) and makes it the default
+pub fn store_credentials(url: String, username: String, name: String, accept_invalid_certs: bool) -> Config {
+    let mut config = load_credentials().unwrap_or_default();
+
+    match config.gateways.iter().position(|g| g.url == url) {
+        Some(i) => {
+            // Keep the favorites when logging in again
+            config.gateways[i].username = username;
+            config.gateways[i].name = Some(name);
+            config.gateways[i].accept_invalid_certs = accept_invalid_certs;
+            config.default = i;
+        }
+        None => {
+            config.gateways.push(GatewayConfig {
+                url,
+                username,
+                name: Some(name),
+                favorites: vec![],
+                accept_invalid_certs,
+            });
+            config.default = config.gateways.len() - 1;
+        }
+    }
+
+    store_config(&config);
+    config
+}
+
+/// Pins a light of a gateway to the top of the light list or unpins it if it already is
+pub fn toggle_favorite(url: &str, light_id: &LightId) {
+    let Some(mut config) = load_credentials() else { return };
+    let Some(gateway) = config.gateways.iter_mut().find(|g| g.url == url) else { return };
+
+    match gateway.favorites.iter().position(|id| id == light_id) {
+        Some(i) => {
+            gateway.favorites.remove(i);
+        }
+        None => gateway.favorites.push(light_id.clone()),
+    }
+    store_config(&config);
+}
+
+/// Removes the credentials of a gateway, deleting the stored config once no gateway is left
+///
+/// Returns the remaining config, if any.
+pub fn forget_gateway(url: &str) -> Option<Config> {
+    let mut config = load_credentials()?;
+    config.gateways.retain(|g| g.url != url);
+
+    if config.gateways.is_empty() {
+        credential_store().delete();
+        return None;
+    }
+    config.default = config.default.min(config.gateways.len() - 1);
+    store_config(&config);
+    Some(config)
+}
+
+pub fn load_credentials() -> Option<Config> {
+    let mut config = credential_store().load()?;
+
+    if config.gateways.is_empty() {
+        return None;
+    }
+    config.default = config.default.min(config.gateways.len() - 1);
+    Some(config)
+}
@@ -1,23 +1,36 @@
 // Prevent console window in addition to Slint window in Windows release builds when, e.g., starting the app via file manager. Ignored on other platforms.
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod cache;
+mod config;
+mod usage;
+
 use std::{
-    env,
+    collections::{HashMap, HashSet},
     error::Error,
-    fs::{create_dir, File},
-    path::PathBuf,
+    cell::{Cell, RefCell},
+    rc::Rc,
     sync::{Arc, Mutex},
 };
 
-use deconz::{DeconzClient, DemoLightClient, Light, LightClient, LightState};
+use cache::{LightCache, load_cache, store_cache};
+use config::{Config, GatewayConfig, forget_gateway, load_credentials, store_config, store_credentials, toggle_favorite};
+use usage::{load_usage, store_usage};
+
+use deconz::{
+    AlertMode, ColorCommand, ColorMode, CommandOutcome, ConnectionState, Controllable, DeconzClient, DemoLightClient, DiscoveredGateway, Effect, Group, Light, LightClient, LightEvent, LightId, LightListExt, LightState, LightStateChange,
+    LightCommandQueue, LightUsageTracker, NewSchedule, PowerOnBehavior, Repeat, Scene, ScheduleCommand, ScheduleTime, SensorEvent, SensorReading, bri_to_perceptual, contrasting_text_color, deconz_hsv_to_rgb, perceptual_to_bri, rgb_to_deconz_hsv, rgb_to_xy, xy_to_rgb,
+};
+use futures_util::StreamExt;
 use gtk::{
-    self as gtk, Button, ColorDialog, ColorDialogButton, Label, ListBox, Orientation,
-    ScrolledWindow, prelude::*,
+    self as gtk, Button, ColorDialog, ColorDialogButton, DropDown, Label, ListBox, Orientation,
+    ScrolledWindow, Stack, StackSwitcher, StringList, prelude::*,
 };
-use gtk::{ApplicationWindow, Scale, gdk::RGBA, prelude::BoxExt};
+use gtk::{ApplicationWindow, Scale, gdk, gdk::RGBA, prelude::BoxExt};
 use gtk::{Entry, glib};
-use palette::{FromColor, Hsv, IntoColor, RgbHue, Srgb, rgb::Rgb};
-use serde::{Deserialize, Serialize};
+use palette::Srgb;
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
 
 struct ViewModel<C>
 where
@@ -25,39 +38,60 @@ where
 {
     state: Mutex<State>,
     client: C,
+    /// Coalesces the many color changes sent while dragging the color or brightness controls
+    queue: LightCommandQueue,
+    /// The url of the gateway whose lights are cached, `None` in demo mode
+    cache_gateway: Option<String>,
+    /// How long each light was on, stored along with the cache
+    usage: LightUsageTracker,
 }
 
+#[derive(Default)]
 struct State {
     lights: Vec<Light>,
-    selected_index: usize,
+    groups: Vec<Group>,
+    /// The id of the selected light, kept when the light list is reloaded
+    selected_light: Option<LightId>,
+    /// The ids of the lights shown in the light list, in the order of its rows
+    light_rows: Vec<LightId>,
+    /// All lights selected in the list, the color control paints them all if there are several
+    selected_lights: Vec<LightId>,
     selected_light_state: Option<LightState>,
+    selected_group: Option<Group>,
+    /// The scenes of the selected group
+    scenes: Vec<Scene>,
+    /// The last known state of every light that was shown, keyed by light id
+    light_states: HashMap<LightId, LightState>,
+    /// Whether the gateway can reach each light, keyed by light id
+    reachability: HashMap<LightId, bool>,
+    /// Lights pinned to the top of the light list
+    favorites: Vec<LightId>,
 }
 
 impl State {
-    fn selected_light<'a>(&'a self) -> Option<&'a Light> {
-        let i = self.selected_index;
-        self.lights.get(i)
+    fn selected_light(&self) -> Option<&Light> {
+        self.lights.find_light_by_id(self.selected_light.as_ref()?)
     }
 }
 
-impl Default for State {
-    fn default() -> Self {
-        State {
-            lights: vec![],
-            selected_index: usize::MAX,
-            selected_light_state: None,
+impl ViewModel<DeconzClient> {
+    fn init(gateway: &GatewayConfig) -> Self {
+        // Start with the lights from the last run until the gateway answers
+        let mut state = State::default();
+        if let Some(cache) = load_cache(&gateway.url) {
+            state.selected_light_state = cache.selected.as_ref().and_then(|id| cache.states.get(id)).cloned();
+            state.selected_light = cache.selected;
+            state.lights = cache.lights;
+            state.light_states = cache.states;
         }
-    }
-}
+        state.favorites = gateway.favorites.clone();
 
-impl ViewModel<DeconzClient> {
-    fn init() -> Self {
-        let url = env::var("DECONZ_URL").expect("Missing DECONZ_URL in env vars");
-        let token = env::var("DECONZ_TOKEN").expect("Missing DECONZ_TOKEN in env vars");
         ViewModel {
-            state: Mutex::new(State::default()),
-            client: DeconzClient::login_with_token(url, token)
-                .expect("Failed to connect to deconz server"),
+            state: Mutex::new(state),
+            client: gateway.client().expect("Failed to connect to deconz server"),
+            queue: LightCommandQueue::default(),
+            cache_gateway: Some(gateway.url.clone()),
+            usage: load_usage(&gateway.url),
         }
     }
 }
@@ -67,46 +101,236 @@ impl ViewModel<DemoLightClient> {
         ViewModel {
             state: Mutex::new(State::default()),
             client: DemoLightClient::new(),
+            queue: LightCommandQueue::default(),
+            cache_gateway: None,
+            usage: LightUsageTracker::new(),
+        }
+    }
+}
+
+impl<C: LightClient> ViewModel<C> {
+    /// Pins a light to the top of the light list or unpins it, which is remembered unless in demo mode
+    fn toggle_favorite(&self, light_id: &LightId) {
+        let mut state = self.state.lock().unwrap();
+        match state.favorites.iter().position(|id| id == light_id) {
+            Some(i) => {
+                state.favorites.remove(i);
+            }
+            None => state.favorites.push(light_id.clone()),
+        }
+        drop(state);
+
+        if let Some(gateway) = &self.cache_gateway {
+            toggle_favorite(gateway, light_id);
         }
     }
+
+    /// Saves the known lights and their states for the next start
+    fn store_cache(&self) {
+        let Some(gateway) = &self.cache_gateway else { return };
+        let state = self.state.lock().unwrap();
+        store_cache(&LightCache {
+            gateway: gateway.clone(),
+            lights: state.lights.clone(),
+            states: state.light_states.clone(),
+            selected: state.selected_light.clone(),
+        });
+    }
+
+    /// Saves how long the lights were on, unless in demo mode
+    fn store_usage(&self) {
+        if let Some(gateway) = &self.cache_gateway {
+            store_usage(gateway, &self.usage);
+        }
+    }
+}
+
+/// The name of an effect as shown in the effect dropdown
+fn effect_label(effect: &Effect) -> String {
+    match effect {
+        Effect::None => String::from("No effect"),
+        Effect::ColorLoop => String::from("Color loop"),
+        Effect::Other(name) => {
+            let mut chars = name.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Tells that a light is offline and, if known, since when, e.g. "Offline, last seen 5 minutes ago"
+fn offline_text(light: &Light) -> String {
+    match light.last_seen.as_ref().and_then(|t| t.elapsed()) {
+        Some(elapsed) => format!("Offline, last seen {}", ago_text(elapsed)),
+        None => String::from("Offline"),
+    }
+}
+
+/// A rough description of how long ago something was, e.g. "5 minutes ago"
+fn ago_text(elapsed: std::time::Duration) -> String {
+    let minutes = elapsed.as_secs() / 60;
+    match minutes {
+        0 => String::from("just now"),
+        1 => String::from("a minute ago"),
+        2..=59 => format!("{} minutes ago", minutes),
+        60..=119 => String::from("an hour ago"),
+        120..=2879 => format!("{} hours ago", minutes / 60),
+        _ => format!("{} days ago", minutes / 60 / 24),
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct Config {
-    url: String,
-    username: String,
+/// The total on time of a light, e.g. "On for 3 h 20 min in total"
+fn usage_text(on_time: std::time::Duration) -> String {
+    let minutes = on_time.as_secs() / 60;
+    match minutes {
+        0 => String::from("On for less than a minute in total"),
+        1..=59 => format!("On for {} min in total", minutes),
+        _ => format!("On for {} h {} min in total", minutes / 60, minutes % 60),
+    }
+}
+
+/// The position of the brightness slider (0-100), which follows the perceived brightness so
+/// it feels even along its whole length
+fn brightness_slider_value(bri: u8) -> f64 {
+    bri_to_perceptual(bri) as f64 * 100.0
+}
+
+/// The color a light (or the action of a group) shows, in the color mode it uses
+fn state_rgb(light_state: &LightState) -> Srgb {
+    let bri = light_state.bri().unwrap_or(255);
+    match (light_state.color_mode(), light_state.xy()) {
+        (Some(ColorMode::Xy), Some(xy)) => xy_to_rgb(xy, bri),
+        _ => deconz_hsv_to_rgb(
+            light_state.hue().unwrap_or_default(),
+            light_state.sat().unwrap_or_default(),
+            bri,
+        ),
+    }
 }
 
-fn config_file_path() -> PathBuf {
-    glib::user_config_dir()
-        .join("deconz-client")
-        .join("config.json")
+/// One swatch per light of a scene: its color if the scene turns it on, ○ if it turns it off
+/// and – if it leaves it as it is
+fn scene_preview_markup(lights: &[(LightId, Option<LightState>)]) -> String {
+    lights
+        .iter()
+        .map(|(_, light_state)| match light_state {
+            Some(light_state) if light_state.on() => {
+                let rgb: palette::Srgb<u8> = state_rgb(light_state).into_format();
+                format!("<span foreground=\"#{:02x}{:02x}{:02x}\">●</span>", rgb.red, rgb.green, rgb.blue)
+            }
+            Some(_) => String::from("○"),
+            None => String::from("–"),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
-fn store_credentials(url: String, username: String) {
-    let config = Config { url, username };
+/// The name of a light for the light list, on its current color if it is on and has one
+fn light_row_markup(name: &str, light_state: Option<&LightState>) -> String {
+    let name = glib::markup_escape_text(name);
+    match light_state.filter(|s| s.on() && s.is_color_capable()) {
+        Some(light_state) => {
+            let background = state_rgb(light_state);
+            let bg: palette::Srgb<u8> = background.into_format();
+            let fg: palette::Srgb<u8> = contrasting_text_color(background).into_format();
+            format!(
+                "<span background=\"#{:02x}{:02x}{:02x}\" foreground=\"#{:02x}{:02x}{:02x}\"> {} </span>",
+                bg.red, bg.green, bg.blue, fg.red, fg.green, fg.blue, name
+            )
+        }
+        None => name.to_string(),
+    }
+}
 
-    // lets just ignore the result to allow for it failing because the directory already exists
-    _ = create_dir(config_file_path().parent().unwrap());
-    let file = File::create(config_file_path()).unwrap();
-    serde_json::to_writer_pretty(file, &config).unwrap();
+/// The color picked in the ui without its alpha
+fn rgba_to_rgb(col: &RGBA) -> Srgb {
+    Srgb::new(col.red(), col.green(), col.blue())
 }
 
-fn load_credentials() -> Option<Config> {
-    File::open(config_file_path()).ok().and_then(|file| serde_json::from_reader::<_, Config>(file).ok())
+/// A row of the light list, kept between updates of the list so only changed lights touch the widgets
+struct LightRow {
+    id: LightId,
+    row: gtk::ListBoxRow,
+    label: Label,
+    /// The section header the row starts, if it is the first of its manufacturer
+    header: Option<String>,
 }
 
 struct MainWindow {
     window: ApplicationWindow,
+    /// Switches between the configured gateways, hidden in demo mode
+    gateway_picker: DropDown,
+    /// Shows information about the gateway, hidden in demo mode
+    about_button: Button,
+    /// Revokes the token and removes the gateway from the config, hidden in demo mode
+    forget_button: Button,
+    /// Lists the apps with access to the gateway, hidden in demo mode
+    apps_button: gtk::MenuButton,
+    apps_popover: gtk::Popover,
+    /// A row per app, filled when `apps_popover` opens
+    apps_layout: gtk::Box,
+    /// A dot colored by the latency of the gateway, hidden in demo mode
+    connection_indicator: Label,
+    /// Tells that the gateway can't be reached, hidden while it can
+    connection_banner: Label,
+    all_off_button: Button,
     list_box: ListBox,
+    group_list_box: ListBox,
+    /// The automations of the gateway with a switch to pause each
+    rule_list_box: ListBox,
+    sidebar_stack: Stack,
+    new_group_name: Entry,
+    new_group_button: Button,
     toggle_button: Button,
+    identify_button: Button,
     light_name_label: Label,
+    /// How long the selected light was on in total
+    usage_label: Label,
     light_status_label: Label,
     toggle_button_text: Label,
     controller_layout: gtk::Box,
     color_control: ColorDialogButton,
+    /// Whether colors are sent as xy instead of hue and saturation
+    xy_toggle: gtk::CheckButton,
+    /// Switches between the color controls and the color temperature slider on lights with both
+    white_toggle: gtk::ToggleButton,
+    /// Color temperature in mireds
+    ct_slider: Scale,
+    /// The effects the selected light supports, hidden if it has none
+    effect_dropdown: DropDown,
+    /// The speed of effects and gradients, only shown for lights with gradients
+    speed_slider: Scale,
+    /// Opens the list of groups in which the light can be added or removed
+    group_popover: gtk::Popover,
+    /// A check box per group, filled when `group_popover` opens
+    group_membership_layout: gtk::Box,
+    /// Chooses what the light does when it gets power (last state, on, off, current state)
+    power_on_dropdown: DropDown,
+    power_on_apply_button: Button,
+    power_on_error_label: Label,
+    /// Minutes after which the auto off timer turns the light off
+    auto_off_minutes: gtk::SpinButton,
+    auto_off_button: Button,
+    auto_off_label: Label,
     search_bar: Entry,
     brightness_slider: Scale,
+    temperature_label: Label,
+    scene_layout: gtk::Box,
+    scene_label: Label,
+    scene_dropdown: DropDown,
+    /// The name of the selected scene, activating it renames the scene
+    scene_name_entry: Entry,
+    scene_name_error_label: Label,
+    /// Shows what the selected scene does to each light
+    scene_preview: Label,
+    scene_recall_button: Button,
+    scene_store_button: Button,
+    /// Activating it saves the current lights of the group as a new scene
+    new_scene_name: Entry,
+    /// Set while the controls are updated from a light state rather than by the user
+    showing_state: Cell<bool>,
 }
 impl MainWindow {
     fn new(application: &gtk::Application) -> Self {
@@ -115,7 +339,47 @@ impl MainWindow {
         window.set_title(Some("Deconz Control"));
         window.set_default_size(500, 700);
 
-        let list_box = gtk::ListBox::new();
+        let gateway_picker = DropDown::builder()
+            .tooltip_text("The deconz gateway to control")
+            .visible(false)
+            .build();
+        let header_bar = gtk::HeaderBar::new();
+        header_bar.pack_start(&gateway_picker);
+        let all_off_button = Button::builder()
+            .label("All off")
+            .tooltip_text("Turns off every light")
+            .css_classes(["destructive-action"])
+            .build();
+        header_bar.pack_start(&all_off_button);
+        let about_button = Button::builder()
+            .icon_name("help-about-symbolic")
+            .tooltip_text("About the gateway")
+            .visible(false)
+            .build();
+        header_bar.pack_end(&about_button);
+        let forget_button = Button::builder()
+            .icon_name("user-trash-symbolic")
+            .tooltip_text("Forget this gateway and revoke access")
+            .visible(false)
+            .build();
+        header_bar.pack_end(&forget_button);
+        let apps_layout = gtk::Box::new(Orientation::Vertical, 5);
+        let apps_popover = gtk::Popover::builder().child(&apps_layout).build();
+        let apps_button = gtk::MenuButton::builder()
+            .icon_name("system-users-symbolic")
+            .tooltip_text("Apps with access to the gateway")
+            .popover(&apps_popover)
+            .visible(false)
+            .build();
+        header_bar.pack_end(&apps_button);
+        let connection_indicator = Label::builder().label("●").visible(false).build();
+        header_bar.pack_end(&connection_indicator);
+        window.set_titlebar(Some(&header_bar));
+
+        // Ctrl- or shift-click selects several lights to paint them at once
+        let list_box = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::Multiple)
+            .build();
 
         let scrolled_window = ScrolledWindow::builder().child(&list_box).build();
         scrolled_window.set_vexpand(true);
@@ -130,6 +394,36 @@ impl MainWindow {
 
         selection_layout.append(&scrolled_window);
 
+        let group_list_box = gtk::ListBox::new();
+        let group_scrolled_window = ScrolledWindow::builder().child(&group_list_box).build();
+        group_scrolled_window.set_vexpand(true);
+
+        let new_group_name = Entry::builder()
+            .hexpand(true)
+            .placeholder_text("Name of the new group")
+            .build();
+        let new_group_button = Button::builder().label("New group").build();
+        let new_group_layout = gtk::Box::new(Orientation::Horizontal, 5);
+        new_group_layout.append(&new_group_name);
+        new_group_layout.append(&new_group_button);
+
+        let group_layout = gtk::Box::new(Orientation::Vertical, 0);
+        group_layout.append(&group_scrolled_window);
+        group_layout.append(&new_group_layout);
+
+        let rule_list_box = gtk::ListBox::builder().selection_mode(gtk::SelectionMode::None).build();
+        rule_list_box.set_placeholder(Some(&Label::new(Some("The gateway has no automations"))));
+        let rule_scrolled_window = ScrolledWindow::builder().child(&rule_list_box).vexpand(true).build();
+
+        let sidebar_stack = Stack::new();
+        sidebar_stack.add_titled(&selection_layout, Some("lights"), "Lights");
+        sidebar_stack.add_titled(&group_layout, Some("groups"), "Groups");
+        sidebar_stack.add_titled(&rule_scrolled_window, Some("automations"), "Automations");
+
+        let sidebar = gtk::Box::new(Orientation::Vertical, 0);
+        sidebar.append(&StackSwitcher::builder().stack(&sidebar_stack).build());
+        sidebar.append(&sidebar_stack);
+
         let controller_layout = gtk::Box::new(Orientation::Vertical, 10);
         controller_layout.set_margin_start(20);
         controller_layout.set_margin_end(20);
@@ -139,9 +433,11 @@ impl MainWindow {
         let light_name_label = Label::new(Some("No lamp selected"));
 
         let light_status_label = Label::new(None);
+        let usage_label = Label::builder().css_classes(["dim-label"]).build();
 
         controller_layout.append(&light_name_label);
         controller_layout.append(&light_status_label);
+        controller_layout.append(&usage_label);
         let toggle_button_text = Label::new(Some("Toggle lamp"));
         let toggle_button = Button::builder()
             .child(&toggle_button_text)
@@ -149,82 +445,396 @@ impl MainWindow {
             .build();
         controller_layout.append(&toggle_button);
 
+        let identify_button = Button::builder()
+            .label("Identify")
+            .tooltip_text("Makes the lamp blink once")
+            .build();
+        controller_layout.append(&identify_button);
+
+        let white_toggle = gtk::ToggleButton::builder()
+            .label("White")
+            .tooltip_text("Switches between colors and shades of white")
+            .build();
+        controller_layout.append(&white_toggle);
+
+        // From cold (153 mireds, 6500 K) to warm (500 mireds, 2000 K)
+        let ct_slider = Scale::with_range(Orientation::Horizontal, 153.0, 500.0, 1.0);
+        ct_slider.set_tooltip_text(Some("Color temperature"));
+        controller_layout.append(&ct_slider);
+
         let dialog = ColorDialog::builder().with_alpha(false).build();
         let col = ColorDialogButton::builder().dialog(&dialog).build();
 
         controller_layout.append(&col);
 
-        let brightness_slider = Scale::with_range(Orientation::Horizontal, 0.0, 255.0, 1.0);
+        let xy_toggle = gtk::CheckButton::builder()
+            .label("Send colors as xy")
+            .tooltip_text("Avoids gamut clipping on bulbs that are more accurate in the xy color space")
+            .build();
+        controller_layout.append(&xy_toggle);
+
+        let effect_dropdown = DropDown::builder()
+            .tooltip_text("Effects the light can run on its own")
+            .build();
+        controller_layout.append(&effect_dropdown);
+
+        let speed_slider = Scale::with_range(Orientation::Horizontal, 0.0, 255.0, 1.0);
+        speed_slider.set_tooltip_text(Some("Speed of effects and gradients"));
+        controller_layout.append(&speed_slider);
+
+        let power_on_dropdown = DropDown::from_strings(&[
+            "Last state",
+            "On",
+            "Off",
+            "Current color and brightness",
+        ]);
+        let power_on_apply_button = Button::builder().label("Apply").build();
+        let power_on_error_label = Label::builder().wrap(true).build();
+        let advanced_layout = gtk::Box::new(Orientation::Vertical, 5);
+        advanced_layout.append(&Label::new(Some("When power is restored")));
+        advanced_layout.append(&power_on_dropdown);
+        advanced_layout.append(&power_on_apply_button);
+        advanced_layout.append(&power_on_error_label);
+        let advanced_button = gtk::MenuButton::builder()
+            .label("Advanced settings")
+            .popover(&gtk::Popover::builder().child(&advanced_layout).build())
+            .build();
+        controller_layout.append(&advanced_button);
+
+        let group_membership_layout = gtk::Box::new(Orientation::Vertical, 5);
+        let group_popover = gtk::Popover::builder()
+            .child(&group_membership_layout)
+            .build();
+        let group_button = gtk::MenuButton::builder()
+            .label("Groups")
+            .tooltip_text("Adds the lamp to groups or removes it from them")
+            .popover(&group_popover)
+            .build();
+        controller_layout.append(&group_button);
+
+        let auto_off_minutes = gtk::SpinButton::with_range(1.0, 240.0, 1.0);
+        auto_off_minutes.set_value(30.0);
+        let auto_off_button = Button::builder()
+            .label("Turn off after minutes")
+            .tooltip_text("Creates a timer on the gateway which turns the lamp off")
+            .hexpand(true)
+            .build();
+        let auto_off_layout = gtk::Box::new(Orientation::Horizontal, 5);
+        auto_off_layout.append(&auto_off_minutes);
+        auto_off_layout.append(&auto_off_button);
+        controller_layout.append(&auto_off_layout);
+        let auto_off_label = Label::new(None);
+        controller_layout.append(&auto_off_label);
+
+        let brightness_slider = Scale::with_range(Orientation::Horizontal, 0.0, 100.0, 1.0);
+        brightness_slider.set_draw_value(true);
+        brightness_slider.set_format_value_func(|_, percent| format!("{:.0} %", percent));
 
         controller_layout.append(&brightness_slider);
 
+        let scene_layout = gtk::Box::new(Orientation::Vertical, 10);
+        scene_layout.set_margin_start(20);
+        scene_layout.set_margin_end(20);
+        scene_layout.set_margin_top(20);
+        scene_layout.set_visible(false);
+
+        let scene_label = Label::new(None);
+        scene_layout.append(&scene_label);
+        let scene_dropdown = DropDown::builder().build();
+        scene_layout.append(&scene_dropdown);
+        let scene_name_entry = Entry::builder()
+            .tooltip_text("Press enter to rename the scene")
+            .build();
+        scene_layout.append(&scene_name_entry);
+        let scene_name_error_label = Label::builder().wrap(true).build();
+        scene_layout.append(&scene_name_error_label);
+        let scene_preview = Label::builder()
+            .halign(gtk::Align::Start)
+            .tooltip_text("The colors the scene gives the lights of the group, ○ turns a light off and – leaves it unchanged")
+            .build();
+        scene_layout.append(&scene_preview);
+        let scene_recall_button = Button::builder()
+            .label("Recall scene")
+            .tooltip_text("Applies the selected scene to the group")
+            .build();
+        scene_layout.append(&scene_recall_button);
+        let scene_store_button = Button::builder()
+            .label("Store current lights in scene")
+            .tooltip_text("Replaces what the selected scene does with the current state of the group's lights")
+            .build();
+        scene_layout.append(&scene_store_button);
+        let new_scene_name = Entry::builder()
+            .placeholder_text("Name of a new scene from the current lights")
+            .build();
+        scene_layout.append(&new_scene_name);
+
+        let temperature_label = Label::builder().margin_top(10).visible(false).build();
+
+        let controls = gtk::Box::new(Orientation::Vertical, 0);
+        controls.append(&temperature_label);
+        controls.append(&controller_layout);
+        controls.append(&scene_layout);
+
         let layout = gtk::Box::new(Orientation::Horizontal, 0);
         layout.set_homogeneous(true);
 
-        layout.append(&selection_layout);
-        layout.append(&controller_layout);
+        layout.append(&sidebar);
+        layout.append(&controls);
 
-        window.set_child(Some(&layout));
+        let connection_banner = Label::builder()
+            .css_classes(["error"])
+            .wrap(true)
+            .margin_top(6)
+            .margin_bottom(6)
+            .visible(false)
+            .build();
+
+        let root = gtk::Box::new(Orientation::Vertical, 0);
+        root.append(&connection_banner);
+        root.append(&layout);
+
+        window.set_child(Some(&root));
 
         window.present();
-        let ui = Self {
+        Self {
             window,
+            gateway_picker,
+            about_button,
+            forget_button,
+            apps_button,
+            apps_popover,
+            apps_layout,
+            connection_indicator,
+            connection_banner,
+            all_off_button,
             list_box,
+            group_list_box,
+            rule_list_box,
+            sidebar_stack,
+            new_group_name,
+            new_group_button,
             toggle_button,
+            identify_button,
             light_name_label,
             light_status_label,
+            usage_label,
             toggle_button_text,
             controller_layout,
             color_control: col,
+            xy_toggle,
+            white_toggle,
+            ct_slider,
+            effect_dropdown,
+            speed_slider,
+            group_popover,
+            group_membership_layout,
+            power_on_dropdown,
+            power_on_apply_button,
+            power_on_error_label,
+            auto_off_minutes,
+            auto_off_button,
+            auto_off_label,
             search_bar,
             brightness_slider,
-        };
+            temperature_label,
+            scene_layout,
+            scene_label,
+            scene_dropdown,
+            scene_name_entry,
+            scene_name_error_label,
+            scene_preview,
+            scene_recall_button,
+            scene_store_button,
+            new_scene_name,
+            showing_state: Cell::new(false),
+        }
+    }
+    /// Fills the gateway picker with the configured gateways and an entry to add another one
+    fn show_gateways(&self, config: &Config, on_gateway_selected: impl Fn(Option<usize>) + 'static) {
+        let mut names: Vec<String> = config.gateways.iter().map(GatewayConfig::label).collect();
+        names.push(String::from("Add gateway…"));
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        self.gateway_picker.set_model(Some(&StringList::new(&names)));
+        self.gateway_picker.set_selected(config.default as u32);
+        self.gateway_picker.set_visible(true);
+
+        let gateway_count = config.gateways.len();
+        self.gateway_picker.connect_selected_notify(move |picker| {
+            let selected = picker.selected() as usize;
+            on_gateway_selected((selected < gateway_count).then_some(selected));
+        });
+    }
+
+    /// Displays the state of the selected light in the controller layout
+    fn show_light_state(&self, light: &Light, light_state: &LightState, on_time: std::time::Duration) {
+        // Setting the controls fires their change handlers which must not send the state back
+        self.showing_state.set(true);
+
+        self.controller_layout.set_visible(true);
+        let plug = light.light_type.as_ref().is_some_and(|t| t.is_plug());
+        self.toggle_button.set_tooltip_text(Some(if plug {
+            "Toggles the on/off state of the plug"
+        } else {
+            "Toggles the on/off state of the lamp"
+        }));
+        // Lights in color temperature mode get the slider instead of the color controls
+        let white = light_state.color_mode() == Some(ColorMode::Ct) || !light.capabilities.color;
+        self.white_toggle.set_active(white);
+        self.show_color_controls(light, white);
+        // The light type may promise more than the light reports, e.g. for plugs sold as lamps
+        if !light_state.is_color_capable() {
+            self.color_control.set_visible(false);
+            self.xy_toggle.set_visible(false);
+            self.white_toggle.set_visible(false);
+        }
+        if light_state.ct().is_none() {
+            self.ct_slider.set_visible(false);
+            self.white_toggle.set_visible(false);
+        }
+        if let Some(ct) = light_state.ct() {
+            self.ct_slider.set_value(ct as f64);
+        }
+        let effects = &light.capabilities.effects;
+        let names: Vec<String> = effects.iter().map(effect_label).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        self.effect_dropdown.set_model(Some(&StringList::new(&names)));
+        self.effect_dropdown
+            .set_visible(effects.iter().any(|effect| *effect != Effect::None));
+        let running = effects
+            .iter()
+            .position(|effect| Some(effect.as_str()) == light_state.effect())
+            .or_else(|| effects.iter().position(|effect| *effect == Effect::None));
+        if let Some(running) = running {
+            self.effect_dropdown.set_selected(running as u32);
+        }
+        self.speed_slider
+            .set_visible(light.capabilities.gradient || light_state.speed().is_some());
+        if let Some(speed) = light_state.speed() {
+            self.speed_slider.set_value(speed as f64);
+        }
+        self.brightness_slider
+            .set_visible(light.capabilities.dimmable && light_state.is_dimmable());
+        // Below the minimum dim level some bulbs turn off
+        self.brightness_slider
+            .set_range(brightness_slider_value(light.capabilities.min_bri), 100.0);
+        self.light_status_label.set_text(&if light_state.reachable() {
+            String::new()
+        } else {
+            offline_text(light)
+        });
+        self.usage_label.set_text(&usage_text(on_time));
+        self.toggle_button_text.set_text(if light_state.on() {
+            "Turn off"
+        } else {
+            "Turn on"
+        });
+
+        if light_state.is_color_capable() {
+            let rgb = state_rgb(light_state);
+            self.color_control
+                .set_rgba(&RGBA::new(rgb.red, rgb.green, rgb.blue, 1.0));
+        }
+        if let Some(bri) = light_state.bri() {
+            self.brightness_slider.set_value(brightness_slider_value(bri));
+        }
 
-        ui
+        self.showing_state.set(false);
     }
+
+    /// Shows either the color controls or the color temperature slider, as far as the light supports them
+    fn show_color_controls(&self, light: &Light, white: bool) {
+        let capabilities = &light.capabilities;
+        self.white_toggle
+            .set_visible(capabilities.color && capabilities.color_temp);
+        self.color_control.set_visible(capabilities.color && !white);
+        self.xy_toggle.set_visible(capabilities.color && !white);
+        self.ct_slider
+            .set_visible(capabilities.color_temp && (white || !capabilities.color));
+    }
+
     fn add_app_logic<C: LightClient + 'static>(self, model: ViewModel<C>) {
         println!("Attaching app logic...");
-        let ui = Arc::new(self);
+        let ui = Rc::new(self);
         let model = Arc::new(model);
 
+        /// Applies a change to the remembered states of a light, returns the new state if the
+        /// light is still selected
+        fn remember_change<C: LightClient>(
+            model: &ViewModel<C>,
+            id: &LightId,
+            change: &LightStateChange,
+        ) -> Option<LightState> {
+            let mut state = model.state.lock().unwrap();
+            if let Some(light_state) = state.light_states.get_mut(id) {
+                change.apply_to(light_state);
+            }
+            if state.selected_light.as_ref() != Some(id) {
+                return None;
+            }
+            let light_state = state.selected_light_state.as_mut()?;
+            change.apply_to(light_state);
+            Some(light_state.clone())
+        }
+
+        /// Sends a color change through the queue and remembers the new state once it is the latest
+        async fn send_color<C: LightClient>(model: &ViewModel<C>, light: &Light, command: ColorCommand) {
+            match model.queue.set_color(&model.client, light, command).await {
+                Ok(CommandOutcome::Sent(sent)) => {
+                    remember_change(model, &light.id, &sent.as_state_change());
+                }
+                // A newer command for the light is on its way and will update the state
+                Ok(CommandOutcome::Superseded) => {}
+                Err(e) => warn!("Failed to change the color of {}: {:?}", light.name, e),
+            }
+        }
+
         fn fetch_light_state<C: LightClient + 'static>(
             model: Arc<ViewModel<C>>,
-            ui: Arc<MainWindow>,
+            ui: Rc<MainWindow>,
         ) {
             glib::spawn_future_local(async move {
-                let mut state = model.state.lock().unwrap();
-                if let Some(light) = state.selected_light() {
-                    let light_state = model
-                        .client
-                        .get_light_state(light)
-                        .await
-                        .expect(&format!("Failed to load state of light {}", light.name));
-                    state.selected_light_state = Some(light_state);
-                    ui.controller_layout.set_visible(true);
-                    ui.light_status_label.set_text(if light_state.reachable {
-                        ""
-                    } else {
-                        "Not reachable"
-                    });
-                    ui.toggle_button_text.set_text(if light_state.on {
-                        "Turn off"
-                    } else {
-                        "Turn on"
-                    });
-
-                    let hsv = Hsv::new(
-                        RgbHue::from_degrees(
-                            light_state.hue.unwrap_or_default() as f32 / u16::MAX as f32 * 360.0,
-                        ),
-                        light_state.sat.unwrap_or_default() as f32 / 255.0,
-                        light_state.bri.unwrap_or(255) as f32 / 255.0,
-                    );
-
-                    let rgb: Srgb = hsv.into_color();
-                    ui.color_control
-                        .set_rgba(&RGBA::new(rgb.red, rgb.green, rgb.blue, 1.0));
+                // Clone the light so the state isn't locked while the request is running
+                let light = model.state.lock().unwrap().selected_light().cloned();
+                if let Some(light) = light {
+                    let light_state = match model.client.get_light_state(&light).await {
+                        Ok(light_state) => light_state,
+                        Err(e) => {
+                            warn!("Failed to load state of light {}: {:?}", light.name, e);
+                            return;
+                        }
+                    };
+                    model.usage.record(&light.id, light_state.on());
+                    ui.show_light_state(&light, &light_state, model.usage.on_duration(&light.id));
+                    {
+                        let mut state = model.state.lock().unwrap();
+                        state.light_states.insert(light.id.clone(), light_state.clone());
+                        state.selected_light_state = Some(light_state);
+                    }
+                    model.store_cache();
+                }
+            });
+        }
 
-                    ui.brightness_slider.set_value(hsv.value as f64 * 255.0);
+        fn fetch_scene_preview<C: LightClient + 'static>(
+            model: Arc<ViewModel<C>>,
+            ui: Rc<MainWindow>,
+        ) {
+            let selected = ui.scene_dropdown.selected() as usize;
+            let scene = model.state.lock().unwrap().scenes.get(selected).cloned();
+            ui.scene_name_error_label.set_text("");
+            let Some(scene) = scene else {
+                ui.scene_name_entry.set_text("");
+                ui.scene_preview.set_text("");
+                return;
+            };
+            ui.scene_name_entry.set_text(&scene.name);
+            glib::spawn_future_local(async move {
+                match model.client.get_scene_details(&scene).await {
+                    Ok(lights) => ui.scene_preview.set_markup(&scene_preview_markup(&lights)),
+                    Err(e) => {
+                        warn!("Failed to load the scene {}: {}", scene.name, e);
+                        ui.scene_preview.set_text("");
+                    }
                 }
             });
         }
@@ -232,81 +842,301 @@ impl MainWindow {
         let update_light_list = {
             let ui = ui.clone();
             let model = model.clone();
+            // The rows currently in the list, in the order they are shown
+            let rows: RefCell<Vec<LightRow>> = RefCell::default();
+            let placeholder = Label::new(None);
+            ui.list_box.set_placeholder(Some(&placeholder));
             move || {
                 let mut state = model.state.lock().unwrap();
 
-                // remember the last selected light
-                let selected_light_id = state
-                    .lights
-                    .get(state.selected_index)
-                    .and_then(|l| Some(l.id));
-
-                while let Some(child) = ui.list_box.first_child() {
-                    ui.list_box.remove(&child);
-                }
-
-                let mut selected_light_index = usize::MAX;
+                placeholder.set_text(if state.lights.is_empty() {
+                    "No lights found on this gateway"
+                } else {
+                    "No lights match the search"
+                });
 
-                let lights = &state.lights;
                 let search_query = ui.search_bar.text().to_lowercase();
+                let mut lights: Vec<Light> = state
+                    .lights
+                    .iter()
+                    .filter(|light| {
+                        light
+                            .name
+                            .to_lowercase()
+                            .matches(&*search_query)
+                            .next()
+                            .is_some()
+                    })
+                    .cloned()
+                    .collect();
+                // Favorites come first, the other lights are grouped by manufacturer
+                let is_favorite = |light: &Light| state.favorites.contains(&light.id);
+                lights.sort_by_key(|light| (!is_favorite(light), light.manufacturer.clone()));
+                let favorites: Vec<bool> = lights.iter().map(is_favorite).collect();
+                let offline: Vec<bool> = lights
+                    .iter()
+                    .map(|light| state.reachability.get(&light.id) == Some(&false))
+                    .collect();
+                let markups: Vec<String> = lights
+                    .iter()
+                    .zip(&favorites)
+                    .map(|(light, favorite)| {
+                        let name = if *favorite { format!("★ {}", light.name) } else { light.name.clone() };
+                        light_row_markup(&name, state.light_states.get(&light.id))
+                    })
+                    .collect();
+                let selected_light = state.selected_light.clone();
+                state.light_rows = lights.iter().map(|l| l.id.clone()).collect();
+                // Changing the rows fires the selection handlers, which need the state
+                drop(state);
+
+                let mut rows = rows.borrow_mut();
+                // Only the rows of lights which are gone are removed, the others are kept or moved
+                let ids: HashSet<&LightId> = lights.iter().map(|light| &light.id).collect();
+                rows.retain(|light_row| {
+                    let keep = ids.contains(&light_row.id);
+                    if !keep {
+                        ui.list_box.remove(&light_row.row);
+                    }
+                    keep
+                });
+
+                let mut last_header = None;
                 for (i, light) in lights.iter().enumerate() {
-                    if light
-                        .name
-                        .to_lowercase()
-                        .matches(&*search_query)
-                        .next()
-                        .is_none()
-                    {
-                        continue;
+                    match rows[i..].iter().position(|light_row| light_row.id == light.id) {
+                        Some(0) => {}
+                        Some(offset) => {
+                            let moved = rows.remove(i + offset);
+                            ui.list_box.remove(&moved.row);
+                            ui.list_box.insert(&moved.row, i as i32);
+                            rows.insert(i, moved);
+                        }
+                        None => {
+                            let label = Label::builder().use_markup(true).build();
+                            let row = gtk::ListBoxRow::builder().child(&label).build();
+                            ui.list_box.insert(&row, i as i32);
+                            rows.insert(
+                                i,
+                                LightRow {
+                                    id: light.id.clone(),
+                                    row,
+                                    label,
+                                    header: None,
+                                },
+                            );
+                        }
                     }
+                    let light_row = &mut rows[i];
 
-                    let label = Label::new(Some(&light.name));
-                    //let row = ListBoxRow::builder().child(&label).build();
-                    ui.list_box.append(&label);
+                    if light_row.label.label() != markups[i] {
+                        light_row.label.set_markup(&markups[i]);
+                    }
+                    let tooltip = if offline[i] {
+                        offline_text(light)
+                    } else {
+                        String::from(light.model_id.as_deref().unwrap_or("Unknown model"))
+                    };
+                    if light_row.label.tooltip_text().as_deref() != Some(tooltip.as_str()) {
+                        light_row.label.set_tooltip_text(Some(&tooltip));
+                    }
+                    if offline[i] != light_row.label.has_css_class("dim-label") {
+                        if offline[i] {
+                            light_row.label.add_css_class("dim-label");
+                        } else {
+                            light_row.label.remove_css_class("dim-label");
+                        }
+                    }
 
-                    if selected_light_id.is_some_and(|id| light.id == id) {
-                        selected_light_index = i;
+                    let header_text = if favorites[i] {
+                        "Favorites"
+                    } else {
+                        light.manufacturer.as_deref().unwrap_or("Unknown manufacturer")
+                    };
+                    let header = (last_header != Some(header_text)).then(|| String::from(header_text));
+                    last_header = Some(header_text);
+                    if light_row.header != header {
+                        let header_label = header.as_deref().map(|text| {
+                            Label::builder()
+                                .label(text)
+                                .css_classes(["heading"])
+                                .margin_top(5)
+                                .build()
+                        });
+                        light_row.row.set_header(header_label.as_ref());
+                        light_row.header = header;
                     }
                 }
 
-                // Reselect the light from before
-                state.selected_index = selected_light_index;
-                // TODO: set the selected row in the ui element
+                // Moved rows lose their selection, which is restored for the selected light
+                let selected_row = selected_light
+                    .and_then(|id| rows.iter().find(|light_row| light_row.id == id))
+                    .map(|light_row| light_row.row.clone());
+                drop(rows);
+                if let Some(row) = selected_row.filter(|row| !row.is_selected()) {
+                    ui.list_box.select_row(Some(&row));
+                }
+                // The handler may have seen the rows while they were changed
+                let mut state = model.state.lock().unwrap();
+                let selected = ui
+                    .list_box
+                    .selected_rows()
+                    .iter()
+                    .filter_map(|row| state.light_rows.get(row.index() as usize).cloned())
+                    .collect();
+                state.selected_lights = selected;
             }
         };
-        let update_light_list = Arc::new(update_light_list);
+        let update_light_list = Rc::new(update_light_list);
 
         let fetch_light_list = {
             let update_light_list = update_light_list.clone();
             move |model: Arc<ViewModel<C>>| {
+                let update_light_list = update_light_list.clone();
                 glib::spawn_future_local(async move {
                     {
-                        let light_list = model.client.get_light_list().await.unwrap();
+                        // The connection banner tells about failures, the list is loaded again once it recovers
+                        let (light_list, reachability) = match futures_util::future::try_join(
+                            model.client.get_light_list(),
+                            model.client.get_light_reachability(),
+                        ).await {
+                            Ok(lists) => lists,
+                            Err(e) => {
+                                error!("Failed to load the lights: {:?}", e);
+                                return;
+                            }
+                        };
 
                         let mut state = model.state.lock().unwrap();
                         state.lights = light_list;
+                        state.reachability = reachability;
                     }
+                    model.store_cache();
                     update_light_list();
+
+                    // The on time is counted from here, the events keep it up to date
+                    if let Ok(states) = model.client.get_all_light_states().await {
+                        model.usage.record_states(&states);
+                        // Shown as the colors of the rows
+                        model.state.lock().unwrap().light_states = states;
+                        update_light_list();
+                    }
                 });
             }
         };
+        let fetch_light_list = Rc::new(fetch_light_list);
+
+        {
+            // Right-clicking a light opens a popover to rename or pin it
+            let model = model.clone();
+            let a_ui = ui.clone();
+            let fetch_light_list = fetch_light_list.clone();
+            let update_light_list = update_light_list.clone();
+            let gesture = gtk::GestureClick::builder().button(3).build();
+            gesture.connect_pressed(move |_, _, _, y| {
+                let Some(row) = a_ui.list_box.row_at_y(y as i32) else {
+                    return;
+                };
+                let light = {
+                    let state = model.state.lock().unwrap();
+                    let id = state.light_rows.get(row.index() as usize);
+                    id.and_then(|id| state.lights.find_light_by_id(id)).cloned()
+                };
+                let Some(light) = light else { return };
+                let is_favorite = model.state.lock().unwrap().favorites.contains(&light.id);
+
+                let entry = Entry::builder().text(&light.name).build();
+                let error_label = Label::new(None);
+                let favorite_button = Button::builder()
+                    .label(if is_favorite { "Unpin from top" } else { "Pin to top" })
+                    .build();
+                let popover_layout = gtk::Box::new(Orientation::Vertical, 5);
+                popover_layout.append(&Label::new(Some("Rename")));
+                popover_layout.append(&entry);
+                popover_layout.append(&error_label);
+                popover_layout.append(&favorite_button);
+
+                let popover = gtk::Popover::builder().child(&popover_layout).build();
+                popover.set_parent(&row);
+                popover.connect_closed(|p| p.unparent());
+
+                {
+                    let model = model.clone();
+                    let update_light_list = update_light_list.clone();
+                    let popover = popover.clone();
+                    let light_id = light.id.clone();
+                    favorite_button.connect_clicked(move |_| {
+                        model.toggle_favorite(&light_id);
+                        popover.popdown();
+                        update_light_list();
+                    });
+                }
+
+                {
+                    let model = model.clone();
+                    let fetch_light_list = fetch_light_list.clone();
+                    let popover = popover.clone();
+                    entry.connect_activate(move |entry| {
+                        let name = String::from(entry.text());
+                        let model = model.clone();
+                        let fetch_light_list = fetch_light_list.clone();
+                        let popover = popover.clone();
+                        let error_label = error_label.clone();
+                        let light = light.clone();
+                        glib::spawn_future_local(async move {
+                            match model.client.set_light_name(&light, &name).await {
+                                Ok(()) => {
+                                    popover.popdown();
+                                    fetch_light_list(model);
+                                }
+                                Err(deconz::Error::ApiError { description, .. }) => {
+                                    error_label.set_text(&format!("Error: {}", description));
+                                }
+                                Err(e) => error_label.set_text(&format!("Error: {}", e)),
+                            }
+                        });
+                    });
+                }
+
+                popover.popup();
+            });
+            ui.list_box.add_controller(gesture);
+        }
+
+        {
+            let model = model.clone();
+            ui.list_box.connect_selected_rows_changed(move |list_box| {
+                let mut state = model.state.lock().unwrap();
+                let selected = list_box
+                    .selected_rows()
+                    .iter()
+                    .filter_map(|row| state.light_rows.get(row.index() as usize).cloned())
+                    .collect();
+                state.selected_lights = selected;
+            });
+        }
 
         {
             let model = model.clone();
             let a_ui = ui.clone();
             ui.list_box.connect_row_selected(move |_, row| {
                 if let Some(row) = row {
-                    let label = row.child().unwrap().downcast::<Label>().unwrap();
-                    println!("Row {} was selected", label.text());
-                    a_ui.light_name_label.set_text(&label.text());
-
                     let mut state = model.state.lock().unwrap();
 
-                    // Find the selected light:
-                    let light_index = state.lights.iter().position(|l| &l.name == &label.text());
+                    // The rows are in the same order as the ids, so lights sharing a name are told apart
+                    let Some(id) = state.light_rows.get(row.index() as usize).cloned() else { return };
+                    let Some(light) = state.lights.find_light_by_id(&id).cloned() else { return };
+                    state.selected_light = Some(id);
 
-                    let Some(light) = light_index else { return };
-                    state.selected_index = light;
+                    println!("Row {} was selected", light.name);
+                    a_ui.light_name_label.set_text(&light.name);
+                    a_ui.auto_off_label.set_text("");
+
+                    // Show the last known state until the current one is loaded
+                    state.selected_light_state = state.light_states.get(&light.id).cloned();
+                    if let Some(light_state) = &state.selected_light_state {
+                        a_ui.show_light_state(&light, light_state, model.usage.on_duration(&light.id));
+                    }
+                    drop(state);
 
                     // Load current light state
                     fetch_light_state(model.clone(), a_ui.clone());
@@ -318,111 +1148,1060 @@ impl MainWindow {
             let model = model.clone();
             let a_ui = ui.clone();
             ui.toggle_button.connect_clicked(move |_| {
-                let state = &model.state.lock().unwrap();
-
-                let Some(light_state) = state.selected_light_state else {
-                    return;
-                };
-                let new_on_state = !light_state.on;
+                let model = model.clone();
+                let ui = a_ui.clone();
+                glib::spawn_future_local(async move {
+                    let (light, on) = {
+                        let state = model.state.lock().unwrap();
+                        (state.selected_light().cloned(), state.selected_light_state.as_ref().map(|s| s.on()))
+                    };
+                    let Some(light) = light else { return };
+                    let Some(on) = on else {
+                        // Works even if the state of the light wasn't loaded yet
+                        if let Err(e) = model.client.toggle_on_state(&light).await {
+                            warn!("Failed to toggle the light: {:?}", e);
+                            return;
+                        }
+                        fetch_light_state(model, ui);
+                        return;
+                    };
 
-                {
-                    let model = model.clone();
-                    let ui = a_ui.clone();
-                    glib::spawn_future_local(async move {
-                        model
-                            .client
-                            .set_on_state(
-                                // TODO: Only give the light id so that the mutex is not locked while the request is sent
-                                &*model.state.lock().unwrap().selected_light().unwrap(),
-                                new_on_state,
-                            )
-                            .await
-                            .unwrap();
-
-                        // Update light state
-                        fetch_light_state(model, ui.clone());
-                    });
-                }
+                    // The answer tells what changed, so the state doesn't have to be loaded again
+                    let applied = match model.client.set_on_state(&light, !on).await {
+                        Ok(applied) => applied,
+                        Err(e) => {
+                            warn!("Failed to toggle the light: {:?}", e);
+                            return;
+                        }
+                    };
+                    model.usage.record(&light.id, !on);
+                    if let Some(light_state) = remember_change(&model, &light.id, &applied.as_state_change()) {
+                        ui.show_light_state(&light, &light_state, model.usage.on_duration(&light.id));
+                    }
+                    model.store_cache();
+                });
             });
         }
 
         {
             let model = model.clone();
-            let ui2 = ui.clone();
-            ui.color_control.connect_rgba_notify(move |but| {
-                let col = but.rgba();
+            let a_ui = ui.clone();
+            ui.all_off_button.connect_clicked(move |_| {
+                let model = model.clone();
+                let ui = a_ui.clone();
+                glib::spawn_future_local(async move {
+                    if let Err(e) = model.client.set_group_on_state(&Group::all_lights_group(), false).await {
+                        warn!("Failed to turn all lights off: {:?}", e);
+                        return;
+                    }
+                    fetch_light_state(model, ui);
+                });
+            });
+        }
 
-                let rgb = Rgb::new(col.red(), col.green(), col.blue());
-                let hsv: Hsv = Hsv::from_color(rgb);
-                let h = (hsv.hue.into_positive_degrees()) / 360.0 * u16::MAX as f32;
+        {
+            let model = model.clone();
+            ui.identify_button.connect_clicked(move |_| {
+                let model = model.clone();
+                glib::spawn_future_local(async move {
+                    let light = model.state.lock().unwrap().selected_light().cloned();
+                    let Some(light) = light else { return };
+                    if let Err(e) = model.client.alert(&light, AlertMode::Select).await {
+                        warn!("Failed to identify the light: {:?}", e);
+                    }
+                });
+            });
+        }
 
-                dbg!(hsv.value);
-                ui2.brightness_slider.set_value(hsv.value as f64 * 255.0);
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.effect_dropdown.connect_selected_notify(move |dropdown| {
+                if a_ui.showing_state.get() {
+                    return;
+                }
+                let selected = dropdown.selected() as usize;
 
                 let model = model.clone();
                 glib::spawn_future_local(async move {
-                    // Convert color from rgb to hsb
-
-                    let state = model.state.lock().unwrap(); // TODO: fix this lock staying while the request is done. fr this time
-                    let light = state.selected_light().unwrap(); // todo fix unwrap
-                    model
-                        .client
-                        .set_light_color(
-                            light,
-                            Some(h as u16),
-                            Some((hsv.value * 255.0) as u8),
-                            Some((hsv.saturation * 255.0) as u8),
-                        )
-                        .await
-                        .unwrap();
+                    let light = model.state.lock().unwrap().selected_light().cloned();
+                    let Some(light) = light else { return };
+                    let Some(effect) = light.capabilities.effects.get(selected).cloned() else {
+                        return;
+                    };
+                    if let Err(e) = model.client.set_effect(&light, effect).await {
+                        warn!("Failed to set the effect of {}: {:?}", light.name, e);
+                    }
                 });
             });
         }
 
         {
             let model = model.clone();
-            let ui2 = ui.clone();
-            ui.brightness_slider.connect_value_changed(move |s| {
-                let val = s.value() as u8;
+            let a_ui = ui.clone();
+            ui.speed_slider.connect_value_changed(move |s| {
+                if a_ui.showing_state.get() {
+                    return;
+                }
+                let speed = s.value() as u8;
 
                 let model = model.clone();
                 glib::spawn_future_local(async move {
-                    let state = model.state.lock().unwrap();
-                    let light = state.selected_light().unwrap();
-
-                    model
-                        .client
-                        .set_light_color(light, None, Some(val), None)
-                        .await
-                        .unwrap();
+                    let light = model.state.lock().unwrap().selected_light().cloned();
+                    let Some(light) = light else { return };
+                    if let Err(e) = model.client.set_speed(&light, speed).await {
+                        warn!("Failed to set the speed of {}: {:?}", light.name, e);
+                    }
                 });
             });
         }
+
         {
-            let update_light_list = update_light_list.clone();
-            ui.search_bar.connect_changed(move |_| {
-                update_light_list();
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.auto_off_button.connect_clicked(move |_| {
+                let light = model.state.lock().unwrap().selected_light().cloned();
+                let Some(light) = light else { return };
+                let minutes = a_ui.auto_off_minutes.value_as_int() as u64;
+                let schedule = NewSchedule {
+                    name: format!("Auto off {}", light.id),
+                    description: format!("Turns {} off", light.name),
+                    command: ScheduleCommand::light_on_state(&light, false),
+                    time: ScheduleTime::Timer {
+                        duration: std::time::Duration::from_secs(minutes * 60),
+                        repeat: Repeat::Once,
+                    },
+                    autodelete: true,
+                };
+
+                let model = model.clone();
+                let ui = a_ui.clone();
+                glib::spawn_future_local(async move {
+                    match model.client.create_schedule(&schedule).await {
+                        Ok(_) => ui
+                            .auto_off_label
+                            .set_text(&format!("Turns off in {} minutes", minutes)),
+                        Err(e) => ui.auto_off_label.set_text(&format!("Error: {}", e)),
+                    }
+                });
             });
         }
-        println!("UI logic attached");
-        fetch_light_list(model);
-    }
+
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.power_on_apply_button.connect_clicked(move |_| {
+                let state = model.state.lock().unwrap();
+                let Some(light) = state.selected_light().cloned() else { return };
+                let behavior = match a_ui.power_on_dropdown.selected() {
+                    0 => PowerOnBehavior::LastState,
+                    1 => PowerOnBehavior::On,
+                    2 => PowerOnBehavior::Off,
+                    _ => match &state.selected_light_state {
+                        Some(light_state) => PowerOnBehavior::Custom(light_state.clone()),
+                        None => return,
+                    },
+                };
+                drop(state);
+
+                a_ui.power_on_error_label.set_text("");
+                let model = model.clone();
+                let ui = a_ui.clone();
+                glib::spawn_future_local(async move {
+                    match model.client.set_power_on_behavior(&light, behavior).await {
+                        Ok(()) => ui.power_on_error_label.set_text("Saved"),
+                        Err(deconz::Error::Unsupported(message)) => {
+                            ui.power_on_error_label.set_text(&message)
+                        }
+                        Err(e) => ui.power_on_error_label.set_text(&format!("Error: {}", e)),
+                    }
+                });
+            });
+        }
+
+        {
+            let model = model.clone();
+            let ui2 = ui.clone();
+            ui.color_control.connect_rgba_notify(move |but| {
+                if ui2.showing_state.get() {
+                    return;
+                }
+                let rgb = rgba_to_rgb(&but.rgba());
+                let (hue, sat, bri) = rgb_to_deconz_hsv(rgb);
+                let command = if ui2.xy_toggle.is_active() {
+                    ColorCommand {
+                        xy: Some(rgb_to_xy(rgb)),
+                        bri: Some(bri),
+                        ..Default::default()
+                    }
+                } else {
+                    ColorCommand {
+                        hue: Some(hue),
+                        sat: Some(sat),
+                        bri: Some(bri),
+                        ..Default::default()
+                    }
+                };
+
+                ui2.brightness_slider.set_value(brightness_slider_value(bri));
+
+                let model = model.clone();
+                glib::spawn_future_local(async move {
+                    let selected: Vec<Light> = {
+                        let state = model.state.lock().unwrap();
+                        state
+                            .selected_lights
+                            .iter()
+                            .filter_map(|id| state.lights.find_light_by_id(id).cloned())
+                            .collect()
+                    };
+                    if selected.len() > 1 {
+                        let lights: Vec<&Light> = selected.iter().collect();
+                        if let Err(e) = model.client.set_lights_color(&lights, Some(hue), Some(bri), Some(sat)).await {
+                            warn!("Failed to paint the selected lights: {}", e);
+                        }
+                        return;
+                    }
+
+                    let light = model.state.lock().unwrap().selected_light().cloned();
+                    let Some(light) = light else { return };
+                    let command = ColorCommand {
+                        bri: command.bri.map(|bri| model.client.clamp_brightness(&light, bri)),
+                        ..command
+                    };
+                    send_color(&model, &light, command).await;
+                });
+            });
+        }
+
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.white_toggle.connect_toggled(move |button| {
+                if a_ui.showing_state.get() {
+                    return;
+                }
+                let light = model.state.lock().unwrap().selected_light().cloned();
+                if let Some(light) = light {
+                    a_ui.show_color_controls(&light, button.is_active());
+                }
+            });
+        }
+
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.ct_slider.connect_value_changed(move |s| {
+                if a_ui.showing_state.get() {
+                    return;
+                }
+                let ct = s.value() as u16;
+
+                let model = model.clone();
+                glib::spawn_future_local(async move {
+                    let light = model.state.lock().unwrap().selected_light().cloned();
+                    let Some(light) = light else { return };
+
+                    let command = ColorCommand {
+                        ct: Some(ct),
+                        ..Default::default()
+                    };
+                    send_color(&model, &light, command).await;
+                });
+            });
+        }
+
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.brightness_slider.connect_value_changed(move |s| {
+                if a_ui.showing_state.get() {
+                    return;
+                }
+                let val = perceptual_to_bri(s.value() as f32 / 100.0);
+
+                let model = model.clone();
+                glib::spawn_future_local(async move {
+                    let light = model.state.lock().unwrap().selected_light().cloned();
+                    let Some(light) = light else { return };
+
+                    // A short transition keeps dragging the slider from flashing the light
+                    let command = ColorCommand {
+                        bri: Some(val),
+                        transition_time: Some(4),
+                        ..Default::default()
+                    };
+                    send_color(&model, &light, command).await;
+                });
+            });
+        }
+        {
+            // The arrow keys step the brightness on the gateway instead of moving the slider by 1
+            let model = model.clone();
+            let a_ui = ui.clone();
+            let keys = gtk::EventControllerKey::new();
+            keys.set_propagation_phase(gtk::PropagationPhase::Capture);
+            keys.connect_key_pressed(move |_, key, _, _| {
+                let delta = match key {
+                    gdk::Key::Up => 16,
+                    gdk::Key::Down => -16,
+                    _ => return glib::Propagation::Proceed,
+                };
+
+                let model = model.clone();
+                let ui = a_ui.clone();
+                glib::spawn_future_local(async move {
+                    let light = model.state.lock().unwrap().selected_light().cloned();
+                    let Some(light) = light else { return };
+                    if let Err(e) = model.client.step_brightness(&light, delta).await {
+                        warn!("Failed to change the brightness: {:?}", e);
+                        return;
+                    }
+
+                    // Show the brightness the gateway clamped the step to
+                    fetch_light_state(model, ui);
+                });
+                glib::Propagation::Stop
+            });
+            ui.brightness_slider.add_controller(keys);
+        }
+        {
+            // On the color button the arrow keys nudge the hue (left, right) and saturation (up, down)
+            let model = model.clone();
+            let a_ui = ui.clone();
+            let keys = gtk::EventControllerKey::new();
+            keys.set_propagation_phase(gtk::PropagationPhase::Capture);
+            keys.connect_key_pressed(move |_, key, _, _| {
+                let (hue_delta, sat_delta) = match key {
+                    gdk::Key::Left => (-2048, 0),
+                    gdk::Key::Right => (2048, 0),
+                    gdk::Key::Up => (0, 16),
+                    gdk::Key::Down => (0, -16),
+                    _ => return glib::Propagation::Proceed,
+                };
+
+                let model = model.clone();
+                let ui = a_ui.clone();
+                glib::spawn_future_local(async move {
+                    let light = model.state.lock().unwrap().selected_light().cloned();
+                    let Some(light) = light else { return };
+                    let result = if hue_delta != 0 {
+                        model.client.step_hue(&light, hue_delta).await
+                    } else {
+                        model.client.step_saturation(&light, sat_delta).await
+                    };
+                    if let Err(e) = result {
+                        warn!("Failed to change the color of {}: {}", light.name, e);
+                        return;
+                    }
+
+                    // The gateway wraps the hue and clamps the saturation
+                    fetch_light_state(model, ui);
+                });
+                glib::Propagation::Stop
+            });
+            ui.color_control.add_controller(keys);
+        }
+        {
+            // Typing fast only filters the list once the typing pauses
+            let update_light_list = update_light_list.clone();
+            let pending: Rc<Cell<Option<glib::SourceId>>> = Rc::default();
+            ui.search_bar.connect_changed(move |_| {
+                if let Some(source) = pending.take() {
+                    source.remove();
+                }
+                let update_light_list = update_light_list.clone();
+                let fired = pending.clone();
+                pending.set(Some(glib::timeout_add_local_once(SEARCH_DEBOUNCE, move || {
+                    fired.set(None);
+                    update_light_list();
+                })));
+            });
+        }
+
+        let update_group_list = {
+            let ui = ui.clone();
+            let model = model.clone();
+            move || {
+                while let Some(child) = ui.group_list_box.first_child() {
+                    ui.group_list_box.remove(&child);
+                }
+
+                let groups = model.state.lock().unwrap().groups.clone();
+                for group in groups {
+                    let row = gtk::Box::new(Orientation::Horizontal, 5);
+                    // Colored like the room while any of its lights is on
+                    let on_indicator = Label::builder().label("●").css_classes(["dim-label"]).build();
+                    row.append(&on_indicator);
+                    let name_label = gtk::EditableLabel::builder()
+                        .text(&group.name)
+                        .hexpand(true)
+                        .tooltip_text("Click to rename the group")
+                        .build();
+                    row.append(&name_label);
+
+                    let group = Rc::new(group);
+                    {
+                        // The name is sent once editing ends, a rejected name is undone
+                        let model = model.clone();
+                        let group = group.clone();
+                        let saved_name = Rc::new(RefCell::new(group.name.clone()));
+                        name_label.connect_editing_notify(move |label| {
+                            let name = label.text().trim().to_string();
+                            if label.is_editing() || name.is_empty() || name == *saved_name.borrow() {
+                                label.set_text(&saved_name.borrow());
+                                return;
+                            }
+
+                            let model = model.clone();
+                            let group = group.clone();
+                            let saved_name = saved_name.clone();
+                            let label = label.clone();
+                            glib::spawn_future_local(async move {
+                                match model.client.set_group_name(&group, &name).await {
+                                    Ok(()) => {
+                                        if let Some(g) = model.state.lock().unwrap().groups.iter_mut().find(|g| g.id == group.id) {
+                                            g.name = name.clone();
+                                        }
+                                        *saved_name.borrow_mut() = name;
+                                    }
+                                    Err(e) => {
+                                        warn!("Failed to rename {}: {}", saved_name.borrow(), e);
+                                        label.set_text(&saved_name.borrow());
+                                    }
+                                }
+                            });
+                        });
+                    }
+                    let refresh_indicator = {
+                        let model = model.clone();
+                        let group = group.clone();
+                        move || {
+                            let model = model.clone();
+                            let group = group.clone();
+                            let on_indicator = on_indicator.clone();
+                            glib::spawn_future_local(async move {
+                                let group_state = match model.client.get_group_state(&group).await {
+                                    Ok(group_state) => group_state,
+                                    Err(e) => {
+                                        warn!("Failed to load the state of {}: {}", group.name, e);
+                                        return;
+                                    }
+                                };
+                                let tooltip = match (group_state.any_on, group_state.all_on) {
+                                    (_, true) => "All lights are on",
+                                    (true, false) => "Some lights are on",
+                                    (false, _) => "All lights are off",
+                                };
+                                on_indicator.set_tooltip_text(Some(tooltip));
+                                if group_state.any_on {
+                                    let rgb: palette::Srgb<u8> = state_rgb(&group_state.action).into_format();
+                                    on_indicator.remove_css_class("dim-label");
+                                    on_indicator.set_markup(&format!(
+                                        "<span foreground=\"#{:02x}{:02x}{:02x}\">●</span>",
+                                        rgb.red, rgb.green, rgb.blue
+                                    ));
+                                } else {
+                                    on_indicator.add_css_class("dim-label");
+                                    on_indicator.set_text("●");
+                                }
+                            });
+                        }
+                    };
+                    let refresh_indicator = Rc::new(refresh_indicator);
+                    refresh_indicator();
+                    for (label, on) in [("On", true), ("Off", false)] {
+                        let button = Button::builder().label(label).build();
+                        let model = model.clone();
+                        let group = group.clone();
+                        let refresh_indicator = refresh_indicator.clone();
+                        button.connect_clicked(move |_| {
+                            let model = model.clone();
+                            let group = group.clone();
+                            let refresh_indicator = refresh_indicator.clone();
+                            glib::spawn_future_local(async move {
+                                if let Err(e) = group.set_on(&model.client, on).await {
+                                    warn!("Failed to switch {}: {}", group.display_name(), e);
+                                }
+                                refresh_indicator();
+                            });
+                        });
+                        row.append(&button);
+                    }
+
+                    let dialog = ColorDialog::builder().with_alpha(false).build();
+                    let color_button = ColorDialogButton::builder().dialog(&dialog).build();
+                    {
+                        let model = model.clone();
+                        let group = group.clone();
+                        color_button.connect_rgba_notify(move |but| {
+                            let (hue, sat, bri) = rgb_to_deconz_hsv(rgba_to_rgb(&but.rgba()));
+
+                            let model = model.clone();
+                            let group = group.clone();
+                            glib::spawn_future_local(async move {
+                                if let Err(e) = group.set_color(&model.client, Some(hue), Some(bri), Some(sat)).await {
+                                    warn!("Failed to change the color of {}: {}", group.display_name(), e);
+                                }
+                            });
+                        });
+                    }
+                    row.append(&color_button);
+
+                    let delete_button = Button::builder()
+                        .icon_name("user-trash-symbolic")
+                        .tooltip_text("Deletes the group, its lights are kept")
+                        .build();
+                    {
+                        let model = model.clone();
+                        let ui = ui.clone();
+                        let group = group.clone();
+                        let row = row.clone();
+                        delete_button.connect_clicked(move |_| {
+                            let model = model.clone();
+                            let ui = ui.clone();
+                            let group = group.clone();
+                            let row = row.clone();
+                            glib::spawn_future_local(async move {
+                                if let Err(e) = model.client.delete_group(&group).await {
+                                    warn!("Failed to delete group {}: {:?}", group.name, e);
+                                    return;
+                                }
+                                model.state.lock().unwrap().groups.retain(|g| g.id != group.id);
+                                if let Some(list_row) = row.parent() {
+                                    ui.group_list_box.remove(&list_row);
+                                }
+                            });
+                        });
+                    }
+                    row.append(&delete_button);
+
+                    ui.group_list_box.append(&row);
+                }
+            }
+        };
+        let update_group_list = Rc::new(update_group_list);
+
+        {
+            // The rules are loaded whenever the page is opened, as they are changed in other apps
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.sidebar_stack.connect_visible_child_name_notify(move |stack| {
+                if stack.visible_child_name().as_deref() != Some("automations") {
+                    return;
+                }
+
+                let model = model.clone();
+                let ui = a_ui.clone();
+                glib::spawn_future_local(async move {
+                    let rules = match model.client.get_rules().await {
+                        Ok(rules) => rules,
+                        Err(e) => {
+                            warn!("Failed to load the automations: {}", e);
+                            return;
+                        }
+                    };
+
+                    while let Some(child) = ui.rule_list_box.first_child() {
+                        ui.rule_list_box.remove(&child);
+                    }
+                    for rule in rules {
+                        let row = gtk::Box::new(Orientation::Horizontal, 5);
+                        let conditions: Vec<String> = rule.conditions.iter().map(ToString::to_string).collect();
+                        let actions: Vec<String> = rule.actions.iter().map(ToString::to_string).collect();
+                        row.append(
+                            &Label::builder()
+                                .label(&rule.name)
+                                .hexpand(true)
+                                .xalign(0.0)
+                                .tooltip_text(format!("When {}\nthen {}", conditions.join(" and "), actions.join(", ")))
+                                .build(),
+                        );
+
+                        let switch = gtk::Switch::builder()
+                            .active(rule.enabled)
+                            .tooltip_text("Pauses the automation while it is off")
+                            .build();
+                        let model = model.clone();
+                        // Set while a failed change is undone, which mustn't be sent again
+                        let reverting = Rc::new(Cell::new(false));
+                        switch.connect_active_notify(move |switch| {
+                            if reverting.get() {
+                                return;
+                            }
+                            let model = model.clone();
+                            let rule = rule.clone();
+                            let switch = switch.clone();
+                            let reverting = reverting.clone();
+                            let enabled = switch.is_active();
+                            glib::spawn_future_local(async move {
+                                if let Err(e) = model.client.set_rule_enabled(&rule, enabled).await {
+                                    warn!("Failed to switch the automation {}: {}", rule.name, e);
+                                    reverting.set(true);
+                                    switch.set_active(!enabled);
+                                    reverting.set(false);
+                                }
+                            });
+                        });
+                        row.append(&switch);
+                        ui.rule_list_box.append(&row);
+                    }
+                });
+            });
+        }
+
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            let update_group_list = update_group_list.clone();
+            ui.new_group_button.connect_clicked(move |_| {
+                let name = a_ui.new_group_name.text().trim().to_string();
+                if name.is_empty() {
+                    return;
+                }
+
+                let model = model.clone();
+                let ui = a_ui.clone();
+                let update_group_list = update_group_list.clone();
+                glib::spawn_future_local(async move {
+                    // Groups may share a name, the new one is told apart by its id
+                    match model.client.create_group(&name).await {
+                        Ok(group) => {
+                            model.state.lock().unwrap().groups.push(group);
+                            ui.new_group_name.set_text("");
+                            update_group_list();
+                        }
+                        Err(e) => warn!("Failed to create group {}: {:?}", name, e),
+                    }
+                });
+            });
+        }
+
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.group_list_box.connect_row_selected(move |_, row| {
+                let Some(row) = row else { return };
+
+                let group = model.state.lock().unwrap().groups.get(row.index() as usize).cloned();
+                let Some(group) = group else { return };
+                a_ui.scene_label.set_text(&format!("Scenes of {}", group.name));
+                model.state.lock().unwrap().selected_group = Some(group.clone());
+
+                let model = model.clone();
+                let ui = a_ui.clone();
+                glib::spawn_future_local(async move {
+                    let scenes = match model.client.get_scenes(&group).await {
+                        Ok(scenes) => scenes,
+                        Err(e) => {
+                            warn!("Failed to load the scenes of {}: {}", group.name, e);
+                            return;
+                        }
+                    };
+
+                    let names: Vec<&str> = scenes.iter().map(|s| s.name.as_str()).collect();
+                    let names = StringList::new(&names);
+                    // Stored first, as setting the model may fire the handler showing the preview
+                    model.state.lock().unwrap().scenes = scenes;
+                    ui.scene_dropdown.set_model(Some(&names));
+                    ui.scene_layout.set_visible(true);
+                    fetch_scene_preview(model, ui);
+                });
+            });
+        }
+
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.scene_dropdown.connect_selected_notify(move |_| {
+                fetch_scene_preview(model.clone(), a_ui.clone());
+            });
+        }
+
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.scene_name_entry.connect_activate(move |entry| {
+                let name = entry.text().trim().to_string();
+                let selected = a_ui.scene_dropdown.selected();
+                let scene = model.state.lock().unwrap().scenes.get(selected as usize).cloned();
+                let Some(scene) = scene else { return };
+                if name.is_empty() || name == scene.name {
+                    return;
+                }
+
+                let model = model.clone();
+                let ui = a_ui.clone();
+                glib::spawn_future_local(async move {
+                    match model.client.set_scene_name(&scene, &name).await {
+                        Ok(()) => {
+                            if let Some(s) = model.state.lock().unwrap().scenes.get_mut(selected as usize) {
+                                s.name = name.clone();
+                            }
+                            if let Some(names) = ui.scene_dropdown.model().and_downcast::<StringList>() {
+                                names.splice(selected, 1, &[name.as_str()]);
+                                ui.scene_dropdown.set_selected(selected);
+                            }
+                        }
+                        Err(deconz::Error::ApiError { description, .. }) => {
+                            ui.scene_name_error_label.set_text(&format!("Error: {}", description));
+                        }
+                        Err(e) => ui.scene_name_error_label.set_text(&format!("Error: {}", e)),
+                    }
+                });
+            });
+        }
+
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.scene_recall_button.connect_clicked(move |_| {
+                let selected = a_ui.scene_dropdown.selected() as usize;
+                let scene = model.state.lock().unwrap().scenes.get(selected).cloned();
+                let Some(scene) = scene else { return };
+
+                let model = model.clone();
+                glib::spawn_future_local(async move {
+                    if let Err(e) = model.client.recall_scene(&scene).await {
+                        warn!("Failed to recall the scene {}: {}", scene.name, e);
+                    }
+                });
+            });
+        }
+
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.scene_store_button.connect_clicked(move |_| {
+                let selected = a_ui.scene_dropdown.selected() as usize;
+                let scene = model.state.lock().unwrap().scenes.get(selected).cloned();
+                let Some(scene) = scene else { return };
+
+                let model = model.clone();
+                let ui = a_ui.clone();
+                glib::spawn_future_local(async move {
+                    match model.client.store_scene(&scene).await {
+                        Ok(()) => fetch_scene_preview(model, ui),
+                        Err(e) => ui.scene_name_error_label.set_text(&format!("Error: {}", e)),
+                    }
+                });
+            });
+        }
+
+        {
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.new_scene_name.connect_activate(move |entry| {
+                let name = entry.text().trim().to_string();
+                let group = model.state.lock().unwrap().selected_group.clone();
+                let Some(group) = group else { return };
+                if name.is_empty() {
+                    return;
+                }
+
+                let model = model.clone();
+                let ui = a_ui.clone();
+                glib::spawn_future_local(async move {
+                    match model.client.create_scene(&group, &name).await {
+                        Ok(scene) => {
+                            ui.new_scene_name.set_text("");
+                            let index = {
+                                let mut state = model.state.lock().unwrap();
+                                state.scenes.push(scene);
+                                state.scenes.len() as u32 - 1
+                            };
+                            if let Some(names) = ui.scene_dropdown.model().and_downcast::<StringList>() {
+                                names.append(&name);
+                            }
+                            ui.scene_dropdown.set_selected(index);
+                        }
+                        Err(deconz::Error::ApiError { description, .. }) => {
+                            ui.scene_name_error_label.set_text(&format!("Error: {}", description));
+                        }
+                        Err(e) => ui.scene_name_error_label.set_text(&format!("Error: {}", e)),
+                    }
+                });
+            });
+        }
+
+        {
+            // The check boxes are rebuilt on every opening to reflect the latest group list
+            let model = model.clone();
+            let a_ui = ui.clone();
+            ui.group_popover.connect_show(move |_| {
+                let layout = &a_ui.group_membership_layout;
+                while let Some(child) = layout.first_child() {
+                    layout.remove(&child);
+                }
+
+                let state = model.state.lock().unwrap();
+                let Some(light) = state.selected_light().cloned() else { return };
+                for group in &state.groups {
+                    let check = gtk::CheckButton::builder()
+                        .label(&group.name)
+                        .active(group.lights.contains(&light.id))
+                        .build();
+
+                    let model = model.clone();
+                    let group = group.clone();
+                    let light = light.clone();
+                    check.connect_toggled(move |check| {
+                        let add = check.is_active();
+                        let model = model.clone();
+                        let group = group.clone();
+                        let light = light.clone();
+                        glib::spawn_future_local(async move {
+                            let result = if add {
+                                model.client.add_light_to_group(&group, &light).await
+                            } else {
+                                model.client.remove_light_from_group(&group, &light).await
+                            };
+                            if let Err(e) = result {
+                                warn!("Failed to change the lights of {}: {:?}", group.name, e);
+                            }
+                            // The next edit starts from what the gateway has now
+                            if let Ok(groups) = model.client.get_group_list().await {
+                                model.state.lock().unwrap().groups = groups;
+                            }
+                        });
+                    });
+                    layout.append(&check);
+                }
+            });
+        }
+
+        {
+            let model = model.clone();
+            glib::spawn_future_local(async move {
+                // The list stays empty if the gateway can't be reached
+                let group_list = match model.client.get_group_list().await {
+                    Ok(group_list) => group_list,
+                    Err(e) => {
+                        warn!("Failed to load the groups: {}", e);
+                        return;
+                    }
+                };
+                model.state.lock().unwrap().groups = group_list;
+                update_group_list();
+            });
+        }
+
+        {
+            // Show the ambient temperature if there is a thermometer
+            let model = model.clone();
+            let ui = ui.clone();
+            glib::spawn_future_local(async move {
+                let sensors = match model.client.get_sensors().await {
+                    Ok(sensors) => sensors,
+                    Err(e) => {
+                        warn!("Failed to load the sensors: {}", e);
+                        ui.temperature_label.set_visible(false);
+                        return;
+                    }
+                };
+                let temperature = sensors.iter().find_map(|s| match s.reading {
+                    SensorReading::Temperature(t) => Some(t),
+                    _ => None,
+                });
+                if let Some(temperature) = temperature {
+                    ui.temperature_label
+                        .set_text(&format!("Temperature: {:.1} °C", temperature));
+                    ui.temperature_label.set_visible(true);
+                }
+            });
+        }
+
+        {
+            // Show a banner while the gateway can't be reached and catch up once it can again
+            let model = model.clone();
+            let ui = ui.clone();
+            let fetch_light_list = fetch_light_list.clone();
+            glib::spawn_future_local(async move {
+                let mut connection = model.client.watch_connection_state();
+                let mut was_connected = true;
+                loop {
+                    let state = connection.borrow_and_update().clone();
+                    match &state {
+                        ConnectionState::Connected => {
+                            ui.connection_banner.set_visible(false);
+                            if !was_connected {
+                                fetch_light_list(model.clone());
+                                fetch_light_state(model.clone(), ui.clone());
+                            }
+                        }
+                        ConnectionState::Reconnecting => {
+                            ui.connection_banner.set_label("Connection to the gateway lost, reconnecting…");
+                            ui.connection_banner.set_tooltip_text(None);
+                            ui.connection_banner.set_visible(true);
+                        }
+                        ConnectionState::Disconnected(error) => {
+                            ui.connection_banner.set_label("The gateway can't be reached, changes are sent once it is back");
+                            ui.connection_banner.set_tooltip_text(Some(error));
+                            ui.connection_banner.set_visible(true);
+                        }
+                    }
+                    was_connected = state == ConnectionState::Connected;
+
+                    // Clients which are always connected drop the sender right away
+                    if connection.changed().await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+
+        {
+            // Keep the controls in sync with changes made by other clients
+            let model = model.clone();
+            let ui = ui.clone();
+            let fetch_light_list = fetch_light_list.clone();
+            let update_light_list = update_light_list.clone();
+            glib::spawn_future_local(async move {
+                let apply_event = |event: LightEvent| {
+                    model.usage.record_event(&event);
+                    if let LightEvent::Reconnected = event {
+                        // The gateway may have restarted, whatever changed meanwhile was missed
+                        fetch_light_list(model.clone());
+                        fetch_light_state(model.clone(), ui.clone());
+                        return;
+                    }
+                    let LightEvent::StateChanged { id, change } = event else {
+                        return;
+                    };
+
+                    let mut state = model.state.lock().unwrap();
+                    // The rows of the light list show the colors
+                    if let Some(light_state) = state.light_states.get_mut(&id) {
+                        change.apply_to(light_state);
+                        drop(state);
+                        update_light_list();
+                        state = model.state.lock().unwrap();
+                    }
+                    let Some(light) = state.selected_light().filter(|l| l.id == id).cloned() else {
+                        return;
+                    };
+                    let Some(light_state) = state.selected_light_state.as_mut() else {
+                        return;
+                    };
+                    change.apply_to(light_state);
+                    let light_state = light_state.clone();
+                    drop(state);
+
+                    ui.show_light_state(&light, &light_state, model.usage.on_duration(&light.id));
+                };
+
+                let events = model.client.subscribe_events();
+                let mut events = std::pin::pin!(events);
+                loop {
+                    match events.next().await {
+                        Some(Ok(event)) => apply_event(event),
+                        Some(Err(e)) => {
+                            warn!("Event stream failed, polling instead: {:?}", e);
+                            break;
+                        }
+                        None => return,
+                    }
+                }
+
+                // Gateways without an exposed websocket still get updates, just a little later
+                let events = model.client.watch_light_states(LIGHT_POLL_INTERVAL);
+                let mut events = std::pin::pin!(events);
+                while let Some(event) = events.next().await {
+                    match event {
+                        Ok(event) => apply_event(event),
+                        Err(e) => warn!("Failed to poll light states: {:?}", e),
+                    }
+                }
+            });
+        }
+
+        {
+            // Automations triggered by motion may have changed lights, so reload them
+            let model = model.clone();
+            let ui = ui.clone();
+            let fetch_light_list = fetch_light_list.clone();
+            glib::spawn_future_local(async move {
+                let events = model.client.subscribe_presence_events();
+                let mut events = std::pin::pin!(events);
+                while let Some(event) = events.next().await {
+                    match event {
+                        Ok(SensorEvent { reading: SensorReading::Presence(true), .. }) => {
+                            fetch_light_list(model.clone());
+                            fetch_light_state(model.clone(), ui.clone());
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            warn!("Sensor event stream failed: {}", e);
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+
+        {
+            // Running on times are only written when stored, so store them now and then
+            let model = model.clone();
+            glib::spawn_future_local(async move {
+                loop {
+                    glib::timeout_future(USAGE_STORE_INTERVAL).await;
+                    model.store_usage();
+                }
+            });
+        }
+
+        {
+            let model = model.clone();
+            ui.window.connect_close_request(move |_| {
+                model.store_usage();
+                glib::Propagation::Proceed
+            });
+        }
+
+        println!("UI logic attached");
+        if !model.state.lock().unwrap().lights.is_empty() {
+            // Lights from the cache
+            update_light_list();
+        }
+        fetch_light_list(model);
+    }
 }
 
+/// How often light states are polled when the gateway's websocket can't be used
+const LIGHT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+/// How long the search waits for the next keystroke before filtering the light list
+const SEARCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(150);
+/// How often the on times of the lights are written to disk
+const USAGE_STORE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// How often the latency of the gateway is measured
+const PING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+/// How soon the gateway is pinged again after it couldn't be reached
+const PING_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Called with the url of the gateway, the token, the name of the gateway and whether
+/// self-signed certificates are accepted
+type LoginCallback = Box<dyn Fn(&SetupWindow, String, String, String, bool)>;
+
 struct SetupWindow {
     window: ApplicationWindow,
+    /// Gateways found in the network, hidden if discovery didn't find any
+    gateway_dropdown: DropDown,
     ip_field: Entry,
+    /// Tells whether a gateway answers at the entered address
+    gateway_status: Label,
+    /// Accepts the self-signed certificate of an https gateway, off by default
+    allow_self_signed: gtk::CheckButton,
     link_button: Button,
     error_msg: Label,
     demo_button: Button,
-    on_login_completed: Box<dyn Fn(&SetupWindow, String, String)>,
+    on_login_completed: LoginCallback,
     on_user_requested_demo: Box<dyn Fn(&SetupWindow)>
 }
 
 impl SetupWindow {
     fn new(
         app: &gtk::Application,
-        on_login_completed: Box<dyn Fn(&SetupWindow, String, String)>,
+        on_login_completed: LoginCallback,
         on_user_requested_demo: Box<dyn Fn(&SetupWindow)>,
     ) -> Self {
         let window = gtk::ApplicationWindow::new(app);
@@ -438,20 +2217,41 @@ impl SetupWindow {
 
         layout.append(&label);
 
+        let gateway_dropdown = DropDown::builder()
+            .tooltip_text("Gateways found in your network")
+            .visible(false)
+            .build();
+        layout.append(&gateway_dropdown);
+
         let ip_field = Entry::builder()
-            .placeholder_text("Deconz Server address")
+            .placeholder_text("Deconz Server address, e.g. 192.168.0.2 or phoscon.local")
             .build();
         layout.append(&ip_field);
 
+        let gateway_status = Label::builder().label("").build();
+        layout.append(&gateway_status);
+
         let label = Label::builder()
             .label("please click the link button on your deconz server, then click the button here")
             .build();
 
         layout.append(&label);
 
-        let label = Label::builder().label("The \"username\" which is used to authenticate users is saved in clear text").build();
+        let label = Label::builder()
+            .label(if cfg!(feature = "keyring") {
+                "The \"username\" which is used to authenticate users is saved in the keyring of your system"
+            } else {
+                "The \"username\" which is used to authenticate users is saved in clear text"
+            })
+            .build();
         layout.append(&label);
 
+        let allow_self_signed = gtk::CheckButton::builder()
+            .label("Allow self-signed certificate")
+            .tooltip_text("Only for https gateways you trust, anyone in your network could pretend to be the gateway")
+            .build();
+        layout.append(&allow_self_signed);
+
         let link_button = Button::builder().label("Login").build();
         layout.append(&link_button);
 
@@ -464,21 +2264,108 @@ impl SetupWindow {
         window.set_child(Some(&layout));
         window.present();
 
-        let w = Self {
+        Self {
             window,
+            gateway_dropdown,
             ip_field,
+            gateway_status,
+            allow_self_signed,
             link_button,
             on_login_completed,
             on_user_requested_demo,
             demo_button,
             error_msg
-        };
-
-        w
+        }
     }
 
     fn add_logic(self) {
-        let s = Arc::new(self);
+        let s = Rc::new(self);
+
+        {
+            // Offer the gateways found in the network, the address can still be typed in manually
+            let s = s.clone();
+            glib::spawn_future_local(async move {
+                // The cloud doesn't work offline and SSDP is often blocked, so both are tried
+                let (cloud, local) = futures_util::future::join(
+                    deconz::discover_gateways(),
+                    deconz::discover_gateways_local(),
+                )
+                .await;
+
+                let mut gateways: Vec<DiscoveredGateway> = vec![];
+                for result in [cloud, local] {
+                    match result {
+                        Ok(found) => {
+                            for gateway in found {
+                                if !gateways.iter().any(|g| g.url() == gateway.url()) {
+                                    gateways.push(gateway);
+                                }
+                            }
+                        }
+                        Err(e) => warn!("Gateway discovery failed: {:?}", e),
+                    }
+                }
+                let Some(first) = gateways.first() else { return };
+
+                let urls: Vec<String> = gateways.iter().map(|g| g.url()).collect();
+                let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+                s.gateway_dropdown.set_model(Some(&StringList::new(&urls)));
+                s.gateway_dropdown.set_visible(true);
+                if s.ip_field.text().is_empty() {
+                    s.ip_field.set_text(&first.url());
+                }
+
+                let ip_field = s.ip_field.clone();
+                s.gateway_dropdown.connect_selected_notify(move |dropdown| {
+                    let Some(item) = dropdown.selected_item() else { return };
+                    let url = item.downcast::<gtk::StringObject>().unwrap().string();
+                    ip_field.set_text(&url);
+                });
+            });
+        }
+
+        {
+            // Check the address while it is typed, so the user knows when to press the link button
+            let generation = Rc::new(Cell::new(0u32));
+            let s_c = s.clone();
+            s.ip_field.connect_changed(move |field| {
+                let s = s_c.clone();
+                let generation = generation.clone();
+                let current = generation.get() + 1;
+                generation.set(current);
+                s.gateway_status.set_text("");
+
+                let address = String::from(field.text());
+                if address.is_empty() {
+                    return;
+                }
+                glib::spawn_future_local(async move {
+                    // Only probe once the user stopped typing
+                    glib::timeout_future(std::time::Duration::from_millis(500)).await;
+                    if generation.get() != current {
+                        return;
+                    }
+                    let builder = DeconzClient::builder()
+                        .danger_accept_invalid_certs(s.allow_self_signed.is_active());
+                    let status = match builder.probe(&address).await {
+                        Ok(probe) => format!("Gateway found: {}, now press the link button", probe.name),
+                        Err(_) => String::from("No deCONZ gateway found at this address"),
+                    };
+                    if generation.get() == current {
+                        s.gateway_status.set_text(&status);
+                    }
+                });
+            });
+        }
+
+        {
+            // A gateway with a self-signed certificate is only found once they are allowed
+            let ip_field = s.ip_field.clone();
+            s.allow_self_signed.connect_toggled(move |_| {
+                ip_field.emit_by_name::<()>("changed", &[]);
+            });
+        }
+
         let s_c = s.clone();
         s.clone().link_button.connect_clicked(move |_| {
             let s = &s_c;
@@ -487,34 +2374,28 @@ impl SetupWindow {
 
             let s = s.clone();
             glib::spawn_future_local(async move {
-                let ip = String::from(s.ip_field.text());
-                
-                let ip = if ip.contains("://"){
-                    ip
-                }else{
-                    format!("http://{}", ip)
+                // The normalized url is stored, so it doesn't matter how the address was typed in
+                let accept_invalid_certs = s.allow_self_signed.is_active();
+                let client = match deconz::gateway_url(&s.ip_field.text()) {
+                    Ok(url) => DeconzClient::builder()
+                        .danger_accept_invalid_certs(accept_invalid_certs)
+                        // Tells apart the installations in the client list of the gateway
+                        .device_type(format!("deconz-client desktop#{}", glib::host_name()))
+                        .pair_with_link_button(&url)
+                        .await
+                        .map(|login| (url, login)),
+                    Err(e) => Err(e),
                 };
 
-                let client = DeconzClient::login_with_link_button(&ip).await; // TODO: Error handling
-
                 match client {
-                    Ok(client) => {
-                        (&s.on_login_completed)(&*s, ip, client.username);
+                    Ok((url, login)) => {
+                        info!("Paired with {} ({})", login.gateway_name, login.bridge_id);
+                        (s.on_login_completed)(&s, url.to_string(), login.client.username, login.gateway_name, accept_invalid_certs);
                     }
                     Err(e) => {
                         let msg = match &e{
-                             deconz::Error::HttpError(e) => 
-                            if let Some(status) = e.status(){
-                                if status.as_u16() == 403{
-                                    format!("Error: Authorization button was not pressed")
-                                }else{
-                                    format!("Error: {}", status.to_string())
-                                }
-                            }else{
-                                e.to_string()
-                            }
-                            deconz::Error::ResponseParseError(e) => format!("Error: {}", e),
-                            deconz::Error::IdParseError(e) => format!("Error: {}", e.to_string())
+                            deconz::Error::ApiError { type_code: 101, .. } => String::from("Error: Authorization button was not pressed"),
+                            e => format!("Error: {}", e),
                         };
                         s.error_msg.set_text(&msg);
                         println!("{:#?}", e);
@@ -524,26 +2405,237 @@ impl SetupWindow {
         });
 
         s.clone().demo_button.connect_clicked(move |_|{
-            (&s.on_user_requested_demo)(&*s);
+            (s.on_user_requested_demo)(&s);
         });
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    // Without RUST_LOG the requests of demo mode are still shown, RUST_LOG=deconz=debug logs every request
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn,deconz=info")),
+        )
+        .init();
+
     let application = gtk::Application::builder()
         .application_id("de.zenonet.deconz")
         .build();
 
-    fn main_window(app: &gtk::Application) {
-        let ui = MainWindow::new(&app);
+    fn main_window(app: &gtk::Application, config: Config) {
+        let Some(gateway) = config.default_gateway().cloned() else {
+            setup_window(app);
+            return;
+        };
+        let ui = MainWindow::new(app);
+
+        {
+            let app = app.clone();
+            let window = ui.window.clone();
+            let default = config.default;
+            ui.show_gateways(&config, move |selected| {
+                if selected == Some(default) {
+                    return;
+                }
+                window.close();
+                match selected {
+                    Some(i) => {
+                        let mut config = load_credentials().unwrap_or_default();
+                        config.default = i;
+                        store_config(&config);
+                        main_window(&app, config);
+                    }
+                    None => setup_window(&app),
+                }
+            });
+        }
+
+        let model = ViewModel::<DeconzClient>::init(&gateway);
+
+        {
+            // Tell which gateway is controlled, the name is cached with the config
+            let client = model.client.clone();
+            let window = ui.window.clone();
+            glib::spawn_future_local(async move {
+                match client.gateway_name().await {
+                    Ok(name) => window.set_title(Some(&format!("Deconz Control — {}", name))),
+                    Err(e) => warn!("Failed to load the gateway name: {}", e),
+                }
+            });
+        }
+
+        {
+            let client = model.client.clone();
+            let window = ui.window.clone();
+            ui.about_button.set_visible(true);
+            ui.about_button.connect_clicked(move |_| {
+                let client = client.clone();
+                let window = window.clone();
+                glib::spawn_future_local(async move {
+                    let api_version = match client.api_version().await {
+                        Ok(version) => version.to_string(),
+                        Err(_) => String::from("unknown"),
+                    };
+                    let comments = match client.get_config().await {
+                        Ok(config) => format!(
+                            "Connected to {} at {}\ndeCONZ {} (API {})",
+                            config.name, config.ip_address, config.sw_version, api_version
+                        ),
+                        Err(e) => format!("Failed to load the gateway config: {:?}", e),
+                    };
+                    gtk::AboutDialog::builder()
+                        .transient_for(&window)
+                        .program_name("Deconz Control")
+                        .version(env!("CARGO_PKG_VERSION"))
+                        .comments(comments)
+                        .build()
+                        .present();
+                });
+            });
+        }
+
+        {
+            // Ping the gateway regularly and more often while it can't be reached
+            let client = model.client.clone();
+            let window = ui.window.downgrade();
+            let indicator = ui.connection_indicator.clone();
+            indicator.set_visible(true);
+            glib::spawn_future_local(async move {
+                // Stop once the window is gone
+                while window.upgrade().is_some() {
+                    let (class, tooltip, next_ping) = match client.ping().await {
+                        Ok(latency) => (
+                            match latency.as_millis() {
+                                0..200 => "success",
+                                200..1000 => "warning",
+                                _ => "error",
+                            },
+                            format!("Gateway answers in {} ms", latency.as_millis()),
+                            PING_INTERVAL,
+                        ),
+                        Err(e) => ("error", format!("Gateway not reachable: {:?}", e), PING_RETRY_INTERVAL),
+                    };
+                    indicator.set_css_classes(&[class]);
+                    indicator.set_tooltip_text(Some(&tooltip));
+                    glib::timeout_future(next_ping).await;
+                }
+            });
+        }
+
+        {
+            // The list is loaded on every opening, as apps may have been paired or used meanwhile
+            let client = model.client.clone();
+            let layout = ui.apps_layout.clone();
+            let window = ui.window.clone();
+            ui.apps_button.set_visible(true);
+            ui.apps_popover.connect_show(move |_| {
+                while let Some(child) = layout.first_child() {
+                    layout.remove(&child);
+                }
+
+                let client = client.clone();
+                let layout = layout.clone();
+                let window = window.clone();
+                glib::spawn_future_local(async move {
+                    let whitelist = match client.get_whitelist().await {
+                        Ok(whitelist) => whitelist,
+                        Err(e) => {
+                            layout.append(&Label::builder().label(format!("Error: {}", e)).wrap(true).build());
+                            return;
+                        }
+                    };
+
+                    for entry in whitelist {
+                        let row = gtk::Box::new(Orientation::Horizontal, 10);
+                        let last_use = match entry.unused_for() {
+                            Some(unused) => format!("Last used {}", ago_text(unused)),
+                            // Dates in an unknown format or ahead of this computer's clock
+                            None if !entry.last_use_date.is_empty() => format!("Last used {} UTC", entry.last_use_date),
+                            None => String::from("Never used"),
+                        };
+                        let text = format!(
+                            "{}\n<small>{}</small>",
+                            glib::markup_escape_text(&entry.name),
+                            glib::markup_escape_text(&last_use)
+                        );
+                        row.append(&Label::builder().label(text).use_markup(true).xalign(0.0).hexpand(true).build());
+
+                        // Revoking this app's own token is what the forget button is for
+                        let own = entry.token == client.username;
+                        let revoke_button = Button::builder()
+                            .label(if own { "This app" } else { "Revoke" })
+                            .css_classes(["destructive-action"])
+                            .sensitive(!own)
+                            .build();
+                        row.append(&revoke_button);
+                        layout.append(&row);
+
+                        let client = client.clone();
+                        let layout = layout.clone();
+                        let window = window.clone();
+                        revoke_button.connect_clicked(move |_| {
+                            let client = client.clone();
+                            let layout = layout.clone();
+                            let window = window.clone();
+                            let row = row.clone();
+                            let entry = entry.clone();
+                            glib::spawn_future_local(async move {
+                                // A revoked app has to be paired again, which needs access to the gateway
+                                let dialog = gtk::AlertDialog::builder()
+                                    .modal(true)
+                                    .message(format!("Revoke the access of {}?", entry.name))
+                                    .detail("The app can't use the gateway anymore until it is paired again with the link button.")
+                                    .buttons(["Cancel", "Revoke"])
+                                    .cancel_button(0)
+                                    .default_button(0)
+                                    .build();
+                                if !matches!(dialog.choose_future(Some(&window)).await, Ok(1)) {
+                                    return;
+                                }
+
+                                match client.delete_token(&entry.token).await {
+                                    // The list may have been reloaded while the dialog was open
+                                    Ok(()) if row.parent().is_some() => layout.remove(&row),
+                                    Ok(()) => {}
+                                    Err(e) => warn!("Failed to revoke the token of {}: {:?}", entry.name, e),
+                                }
+                            });
+                        });
+                    }
+                });
+            });
+        }
+
+        {
+            let app = app.clone();
+            let client = model.client.clone();
+            let window = ui.window.clone();
+            ui.forget_button.set_visible(true);
+            ui.forget_button.connect_clicked(move |_| {
+                let app = app.clone();
+                let client = client.clone();
+                let window = window.clone();
+                let url = gateway.url.clone();
+                glib::spawn_future_local(async move {
+                    // Forget the gateway locally even if it can't be reached to revoke the token
+                    if let Err(e) = client.logout().await {
+                        warn!("Failed to revoke the token: {:?}", e);
+                    }
+                    window.close();
+                    match forget_gateway(&url) {
+                        Some(config) => main_window(&app, config),
+                        None => setup_window(&app),
+                    }
+                });
+            });
+        }
 
-        let model = ViewModel::<DeconzClient>::init();
         ui.add_app_logic(model);
     }
 
     fn demo_window(app: &gtk::Application) {
-        let ui = MainWindow::new(&app);
+        let ui = MainWindow::new(app);
 
         let model = ViewModel::<DemoLightClient>::init();
         ui.add_app_logic(model);
@@ -552,37 +2644,56 @@ async fn main() -> Result<(), Box<dyn Error>> {
     fn init(app: &gtk::Application) {
         // Load credentials here
         if let Some(config) = load_credentials() {
-            unsafe {
-                env::set_var("DECONZ_URL", config.url);
-                env::set_var("DECONZ_TOKEN", config.username);
-            };
-            main_window(app);
+            // Keep the app alive while no window is open yet
+            let hold_guard = app.hold();
+            let app = app.clone();
+            glib::spawn_future_local(async move {
+                // load_credentials only returns configs with a valid default gateway
+                let gateway = config.default_gateway().cloned().unwrap();
+                let valid = match gateway.client() {
+                    Ok(client) => client.validate_token().await,
+                    Err(e) => Err(e),
+                };
+
+                match valid {
+                    Ok(true) => main_window(&app, config),
+                    Ok(false) => {
+                        warn!("The stored token was rejected by the gateway");
+                        setup_window(&app);
+                    }
+                    Err(e) => {
+                        error!("Failed to validate the stored token: {:?}", e);
+                        setup_window(&app);
+                    }
+                }
+                drop(hold_guard);
+            });
         } else {
             // If no credentials are found
-            let app_for_later = app.clone(); // this is reference counted (i think)
-            let app_for_later_again = app.clone();
-            let setup_window = SetupWindow::new(
-                &app,
-                Box::new(move |window, ip, token| {
-                    println!("Got login data!");
-                    unsafe {
-                        env::set_var("DECONZ_URL", &ip);
-                        env::set_var("DECONZ_TOKEN", &token);
-                    };
-                    store_credentials(ip, token);
-                    window.window.close(); // This probably leaks the SetupWindow object but whatever
-                    main_window(&app_for_later);
-                }),
-                Box::new(move |window|{
-                    println!("Starting demo!");
-                    window.window.close();
-                    demo_window(&app_for_later_again);
-                })
-            );
-            setup_window.add_logic();
+            setup_window(app);
         }
     }
 
+    fn setup_window(app: &gtk::Application) {
+        let app_for_later = app.clone(); // this is reference counted (i think)
+        let app_for_later_again = app.clone();
+        let setup_window = SetupWindow::new(
+            app,
+            Box::new(move |window, ip, token, name, accept_invalid_certs| {
+                println!("Got login data!");
+                let config = store_credentials(ip, token, name, accept_invalid_certs);
+                window.window.close(); // This probably leaks the SetupWindow object but whatever
+                main_window(&app_for_later, config);
+            }),
+            Box::new(move |window|{
+                println!("Starting demo!");
+                window.window.close();
+                demo_window(&app_for_later_again);
+            })
+        );
+        setup_window.add_logic();
+    }
+
     application.connect_activate(init);
     application.run();
 
@@ -0,0 +1,43 @@
+use std::{
+    collections::HashMap,
+    fs::{create_dir_all, File},
+    path::PathBuf,
+};
+
+use deconz::LightUsageTracker;
+use gtk::glib;
+
+/// The on times of the lights are kept as data rather than cache, as they can't be fetched again
+fn usage_file_path() -> PathBuf {
+    glib::user_data_dir()
+        .join("deconz-client")
+        .join("usage.json")
+}
+
+/// The stored trackers, keyed by gateway url
+fn load_all() -> HashMap<String, serde_json::Value> {
+    File::open(usage_file_path())
+        .ok()
+        .and_then(|file| serde_json::from_reader(file).ok())
+        .unwrap_or_default()
+}
+
+/// Loads the on times of the lights of a gateway, starting from zero if none are stored
+pub fn load_usage(gateway: &str) -> LightUsageTracker {
+    load_all()
+        .remove(gateway)
+        .and_then(|usage| serde_json::from_value(usage).ok())
+        .unwrap_or_default()
+}
+
+/// Stores the on times of the lights of a gateway, keeping those of the other gateways
+pub fn store_usage(gateway: &str, usage: &LightUsageTracker) {
+    let mut all = load_all();
+    let Ok(usage) = serde_json::to_value(usage) else { return };
+    all.insert(gateway.to_owned(), usage);
+
+    _ = create_dir_all(usage_file_path().parent().unwrap());
+    if let Ok(file) = File::create(usage_file_path()) {
+        _ = serde_json::to_writer(file, &all);
+    }
+}